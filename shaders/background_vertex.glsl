@@ -0,0 +1,11 @@
+#version 430 core
+
+out vec2 clipCoord;
+
+// A triangle covering the screen on the far plane, drawn with depth func LEQUAL
+// so it only fills the pixels no mesh covers
+void main() {
+    vec2 p = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+    clipCoord = p;
+    gl_Position = vec4(p, 1.0, 1.0);
+}
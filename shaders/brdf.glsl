@@ -0,0 +1,28 @@
+// Terms of the Cook-Torrance BRDF: GGX distribution, Smith-Schlick
+// geometry and Schlick Fresnel
+
+#include "constants.glsl"
+
+float distributionGGX(vec3 n, vec3 h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float nDotH = max(dot(n, h), 0.0);
+    float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+
+    return a2 / (PI * d * d);
+}
+
+float geometrySchlickGGX(float nDotX, float roughness) {
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+
+    return nDotX / (nDotX * (1.0 - k) + k);
+}
+
+float geometrySmith(vec3 n, vec3 v, vec3 l, float roughness) {
+    return geometrySchlickGGX(max(dot(n, v), 0.0), roughness) * geometrySchlickGGX(max(dot(n, l), 0.0), roughness);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
@@ -0,0 +1,48 @@
+#version 430 core
+
+#include "ibl_sampling.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+const uint SAMPLE_COUNT = 1024u;
+
+// Geometry term with the k of image based lighting
+float geometrySmithIbl(float nDotV, float nDotL, float roughness) {
+    float k = roughness * roughness / 2.0;
+
+    return nDotV / (nDotV * (1.0 - k) + k) * nDotL / (nDotL * (1.0 - k) + k);
+}
+
+// Scale and bias applied to f0 by the specular BRDF integrated over the
+// hemisphere, for n.v along x and roughness along y
+void main() {
+    float nDotV = max(screenCoord.x, 1e-3);
+    float roughness = screenCoord.y;
+
+    vec3 v = vec3(sqrt(1.0 - nDotV * nDotV), 0.0, nDotV);
+    vec3 n = vec3(0.0, 0.0, 1.0);
+
+    float scale = 0.0;
+    float bias = 0.0;
+
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 h = importanceSampleGGX(hammersley(i, SAMPLE_COUNT), n, roughness);
+        vec3 l = normalize(2.0 * dot(v, h) * h - v);
+
+        float nDotL = max(l.z, 0.0);
+        float nDotH = max(h.z, 0.0);
+        float vDotH = max(dot(v, h), 0.0);
+
+        if (nDotL > 0.0) {
+            float visibility = geometrySmithIbl(nDotV, nDotL, roughness) * vDotH / (nDotH * nDotV);
+            float fc = pow(1.0 - vDotH, 5.0);
+
+            scale += (1.0 - fc) * visibility;
+            bias += fc * visibility;
+        }
+    }
+
+    FragColor = vec4(scale / float(SAMPLE_COUNT), bias / float(SAMPLE_COUNT), 0.0, 1.0);
+}
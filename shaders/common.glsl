@@ -0,0 +1,10 @@
+const vec3 lightDir = vec3(0.0, 1.0, 1.0);
+
+// sRGB approximated by a 2.2 gamma
+vec3 toLinear(vec3 color) {
+    return pow(max(color, 0.0), vec3(2.2));
+}
+
+vec3 toDisplay(vec3 color) {
+    return pow(max(color, 0.0), vec3(1.0 / 2.2));
+}
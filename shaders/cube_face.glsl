@@ -0,0 +1,15 @@
+// Direction through the point `uv` (0 to 1) of a cubemap face, faces in the
+// GL order +X, -X, +Y, -Y, +Z, -Z. Row 0 of a face is at v = 0.
+vec3 cubeDirection(int face, vec2 uv) {
+    float sc = uv.x * 2.0 - 1.0;
+    float tc = uv.y * 2.0 - 1.0;
+
+    switch (face) {
+        case 0: return vec3(1.0, -tc, -sc);
+        case 1: return vec3(-1.0, -tc, sc);
+        case 2: return vec3(sc, 1.0, tc);
+        case 3: return vec3(sc, -1.0, -tc);
+        case 4: return vec3(sc, -tc, 1.0);
+        default: return vec3(-sc, -tc, -1.0);
+    }
+}
@@ -0,0 +1,42 @@
+#version 430 core
+
+#include "common.glsl"
+#include "uniforms.glsl"
+#include "fog.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D gMaterial;
+uniform sampler2D gBase;
+uniform sampler2D gDepth;
+uniform sampler2D lightingTexture;
+
+uniform mat4 inverseProjection;
+
+// Base plus the accumulated lights, finished like the forward path would.
+// Writes the depth of the G-buffer, so later passes are tested against it.
+void main() {
+    float depth = texture(gDepth, screenCoord).r;
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    vec3 color = texture(gBase, screenCoord).rgb + texture(lightingTexture, screenCoord).rgb;
+
+    vec4 view = inverseProjection * vec4(vec3(screenCoord, depth) * 2.0 - 1.0, 1.0);
+    float viewDepth = -view.z / view.w;
+
+#ifdef HDR_OUTPUT
+    color = mix(toLinear(fogColor), color, fogFactor(viewDepth));
+#else
+    // Reinhard for PBR materials, the fixed lighting only goes back to sRGB
+    bool pbr = texture(gMaterial, screenCoord).a > 0.5;
+    color = toDisplay(pbr ? color / (color + 1.0) : color);
+    color = mix(fogColor, color, fogFactor(viewDepth));
+#endif
+
+    FragColor = vec4(color, 1.0);
+    gl_FragDepth = depth;
+}
@@ -0,0 +1,60 @@
+#version 430 core
+
+#include "common.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D gAlbedo;
+uniform sampler2D gNormal;
+uniform sampler2D gMaterial;
+uniform sampler2D gBase;
+uniform sampler2D gDepth;
+uniform sampler2D lightingTexture;
+
+// Order of `GBufferChannel`
+uniform int channel;
+uniform mat4 inverseProjection;
+
+// One channel of the G-buffer in place of the lit image, with its depth so
+// the background and transparent draws still go around it
+void main() {
+    float depth = texture(gDepth, screenCoord).r;
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    vec4 material = texture(gMaterial, screenCoord);
+    vec3 color = vec3(0.0);
+
+    if (channel == 0) {
+        color = toDisplay(texture(gAlbedo, screenCoord).rgb);
+    } else if (channel == 1) {
+        color = texture(gNormal, screenCoord).xyz * 0.5 + 0.5;
+    } else if (channel == 2) {
+        color = vec3(material.r);
+    } else if (channel == 3) {
+        color = vec3(material.g);
+    } else if (channel == 4) {
+        color = vec3(material.b);
+    } else if (channel == 5) {
+        vec3 base = texture(gBase, screenCoord).rgb;
+        color = toDisplay(base / (base + 1.0));
+    } else if (channel == 6) {
+        vec3 lighting = texture(lightingTexture, screenCoord).rgb;
+        color = toDisplay(lighting / (lighting + 1.0));
+    } else if (channel == 7) {
+        // Closer is brighter, view space depth fades out over a few units
+        vec4 view = inverseProjection * vec4(vec3(screenCoord, depth) * 2.0 - 1.0, 1.0);
+        color = vec3(exp(-0.3 * (-view.z / view.w)));
+    }
+
+#ifdef HDR_OUTPUT
+    // The post-processing passes take linear colour
+    color = toLinear(color);
+#endif
+
+    FragColor = vec4(color, 1.0);
+    gl_FragDepth = depth;
+}
@@ -0,0 +1,46 @@
+#version 430 core
+
+#include "uniforms.glsl"
+#ifdef HAS_SHADOWS
+#include "shadow.glsl"
+#endif
+#include "pbr.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D gAlbedo;
+uniform sampler2D gNormal;
+uniform sampler2D gMaterial;
+uniform sampler2D gDepth;
+
+// The light of the `Lights` block this pass adds
+uniform int lightIndex;
+// Clip space back to mesh space, where the lights are
+uniform mat4 inverseTransform;
+
+// Radiance of one light off the surfaces of the G-buffer, blended additively.
+// PBR only: non-PBR materials have the fixed lighting of the forward path,
+// already complete in gBase, which the lights of the block do not add to.
+void main() {
+    vec4 material = texture(gMaterial, screenCoord);
+    float depth = texture(gDepth, screenCoord).r;
+
+    // Nothing drawn, or the fixed lighting which ignores the lights
+    if (depth >= 1.0 || material.a == 0.0) {
+        discard;
+    }
+
+    vec4 p = inverseTransform * vec4(vec3(screenCoord, depth) * 2.0 - 1.0, 1.0);
+    vec3 position = p.xyz / p.w;
+    vec3 n = normalize(texture(gNormal, screenCoord).xyz);
+    vec3 v = normalize(cameraPosition - position);
+
+    vec3 radiance = shadeLight(lightIndex, position, n, v, texture(gAlbedo, screenCoord).rgb, material.r, material.g);
+#ifdef HAS_SHADOWS
+    radiance *= shadowFactor(lightIndex, position, n);
+#endif
+
+    FragColor = vec4(radiance, 1.0);
+}
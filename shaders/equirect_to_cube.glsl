@@ -0,0 +1,20 @@
+#version 430 core
+
+#include "constants.glsl"
+#include "cube_face.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D equirectMap;
+uniform int face;
+
+void main() {
+    vec3 d = normalize(cubeDirection(face, screenCoord));
+
+    // Longitude around y, latitude from the top row of the image
+    vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, 0.5 - asin(d.y) / PI);
+
+    FragColor = vec4(texture(equirectMap, uv).rgb, 1.0);
+}
@@ -0,0 +1,15 @@
+// Distance fog of the `Fog` block
+
+// Fraction of the surface colour left at view space `depth`, 1 without fog
+float fogFactor(float depth) {
+    if (fogMode == 1) {
+        return clamp((fogEnd - depth) / max(fogEnd - fogStart, 1e-5), 0.0, 1.0);
+    } else if (fogMode == 2) {
+        return exp(-fogDensity * depth);
+    } else if (fogMode == 3) {
+        float d = fogDensity * depth;
+        return exp(-d * d);
+    }
+
+    return 1.0;
+}
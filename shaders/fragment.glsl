@@ -1,24 +1,169 @@
 #version 430 core
 
-const vec3 lightDir = vec3(0.0, 1.0, 1.0);
+// The G-buffer and the post-processing passes both take linear values
+#if defined(HDR_OUTPUT) || defined(DEFERRED)
+#define LINEAR_OUTPUT
+#endif
 
+#include "common.glsl"
+#include "uniforms.glsl"
+#include "fog.glsl"
+#ifdef HAS_SHADOWS
+#include "shadow.glsl"
+#endif
+#ifdef PBR
+#include "pbr.glsl"
+#ifdef HAS_IBL
+#include "ibl.glsl"
+#endif
+#endif
+
+in vec3 worldPosition;
 in vec3 vertexNorm;
 in vec2 texCoord;
 in float depth;
+in vec4 vertexColor;
+#ifdef HAS_NORMAL_MAP
+in vec3 vertexTangent;
+in vec3 vertexBitangent;
+#endif
 
+#ifdef DEFERRED
+layout (location = 0) out vec4 gAlbedo;
+layout (location = 1) out vec4 gNormal;
+layout (location = 2) out vec4 gMaterial;
+layout (location = 3) out vec4 gBase;
+#elif defined(WEIGHTED_OIT)
+layout (location = 0) out vec4 accum;
+layout (location = 1) out float reveal;
+#else
 out vec4 FragColor;
+#endif
 
 uniform sampler2D texture0;
+#ifdef HAS_NORMAL_MAP
+uniform sampler2D normalMap;
+#endif
+#ifdef PBR
+uniform sampler2D roughnessMap;
+uniform sampler2D metallicMap;
+uniform sampler2D occlusionMap;
+uniform sampler2D emissiveMap;
+#endif
+#ifdef HAS_SSAO
+// Factor of the ambient light, rendered at the size of the window
+uniform sampler2D ssaoMap;
+#endif
+
+void main() {
+#ifdef HAS_NORMAL_MAP
+    // The interpolated basis is not orthonormal anymore, but MikkTSpace bakers expect it unnormalized
+    mat3 tbn = mat3(vertexTangent, vertexBitangent, vertexNorm);
+    vec3 n = normalize(tbn * (texture(normalMap, texCoord).xyz * 2.0 - 1.0));
+#elif defined(HAS_NORMALS)
+    vec3 n = normalize(vertexNorm);
+#else
+    // Flat shading from the screen space derivatives of the position
+    vec3 n = normalize(cross(dFdx(worldPosition), dFdy(worldPosition)));
+#endif
+
+#ifdef HAS_SSAO
+    float ambientOcclusion = texture(ssaoMap, gl_FragCoord.xy / vec2(textureSize(ssaoMap, 0))).r;
+#else
+    float ambientOcclusion = 1.0;
+#endif
 
-float fog(float depth) {
-    if (depth <= 1) {
-        return 1;
+#ifdef HAS_TEXCOORDS
+    vec4 baseTexel = texture(texture0, texCoord);
+#else
+    vec4 baseTexel = vec4(1.0);
+#endif
+    vec4 color = baseTexel * vertexColor * diffuseColor;
+
+#ifdef PBR
+    // Textures are in sRGB, the factors and vertex colours of glTF are linear
+    // already. Lighting happens in linear space, the G-buffer stores it too.
+    vec3 albedo = toLinear(baseTexel.rgb) * vertexColor.rgb * diffuseColor.rgb;
+    float perceptualRoughness = clamp(texture(roughnessMap, texCoord).g * roughness, 0.04, 1.0);
+    float metalness = clamp(texture(metallicMap, texCoord).b * metallic, 0.0, 1.0);
+    float occlusion = texture(occlusionMap, texCoord).r * ambientOcclusion;
+    vec3 emission = toLinear(texture(emissiveMap, texCoord).rgb) * emissiveColor;
+
+    vec3 v = normalize(cameraPosition - worldPosition);
+
+    vec3 lo = vec3(0.0);
+#ifndef DEFERRED
+    // The deferred path adds the lights in its light pass
+    for (int i = 0; i < min(lightCount, MAX_LIGHTS); i++) {
+        vec3 radiance = shadeLight(i, worldPosition, n, v, albedo, metalness, perceptualRoughness);
+#ifdef HAS_SHADOWS
+        radiance *= shadowFactor(i, worldPosition, n);
+#endif
+        lo += radiance;
     }
+#endif
+#ifdef HAS_IBL
+    vec3 ambient = shadeEnvironment(n, v, albedo, metalness, perceptualRoughness) * occlusion;
+#else
+    vec3 ambient = vec3(0.03) * albedo * occlusion;
+#endif
 
-    return 1.0 / (pow(depth, 0.5));
-}
+    vec3 hdr = ambient + lo + emission;
+#ifdef LINEAR_OUTPUT
+    color.rgb = hdr;
+#else
+    // Reinhard tone mapping back to sRGB
+    color.rgb = toDisplay(hdr / (hdr + 1.0));
+#endif
+#elif defined(HAS_NORMALS)
+    float lightCoef = (1 + dot(n, normalize(lightDir))) / 2.;
+#ifdef HAS_SHADOWS
+    // The fixed light matches the first light of the block, its shadow darkens by half
+    if (lightCount > 0) {
+        lightCoef *= 0.5 + 0.5 * shadowFactor(0, worldPosition, n);
+    }
+#endif
+    color.rgb *= lightCoef;
+#endif
 
-void main() {
-    float lightCoef = (1 + dot(vertexNorm, lightDir)) / 2.;
-    FragColor = texture(texture0, texCoord);
-}
\ No newline at end of file
+#ifndef PBR
+    // The fixed lighting has no separate ambient term, it is darkened as a whole
+    color.rgb *= ambientOcclusion;
+#endif
+
+#if defined(LINEAR_OUTPUT) && !defined(PBR)
+    color.rgb = toLinear(color.rgb);
+#endif
+
+    if (color.a < alphaCutoff) {
+        discard;
+    }
+
+    // The deferred path fogs in its composite pass
+#if defined(HDR_OUTPUT) && !defined(DEFERRED)
+    color.rgb = mix(toLinear(fogColor), color.rgb, fogFactor(depth));
+#elif !defined(DEFERRED)
+    color.rgb = mix(fogColor, color.rgb, fogFactor(depth));
+#endif
+
+#ifdef DEFERRED
+    // The base is what the lights of the block do not light: ambient and emission,
+    // or the whole fixed lighting, flagged by the alpha of the material
+    gNormal = vec4(n, 0.0);
+    gBase = vec4(color.rgb, 1.0);
+#ifdef PBR
+    gAlbedo = vec4(albedo, 1.0);
+    gMaterial = vec4(metalness, perceptualRoughness, occlusion, 1.0);
+#else
+    gAlbedo = vec4(color.rgb, 1.0);
+    gMaterial = vec4(0.0, 1.0, ambientOcclusion, 0.0);
+#endif
+#elif defined(WEIGHTED_OIT)
+    // Weight favouring close and opaque fragments, equation 10 of the paper
+    float w = clamp(pow(min(1.0, color.a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+    accum = vec4(color.rgb * color.a, color.a) * w;
+    reveal = color.a;
+#else
+    FragColor = color;
+#endif
+}
@@ -0,0 +1,10 @@
+#version 430 core
+
+out vec2 screenCoord;
+
+// A single triangle covering the screen, drawn with DrawArrays(TRIANGLES, 0, 3)
+void main() {
+    vec2 p = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    screenCoord = p;
+    gl_Position = vec4(p * 2.0 - 1.0, 0.0, 1.0);
+}
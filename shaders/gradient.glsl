@@ -0,0 +1,16 @@
+#version 430 core
+
+in vec2 clipCoord;
+
+out vec4 FragColor;
+
+uniform vec3 bottomColor;
+uniform vec3 topColor;
+// Writes linear values for the post-processing passes instead
+uniform bool linearOutput;
+
+void main() {
+    vec3 color = mix(bottomColor, topColor, clipCoord.y * 0.5 + 0.5);
+
+    FragColor = vec4(linearOutput ? pow(color, vec3(2.2)) : color, 1.0);
+}
@@ -0,0 +1,26 @@
+// Split sum image based lighting (Karis 2013), from the maps built by `environment.rs`
+
+uniform samplerCube irradianceMap;
+uniform samplerCube prefilterMap;
+uniform sampler2D brdfLut;
+
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 f0, float roughness) {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// Radiance reflected towards `v` from the whole environment
+vec3 shadeEnvironment(vec3 n, vec3 v, vec3 albedo, float metalness, float roughness) {
+    vec3 f0 = mix(vec3(0.04), albedo, metalness);
+    float nDotV = max(dot(n, v), 0.0);
+
+    vec3 f = fresnelSchlickRoughness(nDotV, f0, roughness);
+    vec3 kd = (vec3(1.0) - f) * (1.0 - metalness);
+    vec3 diffuse = texture(irradianceMap, n).rgb * albedo;
+
+    // Rougher surfaces read blurrier mips of the prefiltered map
+    float maxLod = float(textureQueryLevels(prefilterMap) - 1);
+    vec3 prefiltered = textureLod(prefilterMap, reflect(-v, n), roughness * maxLod).rgb;
+    vec2 brdf = texture(brdfLut, vec2(nDotV, roughness)).rg;
+
+    return kd * diffuse + prefiltered * (f * brdf.x + brdf.y);
+}
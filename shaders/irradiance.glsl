@@ -0,0 +1,38 @@
+#version 430 core
+
+#include "constants.glsl"
+#include "cube_face.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform samplerCube environmentMap;
+uniform int face;
+
+const float SAMPLE_DELTA = 0.025;
+// A blurred mip of the environment, so the coarse sampling does not miss small bright spots
+const float SOURCE_LOD = 3.0;
+
+// Cosine weighted integral of the environment over the hemisphere around n
+void main() {
+    vec3 n = normalize(cubeDirection(face, screenCoord));
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 right = normalize(cross(up, n));
+    up = cross(n, right);
+
+    vec3 irradiance = vec3(0.0);
+    float samples = 0.0;
+
+    for (float phi = 0.0; phi < 2.0 * PI; phi += SAMPLE_DELTA) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += SAMPLE_DELTA) {
+            vec3 t = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 d = t.x * right + t.y * up + t.z * n;
+
+            irradiance += textureLod(environmentMap, d, SOURCE_LOD).rgb * cos(theta) * sin(theta);
+            samples += 1.0;
+        }
+    }
+
+    FragColor = vec4(PI * irradiance / samples, 1.0);
+}
@@ -0,0 +1,27 @@
+// Direction and falloff of the lights in the `Lights` block
+
+// Unit vector from `position` towards light `i`
+vec3 lightDirection(int i, vec3 position) {
+    vec4 light = lightPositions[i];
+
+    return light.w == 0.0 ? normalize(light.xyz) : normalize(light.xyz - position);
+}
+
+// Inverse square falloff of point and spot lights, with the soft edge of spot cones
+float lightAttenuation(int i, vec3 position) {
+    vec4 light = lightPositions[i];
+    if (light.w == 0.0) {
+        return 1.0;
+    }
+
+    vec3 toLight = light.xyz - position;
+    float attenuation = 1.0 / max(dot(toLight, toLight), 1e-4);
+
+    vec4 spot = lightSpots[i];
+    if (spot.w > -1.0) {
+        float cosAngle = dot(-normalize(toLight), normalize(spot.xyz));
+        attenuation *= smoothstep(spot.w, mix(spot.w, 1.0, 0.1), cosAngle);
+    }
+
+    return attenuation;
+}
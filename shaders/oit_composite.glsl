@@ -0,0 +1,32 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+#ifdef MULTISAMPLE
+// Reading gl_SampleID runs the shader once per sample of the scene target
+uniform sampler2DMS accumTexture;
+uniform sampler2DMS revealTexture;
+
+#define FETCH(tex) texelFetch(tex, ivec2(gl_FragCoord.xy), gl_SampleID)
+#else
+uniform sampler2D accumTexture;
+uniform sampler2D revealTexture;
+
+#define FETCH(tex) texture(tex, screenCoord)
+#endif
+
+void main() {
+    float reveal = FETCH(revealTexture).r;
+
+    // Nothing transparent covers this pixel
+    if (reveal >= 1.0) {
+        discard;
+    }
+
+    vec4 accum = FETCH(accumTexture);
+    vec3 average = accum.rgb / max(accum.a, 1e-5);
+
+    FragColor = vec4(average, 1.0 - reveal);
+}
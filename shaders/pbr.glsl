@@ -0,0 +1,25 @@
+// Metallic-roughness shading of the lights in the `Lights` block
+
+#include "brdf.glsl"
+#include "lights.glsl"
+
+// Radiance reflected towards `v` from light `i`
+vec3 shadeLight(int i, vec3 position, vec3 n, vec3 v, vec3 albedo, float metalness, float roughness) {
+    vec3 l = lightDirection(i, position);
+    float attenuation = lightAttenuation(i, position);
+
+    vec3 h = normalize(v + l);
+    vec3 radiance = lightColors[i].rgb * lightColors[i].w * attenuation;
+
+    vec3 f0 = mix(vec3(0.04), albedo, metalness);
+    vec3 f = fresnelSchlick(max(dot(h, v), 0.0), f0);
+    float d = distributionGGX(n, h, roughness);
+    float g = geometrySmith(n, v, l, roughness);
+
+    float nDotL = max(dot(n, l), 0.0);
+    vec3 specular = d * g * f / (4.0 * max(dot(n, v), 0.0) * nDotL + 1e-4);
+    // Metals have no diffuse reflection
+    vec3 kd = (vec3(1.0) - f) * (1.0 - metalness);
+
+    return (kd * albedo / PI + specular) * radiance * nDotL;
+}
@@ -0,0 +1,15 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D sourceTexture;
+uniform sampler2D bloomTexture;
+uniform float intensity;
+
+void main() {
+    vec4 color = texture(sourceTexture, screenCoord);
+
+    FragColor = vec4(color.rgb + texture(bloomTexture, screenCoord).rgb * intensity, color.a);
+}
@@ -0,0 +1,17 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D sourceTexture;
+uniform float threshold;
+
+// Keeps what is brighter than the threshold, with a soft knee
+void main() {
+    vec3 color = texture(sourceTexture, screenCoord).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(brightness - threshold, 0.0) / max(brightness, 1e-4);
+
+    FragColor = vec4(color * contribution, 1.0);
+}
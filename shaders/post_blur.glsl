@@ -0,0 +1,22 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D sourceTexture;
+// One texel along the axis of this pass, the blur is separable
+uniform vec2 direction;
+
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 color = texture(sourceTexture, screenCoord).rgb * WEIGHTS[0];
+
+    for (int i = 1; i < 5; i++) {
+        color += texture(sourceTexture, screenCoord + direction * float(i)).rgb * WEIGHTS[i];
+        color += texture(sourceTexture, screenCoord - direction * float(i)).rgb * WEIGHTS[i];
+    }
+
+    FragColor = vec4(color, 1.0);
+}
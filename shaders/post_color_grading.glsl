@@ -0,0 +1,19 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+// Display values, grading runs after tone mapping
+uniform sampler2D sourceTexture;
+uniform sampler3D lutTexture;
+
+void main() {
+    vec4 color = texture(sourceTexture, screenCoord);
+
+    // Texel centers, so 0 and 1 land on the first and last entries
+    float size = float(textureSize(lutTexture, 0).x);
+    vec3 uvw = clamp(color.rgb, 0.0, 1.0) * ((size - 1.0) / size) + 0.5 / size;
+
+    FragColor = vec4(texture(lutTexture, uvw).rgb, color.a);
+}
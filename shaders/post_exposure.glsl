@@ -0,0 +1,15 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D sourceTexture;
+// In stops, each one doubles the light
+uniform float exposure;
+
+void main() {
+    vec4 color = texture(sourceTexture, screenCoord);
+
+    FragColor = vec4(color.rgb * exp2(exposure), color.a);
+}
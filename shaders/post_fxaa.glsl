@@ -0,0 +1,139 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+// Display values, FXAA runs after tone mapping
+uniform sampler2D sourceTexture;
+
+const float EDGE_THRESHOLD_MIN = 0.0312;
+const float EDGE_THRESHOLD_MAX = 0.125;
+const float SUBPIXEL_QUALITY = 0.75;
+const int ITERATIONS = 12;
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+float lumaAt(vec2 uv) {
+    return luma(texture(sourceTexture, uv).rgb);
+}
+
+// FXAA 3.11 (Lottes 2011): finds the direction of the edge through the pixel,
+// walks along it to both ends and blends the pixel with its neighbour across
+// the edge by how close it is to an end
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(sourceTexture, 0));
+    vec4 center = texture(sourceTexture, screenCoord);
+
+    float lumaCenter = luma(center.rgb);
+    float lumaDown = lumaAt(screenCoord + vec2(0.0, -texel.y));
+    float lumaUp = lumaAt(screenCoord + vec2(0.0, texel.y));
+    float lumaLeft = lumaAt(screenCoord + vec2(-texel.x, 0.0));
+    float lumaRight = lumaAt(screenCoord + vec2(texel.x, 0.0));
+
+    float lumaMin = min(lumaCenter, min(min(lumaDown, lumaUp), min(lumaLeft, lumaRight)));
+    float lumaMax = max(lumaCenter, max(max(lumaDown, lumaUp), max(lumaLeft, lumaRight)));
+    float lumaRange = lumaMax - lumaMin;
+
+    // Not an edge
+    if (lumaRange < max(EDGE_THRESHOLD_MIN, lumaMax * EDGE_THRESHOLD_MAX)) {
+        FragColor = center;
+        return;
+    }
+
+    float lumaDownLeft = lumaAt(screenCoord - texel);
+    float lumaUpRight = lumaAt(screenCoord + texel);
+    float lumaUpLeft = lumaAt(screenCoord + vec2(-texel.x, texel.y));
+    float lumaDownRight = lumaAt(screenCoord + vec2(texel.x, -texel.y));
+
+    float lumaDownUp = lumaDown + lumaUp;
+    float lumaLeftRight = lumaLeft + lumaRight;
+    float lumaLeftCorners = lumaDownLeft + lumaUpLeft;
+    float lumaDownCorners = lumaDownLeft + lumaDownRight;
+    float lumaRightCorners = lumaDownRight + lumaUpRight;
+    float lumaUpCorners = lumaUpRight + lumaUpLeft;
+
+    float edgeHorizontal = abs(-2.0 * lumaLeft + lumaLeftCorners) + abs(-2.0 * lumaCenter + lumaDownUp) * 2.0 + abs(-2.0 * lumaRight + lumaRightCorners);
+    float edgeVertical = abs(-2.0 * lumaUp + lumaUpCorners) + abs(-2.0 * lumaCenter + lumaLeftRight) * 2.0 + abs(-2.0 * lumaDown + lumaDownCorners);
+    bool isHorizontal = edgeHorizontal >= edgeVertical;
+
+    // The side of the pixel the edge is on
+    float luma1 = isHorizontal ? lumaDown : lumaLeft;
+    float luma2 = isHorizontal ? lumaUp : lumaRight;
+    float gradient1 = luma1 - lumaCenter;
+    float gradient2 = luma2 - lumaCenter;
+    bool is1Steepest = abs(gradient1) >= abs(gradient2);
+    float gradientScaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+    float stepLength = isHorizontal ? texel.y : texel.x;
+    float lumaLocalAverage;
+    if (is1Steepest) {
+        stepLength = -stepLength;
+        lumaLocalAverage = 0.5 * (luma1 + lumaCenter);
+    } else {
+        lumaLocalAverage = 0.5 * (luma2 + lumaCenter);
+    }
+
+    vec2 currentUv = screenCoord;
+    if (isHorizontal) {
+        currentUv.y += stepLength * 0.5;
+    } else {
+        currentUv.x += stepLength * 0.5;
+    }
+
+    // Walk along the edge in both directions until its ends
+    vec2 offset = isHorizontal ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+    vec2 uv1 = currentUv - offset;
+    vec2 uv2 = currentUv + offset;
+
+    float lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+    float lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+    bool reached1 = abs(lumaEnd1) >= gradientScaled;
+    bool reached2 = abs(lumaEnd2) >= gradientScaled;
+
+    for (int i = 2; i < ITERATIONS && !(reached1 && reached2); i++) {
+        // Longer steps further from the pixel
+        float quality = i < 5 ? 1.0 : (i < 6 ? 1.5 : (i < 10 ? 2.0 : 4.0));
+
+        if (!reached1) {
+            uv1 -= offset * quality;
+            lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+            reached1 = abs(lumaEnd1) >= gradientScaled;
+        }
+        if (!reached2) {
+            uv2 += offset * quality;
+            lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+            reached2 = abs(lumaEnd2) >= gradientScaled;
+        }
+    }
+
+    float distance1 = isHorizontal ? screenCoord.x - uv1.x : screenCoord.y - uv1.y;
+    float distance2 = isHorizontal ? uv2.x - screenCoord.x : uv2.y - screenCoord.y;
+    bool isDirection1 = distance1 < distance2;
+    float distanceFinal = min(distance1, distance2);
+    float edgeThickness = distance1 + distance2;
+
+    // Only blend when the closest end goes the other way than the pixel
+    bool isLumaCenterSmaller = lumaCenter < lumaLocalAverage;
+    bool correctVariation = ((isDirection1 ? lumaEnd1 : lumaEnd2) < 0.0) != isLumaCenterSmaller;
+    float pixelOffset = correctVariation ? -distanceFinal / edgeThickness + 0.5 : 0.0;
+
+    // Sub-pixel aliasing, from the average of the 3x3 neighbourhood
+    float lumaAverage = (1.0 / 12.0) * (2.0 * (lumaDownUp + lumaLeftRight) + lumaLeftCorners + lumaRightCorners);
+    float subPixelOffset1 = clamp(abs(lumaAverage - lumaCenter) / lumaRange, 0.0, 1.0);
+    float subPixelOffset2 = (-2.0 * subPixelOffset1 + 3.0) * subPixelOffset1 * subPixelOffset1;
+    float subPixelOffsetFinal = subPixelOffset2 * subPixelOffset2 * SUBPIXEL_QUALITY;
+
+    pixelOffset = max(pixelOffset, subPixelOffsetFinal);
+
+    vec2 finalUv = screenCoord;
+    if (isHorizontal) {
+        finalUv.y += pixelOffset * stepLength;
+    } else {
+        finalUv.x += pixelOffset * stepLength;
+    }
+
+    FragColor = vec4(texture(sourceTexture, finalUv).rgb, center.a);
+}
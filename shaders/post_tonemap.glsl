@@ -0,0 +1,24 @@
+#version 430 core
+
+#include "common.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D sourceTexture;
+// 0 Reinhard, 1 ACES
+uniform int tonemapOperator;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+// Linear HDR to display values
+void main() {
+    vec4 color = texture(sourceTexture, screenCoord);
+    vec3 mapped = tonemapOperator == 1 ? aces(color.rgb) : color.rgb / (color.rgb + 1.0);
+
+    FragColor = vec4(toDisplay(mapped), color.a);
+}
@@ -0,0 +1,19 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D sourceTexture;
+// Darkening at the corners, 0 to 1
+uniform float intensity;
+// Distance from the center where the darkening starts, 1 is the middle of the edges
+uniform float radius;
+
+void main() {
+    vec4 color = texture(sourceTexture, screenCoord);
+    float dist = length(screenCoord * 2.0 - 1.0);
+    float vignette = 1.0 - intensity * smoothstep(radius, radius + 0.6, dist);
+
+    FragColor = vec4(color.rgb * vignette, color.a);
+}
@@ -0,0 +1,45 @@
+#version 430 core
+
+#include "cube_face.glsl"
+#include "ibl_sampling.glsl"
+
+in vec2 screenCoord;
+
+out vec4 FragColor;
+
+uniform samplerCube environmentMap;
+uniform int face;
+uniform float roughness;
+
+const uint SAMPLE_COUNT = 512u;
+
+// Environment convolved with the GGX lobe, assuming n = v = r (Karis 2013)
+void main() {
+    vec3 n = normalize(cubeDirection(face, screenCoord));
+    vec3 v = n;
+
+    float resolution = float(textureSize(environmentMap, 0).x);
+    float texelSolidAngle = 4.0 * PI / (6.0 * resolution * resolution);
+
+    vec3 color = vec3(0.0);
+    float weight = 0.0;
+
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 h = importanceSampleGGX(hammersley(i, SAMPLE_COUNT), n, roughness);
+        vec3 l = normalize(2.0 * dot(v, h) * h - v);
+
+        float nDotL = dot(n, l);
+        if (nDotL > 0.0) {
+            // Samples of unlikely directions read a blurrier mip, covering the solid angle they stand for
+            float nDotH = max(dot(n, h), 0.0);
+            float pdf = distributionGGX(n, h, roughness) * nDotH / (4.0 * max(dot(h, v), 0.0)) + 1e-4;
+            float sampleSolidAngle = 1.0 / (float(SAMPLE_COUNT) * pdf + 1e-4);
+            float lod = roughness == 0.0 ? 0.0 : 0.5 * log2(sampleSolidAngle / texelSolidAngle);
+
+            color += textureLod(environmentMap, l, lod).rgb * nDotL;
+            weight += nDotL;
+        }
+    }
+
+    FragColor = vec4(color / max(weight, 1e-4), 1.0);
+}
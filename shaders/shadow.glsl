@@ -0,0 +1,43 @@
+// Percentage closer filtered shadows, from the maps rendered by `shadow.rs`
+
+#include "lights.glsl"
+
+uniform sampler2DArrayShadow shadowMaps;
+
+// Fraction of light `i` reaching `position`, 0 in its shadow
+float shadowFactor(int i, vec3 position, vec3 n) {
+    ivec4 maps = lightShadowMaps[i];
+    if (maps.y == 0) {
+        return 1.0;
+    }
+
+    // Grazing surfaces are pushed along their normal and get a larger depth bias, against acne
+    float nDotL = clamp(dot(n, lightDirection(i, position)), 0.0, 1.0);
+    vec3 p = position + n * shadowNormalBias * (1.0 - nDotL);
+    float bias = shadowBias * (1.0 + 4.0 * (1.0 - nDotL));
+
+    vec2 texel = 1.0 / vec2(textureSize(shadowMaps, 0).xy);
+
+    // Cascades go from the closest to the camera, the first one holding the point is the sharpest
+    for (int c = 0; c < maps.y; c++) {
+        int layer = maps.x + c;
+        vec4 clip = shadowMatrices[layer] * vec4(p, 1.0);
+        vec3 coord = clip.xyz / clip.w * 0.5 + 0.5;
+
+        if (clip.w <= 0.0 || any(lessThan(coord, vec3(0.0))) || any(greaterThan(coord, vec3(1.0)))) {
+            continue;
+        }
+
+        float lit = 0.0;
+        for (int x = -pcfRadius; x <= pcfRadius; x++) {
+            for (int y = -pcfRadius; y <= pcfRadius; y++) {
+                lit += texture(shadowMaps, vec4(coord.xy + vec2(x, y) * texel, float(layer), coord.z - bias));
+            }
+        }
+
+        float taps = float((2 * pcfRadius + 1) * (2 * pcfRadius + 1));
+        return lit / taps;
+    }
+
+    return 1.0;
+}
@@ -0,0 +1,5 @@
+#version 430 core
+
+// Shadow maps only keep the depth
+void main() {
+}
@@ -0,0 +1,10 @@
+#version 430 core
+
+layout (location = 0) in vec3 position;
+
+// Mesh space to the clip space of the shadow map being rendered
+uniform mat4 lightMatrix;
+
+void main() {
+    gl_Position = lightMatrix * vec4(position, 1.0);
+}
@@ -0,0 +1,28 @@
+#version 430 core
+
+in vec2 clipCoord;
+
+out vec4 FragColor;
+
+uniform samplerCube environmentMap;
+// Inverse of the camera transform, maps clip space back to mesh space
+uniform mat4 inverseTransform;
+// Linear HDR values, tone mapped like the PBR path. Others are shown as they are stored.
+uniform bool hdr;
+// Writes linear values for the post-processing passes instead
+uniform bool linearOutput;
+
+void main() {
+    vec4 near = inverseTransform * vec4(clipCoord, -1.0, 1.0);
+    vec4 far = inverseTransform * vec4(clipCoord, 1.0, 1.0);
+    vec3 d = far.xyz / far.w - near.xyz / near.w;
+
+    vec3 color = textureLod(environmentMap, d, 0.0).rgb;
+    if (linearOutput && !hdr) {
+        color = pow(color, vec3(2.2));
+    } else if (!linearOutput && hdr) {
+        color = pow(color / (color + 1.0), vec3(1.0 / 2.2));
+    }
+
+    FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,60 @@
+#version 430 core
+
+#define MAX_KERNEL_SIZE 64
+
+in vec2 screenCoord;
+
+out float FragOcclusion;
+
+uniform sampler2D normalTexture;
+uniform sampler2D depthTexture;
+// Random rotations of the kernel around the normal, tiled over the screen
+uniform sampler2D noiseTexture;
+
+// Offsets in the hemisphere around +z, denser close to the center
+uniform vec3 kernel[MAX_KERNEL_SIZE];
+uniform int kernelSize;
+uniform float radius;
+uniform float bias;
+uniform mat4 projection;
+uniform mat4 inverseProjection;
+
+vec3 viewPositionAt(vec2 uv) {
+    vec4 p = inverseProjection * vec4(vec3(uv, texture(depthTexture, uv).r) * 2.0 - 1.0, 1.0);
+    return p.xyz / p.w;
+}
+
+// Crytek style SSAO with normal oriented hemispheres: the fraction of the
+// kernel around the point that lies behind the depth buffer
+void main() {
+    // Nothing drawn, the background is not occluded
+    if (texture(depthTexture, screenCoord).r >= 1.0) {
+        FragOcclusion = 0.0;
+        return;
+    }
+
+    vec3 position = viewPositionAt(screenCoord);
+    vec3 n = normalize(texture(normalTexture, screenCoord).xyz);
+
+    vec2 noiseScale = vec2(textureSize(depthTexture, 0)) / vec2(textureSize(noiseTexture, 0));
+    vec3 randomVec = texture(noiseTexture, screenCoord * noiseScale).xyz;
+
+    // Gram-Schmidt towards the random vector gives a rotated tangent frame
+    vec3 tangent = normalize(randomVec - n * dot(randomVec, n));
+    mat3 tbn = mat3(tangent, cross(n, tangent), n);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < min(kernelSize, MAX_KERNEL_SIZE); i++) {
+        vec3 samplePosition = position + tbn * kernel[i] * radius;
+
+        vec4 offset = projection * vec4(samplePosition, 1.0);
+        vec2 uv = offset.xy / offset.w * 0.5 + 0.5;
+
+        float sceneDepth = viewPositionAt(uv).z;
+        // Geometry far in front of the point does not occlude it
+        float rangeCheck = smoothstep(0.0, 1.0, radius / abs(position.z - sceneDepth));
+        occlusion += (sceneDepth >= samplePosition.z + bias ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    FragOcclusion = occlusion / float(max(kernelSize, 1));
+}
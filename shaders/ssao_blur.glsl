@@ -0,0 +1,24 @@
+#version 430 core
+
+in vec2 screenCoord;
+
+out float FragAmbientOcclusion;
+
+uniform sampler2D occlusionTexture;
+// Strength of the darkening, 0 leaves the ambient light alone
+uniform float intensity;
+
+// Box blur over the size of the noise texture, which removes its pattern.
+// Outputs the factor the ambient light is multiplied by.
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(occlusionTexture, 0));
+
+    float occlusion = 0.0;
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            occlusion += texture(occlusionTexture, screenCoord + vec2(x, y) * texel).r;
+        }
+    }
+
+    FragAmbientOcclusion = clamp(1.0 - intensity * occlusion / 16.0, 0.0, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 430 core
+
+in vec3 viewPosition;
+in vec3 viewNormal;
+
+out vec4 FragNormal;
+
+// View space normals for the SSAO pass, the depth goes to the depth attachment
+void main() {
+#ifdef HAS_NORMALS
+    vec3 n = normalize(viewNormal);
+#else
+    vec3 n = normalize(cross(dFdx(viewPosition), dFdy(viewPosition)));
+#endif
+
+    FragNormal = vec4(n, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 430 core
+
+#include "uniforms.glsl"
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+
+out vec3 viewPosition;
+out vec3 viewNormal;
+
+void main() {
+    gl_Position = transformMatrix * vec4(position, 1.0);
+    viewPosition = (viewMatrix * vec4(position, 1.0)).xyz;
+#ifdef HAS_NORMALS
+    viewNormal = mat3(viewMatrix) * normal;
+#else
+    viewNormal = vec3(0.);
+#endif
+}
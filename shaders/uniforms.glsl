@@ -0,0 +1,51 @@
+// Uniform blocks shared by every program, laid out by `uniform_buffer.rs`
+
+layout (std140) uniform Camera {
+    mat4 transformMatrix;
+    vec3 cameraPosition;
+    mat4 viewMatrix;
+};
+
+layout (std140) uniform Material {
+    vec4 diffuseColor;
+    vec3 ambientColor;
+    float shininess;
+    vec3 specularColor;
+    float alphaCutoff;
+    vec3 emissiveColor;
+    float metallic;
+    float roughness;
+};
+
+#define MAX_LIGHTS 64
+
+layout (std140) uniform Lights {
+    // w = 0 for directional lights, xyz is then the direction towards the light
+    vec4 lightPositions[MAX_LIGHTS];
+    // rgb colour, intensity in w
+    vec4 lightColors[MAX_LIGHTS];
+    // Direction of the cone of spot lights, cosine of its half angle in w, -1 for other lights
+    vec4 lightSpots[MAX_LIGHTS];
+    int lightCount;
+};
+
+#define MAX_SHADOW_MAPS 8
+
+layout (std140) uniform Shadows {
+    // Mesh space to the clip space of each shadow map
+    mat4 shadowMatrices[MAX_SHADOW_MAPS];
+    // x = first shadow map of each light, y = number of maps, 0 without shadows
+    ivec4 lightShadowMaps[MAX_LIGHTS];
+    float shadowBias;
+    float shadowNormalBias;
+    int pcfRadius;
+};
+
+layout (std140) uniform Fog {
+    vec3 fogColor;
+    // 0 none, 1 linear, 2 exponential, 3 exponential squared
+    int fogMode;
+    float fogDensity;
+    float fogStart;
+    float fogEnd;
+};
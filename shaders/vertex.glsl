@@ -1,20 +1,45 @@
 #version 430 core
 
+#include "uniforms.glsl"
+
 layout (location = 0) in vec3 position;
 layout (location = 1) in vec3 normal;
 layout (location = 2) in vec2 textureCoordinate;
+layout (location = 3) in vec4 tangent;
+layout (location = 5) in vec4 color;
 
+out vec3 worldPosition;
 out vec3 vertexNorm;
 out vec2 texCoord;
 out float depth;
-
-uniform mat4 transformMatrix;
+out vec4 vertexColor;
+#ifdef HAS_NORMAL_MAP
+out vec3 vertexTangent;
+out vec3 vertexBitangent;
+#endif
 
 void main() {
     vec4 v = vec4(position, 1.0);
     v = transformMatrix * v;
     gl_Position = v;
+    // The camera transform includes the model, positions stay in mesh space
+    worldPosition = position;
+#ifdef HAS_NORMALS
+    vertexNorm = normalize(normal);
+#else
     vertexNorm = vec3(1.);
+#endif
+#ifdef HAS_NORMAL_MAP
+    vertexTangent = normalize(tangent.xyz);
+    // MikkTSpace handedness, the bitangent is flipped for mirrored UVs
+    vertexBitangent = tangent.w * cross(vertexNorm, vertexTangent);
+#endif
+#ifdef HAS_TEXCOORDS
     texCoord = vec2(textureCoordinate.x, 1. - textureCoordinate.y);
-    depth = v.z;
-}
\ No newline at end of file
+#else
+    texCoord = vec2(0.);
+#endif
+    // Distance in front of the eye, clip space z is not linear in it
+    depth = -(viewMatrix * vec4(position, 1.0)).z;
+    vertexColor = color;
+}
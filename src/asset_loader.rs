@@ -0,0 +1,213 @@
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex},
+    thread
+};
+
+use crate::environment::load_hdr;
+use crate::mesh_loader::{load_mesh_with_materials, MeshMaterials};
+use crate::obj_parser::FaceLayout;
+use crate::texture::Texture;
+use crate::triangles::{TriangleMesh, NORMAL_ATTRIB, TANGENT_ATTRIB};
+
+enum LoadRequest {
+    Mesh(String, FaceLayout),
+    Texture(String),
+    Environment(String),
+}
+
+/// Parsed or decoded asset waiting to be uploaded on the GL thread
+pub enum LoadedAsset {
+    /// With the materials of OBJ and glTF files
+    Mesh(String, TriangleMesh, Option<Box<MeshMaterials>>),
+    Texture(String, image::RgbaImage),
+    /// Equirectangular HDR image
+    Environment(String, image::Rgb32FImage),
+    Failed(String, String),
+}
+
+/// Parses meshes and decodes images on worker threads. The GL thread calls
+/// `poll` between frames and uploads whatever has finished.
+pub struct AssetLoader {
+    requests : Option<Sender<(u64, LoadRequest)>>,
+    results : Receiver<(u64, LoadedAsset)>,
+    workers : Vec<thread::JoinHandle<()>>,
+    /// Latest generation requested per path. Workers finish in any order, so
+    /// a result older than that would overwrite a newer load of the same file.
+    generations : HashMap<String, u64>,
+    requested : usize,
+    finished : usize,
+}
+
+fn asset_path(asset : &LoadedAsset) -> &str {
+    match asset {
+        LoadedAsset::Mesh(path, _, _) | LoadedAsset::Texture(path, _) |
+        LoadedAsset::Environment(path, _) | LoadedAsset::Failed(path, _) => path
+    }
+}
+
+fn load(request : LoadRequest) -> LoadedAsset {
+    let path = match &request {
+        LoadRequest::Mesh(path, _) | LoadRequest::Texture(path) | LoadRequest::Environment(path) => path.clone()
+    };
+
+    // The parsers panic on bad input, report that instead of losing the worker
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match request {
+        LoadRequest::Mesh(path, face_layout) => {
+            let (mut mesh, materials) = load_mesh_with_materials(&path, &face_layout);
+
+            // Tangents for normal mapping, unless the file had them
+            let layout = &mesh.vertex_attrib_layout;
+            if layout.contains(NORMAL_ATTRIB) && !layout.contains(TANGENT_ATTRIB) {
+                mesh.generate_tangents();
+            }
+            LoadedAsset::Mesh(path, mesh, materials.map(Box::new))
+        }
+        LoadRequest::Texture(path) => {
+            let img = Texture::decode(&path);
+            LoadedAsset::Texture(path, img)
+        }
+        LoadRequest::Environment(path) => match load_hdr(&path) {
+            Ok(img) => LoadedAsset::Environment(path, img),
+            Err(e) => LoadedAsset::Failed(path, e)
+        }
+    }));
+
+    result.unwrap_or_else(|e| {
+        let msg = e.downcast_ref::<String>().cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_default();
+
+        LoadedAsset::Failed(path, msg)
+    })
+}
+
+impl AssetLoader {
+    pub fn new(num_workers : usize) -> Self {
+        let (request_tx, request_rx) = mpsc::channel::<(u64, LoadRequest)>();
+        let (result_tx, result_rx) = mpsc::channel();
+
+        let request_rx = Arc::new(Mutex::new(request_rx));
+
+        let workers = (0..num_workers.max(1)).map(|_| {
+            let request_rx = Arc::clone(&request_rx);
+            let result_tx = result_tx.clone();
+
+            thread::spawn(move || loop {
+                let (generation, request) = match request_rx.lock().unwrap().recv() {
+                    Ok(r) => r,
+                    Err(_) => break
+                };
+
+                if result_tx.send((generation, load(request))).is_err() {
+                    break;
+                }
+            })
+        }).collect();
+
+        AssetLoader {
+            requests : Some(request_tx),
+            results : result_rx,
+            workers,
+            generations : HashMap::new(),
+            requested : 0,
+            finished : 0
+        }
+    }
+
+    fn request(&mut self, path : &str, request : LoadRequest) -> Result<(), String> {
+        let generation = self.generations.get(path).map_or(0, |g| g + 1);
+
+        self.requests.as_ref()
+            .and_then(|requests| requests.send((generation, request)).ok())
+            .ok_or_else(|| format!("Could not load {}: the asset loader workers have stopped", path))?;
+
+        self.generations.insert(path.to_string(), generation);
+        self.requested += 1;
+
+        Ok(())
+    }
+
+    pub fn load_mesh(&mut self, path : &str, face_layout : FaceLayout) -> Result<(), String> {
+        self.request(path, LoadRequest::Mesh(path.to_string(), face_layout))
+    }
+
+    pub fn load_texture(&mut self, path : &str) -> Result<(), String> {
+        self.request(path, LoadRequest::Texture(path.to_string()))
+    }
+
+    /// Decodes a `.hdr` or `.exr` environment map
+    pub fn load_environment(&mut self, path : &str) -> Result<(), String> {
+        self.request(path, LoadRequest::Environment(path.to_string()))
+    }
+
+    /// Assets finished since the last call, never blocks. Results superseded
+    /// by a later request for the same path are dropped.
+    pub fn poll(&mut self) -> Vec<LoadedAsset> {
+        let done : Vec<(u64, LoadedAsset)> = self.results.try_iter().collect();
+        self.finished += done.len();
+
+        done.into_iter()
+            .filter(|(generation, asset)| self.generations.get(asset_path(asset)) == Some(generation))
+            .map(|(_, asset)| asset)
+            .collect()
+    }
+
+    /// (finished, requested)
+    pub fn progress(&self) -> (usize, usize) {
+        (self.finished, self.requested)
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.finished < self.requested
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        // Closing the channel lets the workers finish their current asset and exit
+        self.requests = None;
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::{Duration, Instant}};
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn superseded_loads_are_dropped() {
+        let dir = temp_dir("asset-loader");
+        let path = dir.join("triangle.stl").to_string_lossy().into_owned();
+        fs::write(&path, "solid t\nfacet normal 0 0 1\nouter loop\n\
+            vertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid t\n").unwrap();
+
+        let mut loader = AssetLoader::new(2);
+        let layout = FaceLayout::new(Some(0), None, None);
+        for _ in 0..4 {
+            loader.load_mesh(&path, layout.clone()).unwrap();
+        }
+
+        let start = Instant::now();
+        let mut loaded = Vec::new();
+        while loader.is_loading() {
+            assert!(start.elapsed() < Duration::from_secs(10), "Loads did not finish");
+            loaded.extend(loader.poll());
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // Only the result of the last request is handed out
+        assert_eq!(loader.progress(), (4, 4));
+        assert_eq!(loaded.len(), 1);
+        assert!(matches!(&loaded[0], LoadedAsset::Mesh(p, mesh, None) if *p == path && mesh.num_verticies() == 3));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
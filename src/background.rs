@@ -0,0 +1,171 @@
+use gl::types::{GLenum, GLint, GLuint};
+
+use crate::environment::{equirect_to_cubemap, load_hdr, Environment};
+use crate::fullscreen::EmptyVertexArray;
+use crate::shader_program::{ShaderCache, ShaderProgram};
+
+pub const BACKGROUND_VERTEX_SHADER_PATH : &str = "shaders/background_vertex.glsl";
+pub const SKYBOX_FRAGMENT_SHADER_PATH : &str = "shaders/skybox.glsl";
+pub const GRADIENT_FRAGMENT_SHADER_PATH : &str = "shaders/gradient.glsl";
+
+pub const DEFAULT_BACKGROUND_COLOR : [f32; 3] = [0.2, 0.3, 0.3];
+
+/// Size of the faces of skyboxes made from equirectangular images
+const SKYBOX_SIZE : i32 = 1024;
+
+/// A cubemap drawn behind the mesh
+pub struct Skybox {
+    cubemap : GLuint,
+    /// Linear HDR values, tone mapped when drawn
+    hdr : bool,
+}
+
+impl Skybox {
+    /// Faces in the GL order +X, -X, +Y, -Y, +Z, -Z, each one a square of the same size
+    pub fn from_faces(faces : &[image::RgbaImage]) -> Result<Self, String> {
+        if faces.len() != 6 {
+            return Err(format!("A skybox needs 6 faces, {} given", faces.len()));
+        }
+
+        let size = faces[0].width();
+        if faces.iter().any(|face| face.width() != size || face.height() != size) {
+            return Err("Skybox faces must be squares of the same size".to_string());
+        }
+
+        let mut cubemap = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut cubemap);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+
+            for (i, face) in faces.iter().enumerate() {
+                gl::TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum, 0, gl::RGBA as GLint, size as i32, size as i32,
+                    0, gl::RGBA, gl::UNSIGNED_BYTE, face.as_raw().as_ptr() as *const _
+                );
+            }
+
+            for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+                gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as GLint);
+            }
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        }
+
+        Ok(Skybox { cubemap, hdr : false })
+    }
+
+    /// Reads the six faces, see `from_faces`
+    pub fn load_faces(paths : &[&str]) -> Result<Self, String> {
+        let faces = paths.iter()
+            .map(|path| image::open(path)
+                .map(|img| img.into_rgba8())
+                .map_err(|e| format!("Could not read skybox face {}: {}", path, e)))
+            .collect::<Result<Vec<image::RgbaImage>, String>>()?;
+
+        Skybox::from_faces(&faces)
+    }
+
+    /// `hdr` for linear values, which are tone mapped when drawn
+    pub fn from_equirect(img : &image::Rgb32FImage, hdr : bool, shader_cache : &mut ShaderCache) -> Result<Self, String> {
+        let cubemap = equirect_to_cubemap(img, SKYBOX_SIZE, shader_cache)?;
+
+        Ok(Skybox { cubemap, hdr })
+    }
+
+    /// Reads an equirectangular image, `.hdr` and `.exr` files are tone mapped
+    pub fn load_equirect(path : &str, shader_cache : &mut ShaderCache) -> Result<Self, String> {
+        let hdr = [".hdr", ".exr"].iter().any(|ext| path.to_lowercase().ends_with(ext));
+
+        Skybox::from_equirect(&load_hdr(path)?, hdr, shader_cache)
+    }
+}
+
+impl Drop for Skybox {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.cubemap) };
+    }
+}
+
+/// What fills the pixels no mesh covers
+pub enum Background {
+    COLOR([f32; 3]),
+    /// Vertical gradient from the bottom to the top of the window
+    GRADIENT { bottom : [f32; 3], top : [f32; 3] },
+    SKYBOX(Skybox),
+    /// The environment map lighting PBR materials, the default colour without one
+    ENVIRONMENT,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::COLOR(DEFAULT_BACKGROUND_COLOR)
+    }
+}
+
+impl Background {
+    /// The colour the framebuffer is cleared to, the other backgrounds are drawn over it
+    pub fn clear_color(&self) -> [f32; 3] {
+        match self {
+            Background::COLOR(color) => *color,
+            _ => DEFAULT_BACKGROUND_COLOR
+        }
+    }
+
+    /// Vertex and fragment shader of the pass drawing the background
+    pub fn shaders(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Background::COLOR(_) => None,
+            Background::GRADIENT { .. } => Some((BACKGROUND_VERTEX_SHADER_PATH, GRADIENT_FRAGMENT_SHADER_PATH)),
+            Background::SKYBOX(_) | Background::ENVIRONMENT => Some((BACKGROUND_VERTEX_SHADER_PATH, SKYBOX_FRAGMENT_SHADER_PATH)),
+        }
+    }
+
+    /// Draws over the pixels still at the far plane, so after the opaque draws
+    /// only the uncovered ones are shaded. `transform` is the camera transform,
+    /// `linear_output` writes linear values for the post-processing passes.
+    pub fn draw(
+        &self, program : &ShaderProgram, transform : &glm::Matrix4<f32>, environment : Option<&Environment>,
+        screen : &EmptyVertexArray, linear_output : bool
+    ) {
+        let cubemap = match self {
+            Background::COLOR(_) => return,
+            Background::GRADIENT { .. } => None,
+            Background::SKYBOX(skybox) => Some((skybox.cubemap, skybox.hdr)),
+            Background::ENVIRONMENT => match environment {
+                Some(environment) => Some((environment.cubemap(), true)),
+                None => return
+            }
+        };
+
+        program.use_program();
+        program.set_uniform("linearOutput", linear_output);
+
+        if let Background::GRADIENT { bottom, top } = self {
+            program.set_uniform("bottomColor", *bottom);
+            program.set_uniform("topColor", *top);
+        }
+
+        if let Some((cubemap, hdr)) = cubemap {
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+            }
+            program.set_uniform("environmentMap", 0);
+            program.set_uniform("inverseTransform", glm::inverse(transform));
+            program.set_uniform("hdr", hdr);
+        }
+
+        unsafe {
+            gl::DepthFunc(gl::LEQUAL);
+            gl::DepthMask(gl::FALSE);
+        }
+
+        screen.draw_fullscreen();
+
+        unsafe {
+            gl::DepthFunc(gl::LESS);
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+}
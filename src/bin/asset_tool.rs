@@ -0,0 +1,165 @@
+use std::{env, path::Path, process, time::{Duration, Instant}};
+
+use rendering::mesh_cache::{write_mesh_cache, SourceStamp};
+use rendering::mesh_export::{atlas_diffuse_maps, write_obj, write_ply, write_stl};
+use rendering::mtl_parser::{mtl_to_materials, MtlMaterial};
+use rendering::moving::get_bounding_box;
+use rendering::obj_parser::{obj_info, obj_to_mesh, validate_obj, FaceLayout};
+
+const USAGE : &str = "Usage:
+    asset-tool stats <file.obj>
+    asset-tool validate <file.obj>
+    asset-tool bench <file.obj> [runs]
+    asset-tool convert <file.obj> <output.obj|output.ply|output.stl|output.meshcache>
+    asset-tool atlas <file.obj> <output.obj> <atlas.png>";
+
+fn stats(obj : &str) -> Result<(), String> {
+    let info = obj_info(obj);
+
+    println!("{}", obj);
+    println!("  verticies:       {}", info.verticies);
+    println!("  normals:         {}", info.normals);
+    println!("  texture coords:  {}", info.texture_coords);
+    println!("  faces:           {}", info.faces);
+    println!("  materials:       {}", info.materials);
+    println!("  face layout:     {}", info.face_layout);
+
+    for lib in &info.material_libs {
+        println!("  material lib:    {}", lib);
+    }
+
+    if info.verticies > 0 {
+        let bb = get_bounding_box(obj);
+
+        println!("  bounding box:    [{}, {}] x [{}, {}] x [{}, {}]", bb.x_min, bb.x_max, bb.y_min, bb.y_max, bb.z_min, bb.z_max);
+        println!("  center:          ({}, {}, {})", bb.mean_x(), bb.mean_y(), bb.mean_z());
+    }
+
+    Ok(())
+}
+
+fn validate(obj : &str) -> Result<(), String> {
+    let problems = validate_obj(obj);
+
+    for problem in &problems {
+        println!("{}: {}", obj, problem);
+    }
+
+    if problems.is_empty() {
+        println!("{}: ok", obj);
+        Ok(())
+    } else {
+        Err(format!("{} problem(s) found", problems.len()))
+    }
+}
+
+/// Materials of every MTL file the OBJ uses. Texture paths are relative to
+/// their own MTL, which may sit anywhere, so they are resolved against the
+/// working directory.
+fn obj_materials(obj : &str) -> Vec<MtlMaterial> {
+    obj_info(obj).material_libs.iter()
+        .filter(|lib| Path::new(lib).is_file())
+        .flat_map(|lib| {
+            let lib_dir = Path::new(lib).parent().unwrap_or(Path::new("")).to_path_buf();
+
+            mtl_to_materials(lib).into_iter().map(move |mut material| {
+                material.map_paths(|map| lib_dir.join(map).to_string_lossy().into_owned());
+                material
+            })
+        })
+        .collect()
+}
+
+fn convert(obj : &str, output : &str) -> Result<(), String> {
+    let extension = Path::new(output)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("stl") => {
+            // STL only stores positions
+            let mesh = obj_to_mesh(obj, &FaceLayout::new(Some(0), None, None));
+            write_stl(&mesh, output).map_err(|e| e.to_string())
+        }
+        Some("obj") => {
+            let mesh = obj_to_mesh(obj, &FaceLayout::detect(obj));
+            write_obj(&mesh, &obj_materials(obj), ".", output).map_err(|e| e.to_string())
+        }
+        Some("ply") => {
+            let mesh = obj_to_mesh(obj, &FaceLayout::detect(obj));
+            write_ply(&mesh, output).map_err(|e| e.to_string())
+        }
+        Some("meshcache") => {
+            let stamp = SourceStamp::of(obj).map_err(|e| e.to_string())?;
+            let mesh = obj_to_mesh(obj, &FaceLayout::detect(obj));
+            write_mesh_cache(&mesh, &stamp, output).map_err(|e| e.to_string())
+        }
+        _ => Err(format!("Unsupported output format: {}", output))
+    }
+}
+
+/// Writes the OBJ again with all of its diffuse maps merged into one image
+fn atlas(obj : &str, output : &str, atlas_path : &str) -> Result<(), String> {
+    let mut mesh = obj_to_mesh(obj, &FaceLayout::detect(obj));
+    let mut materials = obj_materials(obj);
+
+    atlas_diffuse_maps(&mut mesh, &mut materials, ".", atlas_path).map_err(|e| e.to_string())?;
+    write_obj(&mesh, &materials, ".", output).map_err(|e| e.to_string())
+}
+
+fn bench(obj : &str, runs : &str) -> Result<(), String> {
+    let runs = runs.parse::<u32>().map_err(|_| format!("Invalid number of runs: {}", runs))?.max(1);
+    let face_layout = FaceLayout::detect(obj);
+
+    let mut times = Vec::new();
+    let mut num_verticies = 0;
+
+    for _ in 0..runs {
+        let start = Instant::now();
+        let mesh = obj_to_mesh(obj, &face_layout);
+        times.push(start.elapsed());
+
+        num_verticies = mesh.num_verticies();
+    }
+
+    let min = times.iter().min().unwrap();
+    let mean = times.iter().sum::<Duration>() / runs;
+
+    println!("{}: {} triangles, {} runs", obj, num_verticies / 3, runs);
+    println!("  min:  {:?}", min);
+    println!("  mean: {:?}", mean);
+    println!("  {:.1} M triangles/s", num_verticies as f64 / 3. / min.as_secs_f64() / 1e6);
+
+    Ok(())
+}
+
+fn main() {
+    let args : Vec<String> = env::args().skip(1).collect();
+    let args : Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    if let Some(obj) = args.get(1) {
+        if !Path::new(obj).is_file() {
+            eprintln!("Could not read file: {}", obj);
+            process::exit(1);
+        }
+    }
+
+    let result = match args.as_slice() {
+        ["stats", obj] => stats(obj),
+        ["validate", obj] => validate(obj),
+        ["bench", obj] => bench(obj, "10"),
+        ["bench", obj, runs] => bench(obj, runs),
+        ["convert", obj, output] => convert(obj, output),
+        ["atlas", obj, output, atlas_path] => atlas(obj, output, atlas_path),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,295 @@
+use gl::types::{GLenum, GLint, GLuint};
+use glm::{Matrix4, Vector4};
+
+use crate::fullscreen::EmptyVertexArray;
+use crate::shader_program::ShaderProgram;
+use crate::uniform_buffer::{Light, MAX_LIGHTS};
+
+pub const DEFERRED_LIGHT_SHADER_PATH : &str = "shaders/deferred_light.glsl";
+pub const DEFERRED_COMPOSITE_SHADER_PATH : &str = "shaders/deferred_composite.glsl";
+pub const DEFERRED_DEBUG_SHADER_PATH : &str = "shaders/deferred_debug.glsl";
+
+/// Samplers of the G-buffer and of the accumulated lights, with their texture units
+const GBUFFER_SAMPLERS : [(&str, i32); 6] = [
+    ("gAlbedo", 0),
+    ("gNormal", 1),
+    ("gMaterial", 2),
+    ("gBase", 3),
+    ("gDepth", 4),
+    ("lightingTexture", 5),
+];
+
+/// Radiance below which a point or spot light is cut off, bounds its scissor rectangle
+const LIGHT_CUTOFF : f32 = 1. / 256.;
+
+/// A channel of the G-buffer shown instead of the lit image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GBufferChannel {
+    ALBEDO,
+    NORMAL,
+    METALNESS,
+    ROUGHNESS,
+    OCCLUSION,
+    /// Ambient, emission and the fixed lighting
+    BASE,
+    /// What the light pass accumulated
+    LIGHTING,
+    DEPTH,
+}
+
+impl GBufferChannel {
+    const ALL : [GBufferChannel; 8] = [
+        GBufferChannel::ALBEDO,
+        GBufferChannel::NORMAL,
+        GBufferChannel::METALNESS,
+        GBufferChannel::ROUGHNESS,
+        GBufferChannel::OCCLUSION,
+        GBufferChannel::BASE,
+        GBufferChannel::LIGHTING,
+        GBufferChannel::DEPTH,
+    ];
+
+    /// The channel after `current`, from the lit image (`None`) through every channel and back
+    pub fn cycle(current : Option<GBufferChannel>) -> Option<GBufferChannel> {
+        match current {
+            None => Some(GBufferChannel::ALL[0]),
+            Some(channel) => GBufferChannel::ALL.get(channel as usize + 1).copied(),
+        }
+    }
+}
+
+fn texture(internal_format : GLenum, format : GLenum, width : i32, height : i32) -> GLuint {
+    let mut id = 0;
+
+    unsafe {
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as GLint, width, height, 0, format, gl::FLOAT, std::ptr::null());
+    }
+
+    id
+}
+
+/// Distance at which the radiance of `light` falls under `LIGHT_CUTOFF`
+fn light_range(light : &Light) -> f32 {
+    let brightest = light.color.iter().copied().fold(0., f32::max);
+
+    (light.intensity * brightest / LIGHT_CUTOFF).sqrt()
+}
+
+/// Pixel rectangle (x, y, width, height) holding the sphere of the range of
+/// `light` on screen. The whole viewport for directional lights and spheres
+/// reaching behind the camera.
+fn light_scissor(light : &Light, projection : &Matrix4<f32>, view : &Matrix4<f32>, width : i32, height : i32) -> [i32; 4] {
+    let full = [0, 0, width, height];
+    if light.is_directional() {
+        return full;
+    }
+
+    let [x, y, z, _] = light.position;
+    let center = *view * Vector4::new(x, y, z, 1.);
+    // The view holds the scale of the mesh, the range is in mesh units
+    let scale = (0..3).map(|i| glm::length(view[i].truncate(3))).fold(0., f32::max);
+    let radius = light_range(light) * scale;
+
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for corner in 0..8 {
+        let offset = |bit : i32| if corner & bit == 0 { -radius } else { radius };
+        let clip = *projection * Vector4::new(center.x + offset(1), center.y + offset(2), center.z + offset(4), 1.);
+
+        if clip.w <= 1e-4 {
+            return full;
+        }
+
+        for (axis, value) in [clip.x / clip.w, clip.y / clip.w].into_iter().enumerate() {
+            min[axis] = min[axis].min(value);
+            max[axis] = max[axis].max(value);
+        }
+    }
+
+    // Entirely off screen
+    if (0..2).any(|axis| max[axis] < -1. || min[axis] > 1.) {
+        return [0; 4];
+    }
+
+    let to_pixels = |ndc : f32, size : i32| (((ndc.clamp(-1., 1.) * 0.5 + 0.5) * size as f32) as i32).clamp(0, size);
+    let (x0, y0) = (to_pixels(min[0], width), to_pixels(min[1], height));
+    let (x1, y1) = (to_pixels(max[0], width) + 1, to_pixels(max[1], height) + 1);
+
+    [x0, y0, x1.min(width) - x0, y1.min(height) - y0]
+}
+
+/// G-buffer of the deferred path. The geometry pass writes albedo, normal,
+/// material parameters (metalness, roughness, occlusion, PBR flag) and the
+/// light independent base colour of the opaque draws, all in mesh space and
+/// linear colour. The light pass then adds each light with a full-screen draw
+/// scissored to the screen rectangle of its range, and the composite pass
+/// finishes the image into the scene framebuffer.
+pub struct GBuffer {
+    fbo : GLuint,
+    /// Albedo, normal, material and base
+    targets : [GLuint; 4],
+    depth : GLuint,
+    light_fbo : GLuint,
+    lighting : GLuint,
+    width : i32,
+    height : i32,
+    screen : EmptyVertexArray,
+}
+
+impl GBuffer {
+    pub fn new(width : i32, height : i32) -> Result<Self, String> {
+        let targets = [
+            texture(gl::RGBA16F, gl::RGBA, width, height),
+            texture(gl::RGBA16F, gl::RGBA, width, height),
+            texture(gl::RGBA8, gl::RGBA, width, height),
+            texture(gl::RGBA16F, gl::RGBA, width, height),
+        ];
+        let depth = texture(gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, width, height);
+        let lighting = texture(gl::RGBA16F, gl::RGBA, width, height);
+
+        let mut gbuffer = GBuffer {
+            fbo : 0, targets, depth, light_fbo : 0, lighting, width, height, screen : EmptyVertexArray::new()
+        };
+
+        let status = unsafe {
+            gl::GenFramebuffers(1, &mut gbuffer.fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, gbuffer.fbo);
+            let attachments : Vec<GLenum> = (0..targets.len() as GLenum).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+            for (attachment, target) in attachments.iter().zip(targets) {
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, *attachment, gl::TEXTURE_2D, target, 0);
+            }
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, depth, 0);
+            gl::DrawBuffers(attachments.len() as i32, attachments.as_ptr());
+            let geometry_status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+
+            gl::GenFramebuffers(1, &mut gbuffer.light_fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, gbuffer.light_fbo);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, lighting, 0);
+            let light_status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            if geometry_status != gl::FRAMEBUFFER_COMPLETE { geometry_status } else { light_status }
+        };
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("G-buffer framebuffer is incomplete: 0x{:X}", status));
+        }
+
+        Ok(gbuffer)
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Binds and clears the G-buffer for the geometry pass
+    pub fn begin_geometry(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl::Viewport(0, 0, self.width, self.height);
+            for i in 0..self.targets.len() as GLint {
+                gl::ClearBufferfv(gl::COLOR, i, [0f32; 4].as_ptr());
+            }
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+    }
+
+    fn bind_textures(&self, program : &ShaderProgram) {
+        let textures = [self.targets[0], self.targets[1], self.targets[2], self.targets[3], self.depth, self.lighting];
+
+        for ((sampler, unit), texture) in GBUFFER_SAMPLERS.iter().zip(textures) {
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0 + *unit as GLenum);
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+            }
+            if program.uniform(sampler).is_some() {
+                program.set_uniform(sampler, *unit);
+            }
+        }
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+    }
+
+    /// Adds the lights of the `Lights` block one by one, each a full-screen draw
+    /// scissored to the rectangle around its range. Pixels in the rectangle but
+    /// outside the range are shaded too, there is no depth test against proxy
+    /// geometry. The lights must be those uploaded to the block.
+    pub fn accumulate_lights(&self, program : &ShaderProgram, lights : &[Light], projection : &Matrix4<f32>, view : &Matrix4<f32>) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.light_fbo);
+            gl::Viewport(0, 0, self.width, self.height);
+            gl::ClearBufferfv(gl::COLOR, 0, [0f32; 4].as_ptr());
+
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::ONE, gl::ONE);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+
+        program.use_program();
+        program.set_uniform("inverseTransform", glm::inverse(&(*projection * *view)));
+        self.bind_textures(program);
+
+        for (i, light) in lights.iter().take(MAX_LIGHTS).enumerate() {
+            let [x, y, width, height] = light_scissor(light, projection, view, self.width, self.height);
+            if width <= 0 || height <= 0 {
+                continue;
+            }
+
+            unsafe { gl::Scissor(x, y, width, height) };
+            program.set_uniform("lightIndex", i as i32);
+            self.screen.draw_fullscreen();
+        }
+
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::Disable(gl::BLEND);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    /// Draws the lit image into `output_fbo` with the depth of the G-buffer,
+    /// leaving the pixels without geometry alone
+    pub fn composite(&self, program : &ShaderProgram, projection : &Matrix4<f32>, output_fbo : GLuint) {
+        program.use_program();
+        self.draw_with_depth(program, projection, output_fbo);
+    }
+
+    /// Draws `channel` into `output_fbo` instead of the lit image, with the
+    /// depth of the G-buffer like `composite`
+    pub fn debug(&self, program : &ShaderProgram, channel : GBufferChannel, projection : &Matrix4<f32>, output_fbo : GLuint) {
+        program.use_program();
+        program.set_uniform("channel", channel as i32);
+        self.draw_with_depth(program, projection, output_fbo);
+    }
+
+    /// Full-screen draw of `program`, which is in use and writes the depth of the G-buffer
+    fn draw_with_depth(&self, program : &ShaderProgram, projection : &Matrix4<f32>, output_fbo : GLuint) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, output_fbo);
+            gl::Viewport(0, 0, self.width, self.height);
+            // Depth is only written with the test on
+            gl::DepthFunc(gl::ALWAYS);
+        }
+
+        program.set_uniform("inverseProjection", glm::inverse(projection));
+        self.bind_textures(program);
+        self.screen.draw_fullscreen();
+
+        unsafe { gl::DepthFunc(gl::LESS) };
+    }
+}
+
+impl Drop for GBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(2, [self.fbo, self.light_fbo].as_ptr());
+            gl::DeleteTextures(self.targets.len() as i32, self.targets.as_ptr());
+            gl::DeleteTextures(2, [self.depth, self.lighting].as_ptr());
+        }
+    }
+}
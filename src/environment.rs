@@ -0,0 +1,258 @@
+use gl::types::{GLenum, GLint, GLuint};
+
+use crate::fullscreen::{EmptyVertexArray, FULLSCREEN_VERTEX_SHADER_PATH};
+use crate::shader_preprocessor::ShaderFeatures;
+use crate::shader_program::{ShaderCache, ShaderProgram};
+
+pub const EQUIRECT_TO_CUBE_SHADER_PATH : &str = "shaders/equirect_to_cube.glsl";
+pub const IRRADIANCE_SHADER_PATH : &str = "shaders/irradiance.glsl";
+pub const PREFILTER_SHADER_PATH : &str = "shaders/prefilter.glsl";
+pub const BRDF_LUT_SHADER_PATH : &str = "shaders/brdf_lut.glsl";
+
+pub const IRRADIANCE_SAMPLER : &str = "irradianceMap";
+pub const PREFILTER_SAMPLER : &str = "prefilterMap";
+pub const BRDF_LUT_SAMPLER : &str = "brdfLut";
+
+/// Texture units of the IBL maps, above the ones materials use
+pub const IBL_SAMPLERS : [(&str, i32); 3] = [
+    (IRRADIANCE_SAMPLER, 10),
+    (PREFILTER_SAMPLER, 11),
+    (BRDF_LUT_SAMPLER, 12),
+];
+
+const CUBEMAP_SIZE : i32 = 512;
+const IRRADIANCE_SIZE : i32 = 32;
+const PREFILTER_SIZE : i32 = 128;
+/// Roughness 0 to 1 spread over the mips of the prefiltered map
+const PREFILTER_MIP_LEVELS : i32 = 5;
+const BRDF_LUT_SIZE : i32 = 256;
+
+/// Decodes an image to linear floats without touching GL, `.hdr` and `.exr`
+/// keep their range, other formats are read as they are stored
+pub fn load_hdr(path : &str) -> Result<image::Rgb32FImage, String> {
+    image::open(path)
+        .map(|img| img.into_rgb32f())
+        .map_err(|e| format!("Could not read environment map {}: {}", path, e))
+}
+
+fn cubemap(size : i32, mip_levels : i32) -> GLuint {
+    let mut id = 0;
+    let min_filter = if mip_levels > 1 { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+
+    unsafe {
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, id);
+        gl::TexStorage2D(gl::TEXTURE_CUBE_MAP, mip_levels, gl::RGB16F, size, size);
+
+        for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as GLint);
+        }
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+    }
+
+    id
+}
+
+fn texture_2d(internal_format : GLenum, format : GLenum, width : i32, height : i32, wrap_s : GLenum, pixels : *const f32) -> GLuint {
+    let mut id = 0;
+
+    unsafe {
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap_s as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as GLint, width, height, 0, format, gl::FLOAT, pixels as *const _);
+    }
+
+    id
+}
+
+/// Offscreen pass rendering fullscreen triangles into cubemap faces. The
+/// viewport, framebuffer and depth test are restored when it is dropped.
+struct CubemapPass {
+    fbo : GLuint,
+    screen : EmptyVertexArray,
+    viewport : [GLint; 4],
+    previous_fbo : GLint,
+    depth_test : bool,
+}
+
+impl CubemapPass {
+    fn new() -> Self {
+        let mut pass = CubemapPass {
+            fbo : 0,
+            screen : EmptyVertexArray::new(),
+            viewport : [0; 4],
+            previous_fbo : 0,
+            depth_test : unsafe { gl::IsEnabled(gl::DEPTH_TEST) } == gl::TRUE,
+        };
+
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, pass.viewport.as_mut_ptr());
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut pass.previous_fbo);
+
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+
+            gl::GenFramebuffers(1, &mut pass.fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, pass.fbo);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+
+        pass
+    }
+
+    /// Draws into each face of `mip` of `cubemap`, the program gets the face index as `face`
+    fn render_faces(&self, program : &ShaderProgram, cubemap : GLuint, size : i32, mip : i32) {
+        unsafe { gl::Viewport(0, 0, size, size) };
+
+        for face in 0..6 {
+            unsafe {
+                gl::FramebufferTexture2D(
+                    gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as GLenum, cubemap, mip
+                );
+            }
+            program.set_uniform("face", face);
+            self.screen.draw_fullscreen();
+        }
+    }
+
+    fn render_2d(&self, texture : GLuint, size : i32) {
+        unsafe {
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+            gl::Viewport(0, 0, size, size);
+        }
+        self.screen.draw_fullscreen();
+    }
+}
+
+impl Drop for CubemapPass {
+    fn drop(&mut self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_fbo as GLuint);
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::Viewport(self.viewport[0], self.viewport[1], self.viewport[2], self.viewport[3]);
+
+            if self.depth_test {
+                gl::Enable(gl::DEPTH_TEST);
+            }
+        }
+    }
+}
+
+fn fullscreen_program<'a>(shader_cache : &'a mut ShaderCache, fragment_path : &str) -> Result<&'a ShaderProgram, String> {
+    shader_cache.get_or_compile(FULLSCREEN_VERTEX_SHADER_PATH, fragment_path, ShaderFeatures::default())
+}
+
+/// Converts an equirectangular image to a mipmapped cubemap of `size`, +y is
+/// up and the top row of the image is the zenith
+pub fn equirect_to_cubemap(img : &image::Rgb32FImage, size : i32, shader_cache : &mut ShaderCache) -> Result<GLuint, String> {
+    let program = fullscreen_program(shader_cache, EQUIRECT_TO_CUBE_SHADER_PATH)?;
+
+    let equirect = texture_2d(
+        gl::RGB32F, gl::RGB, img.width() as i32, img.height() as i32, gl::REPEAT, img.as_raw().as_ptr()
+    );
+    let cubemap = cubemap(size, size.ilog2() as i32 + 1);
+
+    let pass = CubemapPass::new();
+    program.use_program();
+    program.set_uniform("equirectMap", 0);
+    unsafe { gl::BindTexture(gl::TEXTURE_2D, equirect) };
+    pass.render_faces(program, cubemap, size, 0);
+    drop(pass);
+
+    unsafe {
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+        gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        gl::DeleteTextures(1, &equirect);
+    }
+
+    Ok(cubemap)
+}
+
+/// Image based lighting from an environment map: the environment as a
+/// cubemap, its diffuse irradiance, its specular reflection prefiltered per
+/// roughness, and the BRDF lookup table of the split sum approximation.
+/// Directions are in the space of the mesh, like the lights.
+pub struct Environment {
+    cubemap : GLuint,
+    irradiance : GLuint,
+    prefiltered : GLuint,
+    brdf_lut : GLuint,
+}
+
+impl Environment {
+    /// Renders every map from an equirectangular image, see `equirect_to_cubemap`
+    pub fn from_equirect(img : &image::Rgb32FImage, shader_cache : &mut ShaderCache) -> Result<Self, String> {
+        for fragment_path in [IRRADIANCE_SHADER_PATH, PREFILTER_SHADER_PATH, BRDF_LUT_SHADER_PATH] {
+            fullscreen_program(shader_cache, fragment_path)?;
+        }
+
+        let environment = Environment {
+            cubemap : equirect_to_cubemap(img, CUBEMAP_SIZE, shader_cache)?,
+            irradiance : cubemap(IRRADIANCE_SIZE, 1),
+            prefiltered : cubemap(PREFILTER_SIZE, PREFILTER_MIP_LEVELS),
+            brdf_lut : texture_2d(gl::RG16F, gl::RG, BRDF_LUT_SIZE, BRDF_LUT_SIZE, gl::CLAMP_TO_EDGE, std::ptr::null()),
+        };
+
+        let program = |fragment_path| {
+            shader_cache.get(FULLSCREEN_VERTEX_SHADER_PATH, fragment_path, ShaderFeatures::default()).unwrap()
+        };
+
+        let pass = CubemapPass::new();
+        // The convolutions read the whole environment from unit 0
+        unsafe { gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment.cubemap) };
+
+        let irradiance = program(IRRADIANCE_SHADER_PATH);
+        irradiance.use_program();
+        irradiance.set_uniform("environmentMap", 0);
+        pass.render_faces(irradiance, environment.irradiance, IRRADIANCE_SIZE, 0);
+
+        let prefilter = program(PREFILTER_SHADER_PATH);
+        prefilter.use_program();
+        prefilter.set_uniform("environmentMap", 0);
+        for mip in 0..PREFILTER_MIP_LEVELS {
+            prefilter.set_uniform("roughness", mip as f32 / (PREFILTER_MIP_LEVELS - 1) as f32);
+            pass.render_faces(prefilter, environment.prefiltered, PREFILTER_SIZE >> mip, mip);
+        }
+
+        program(BRDF_LUT_SHADER_PATH).use_program();
+        pass.render_2d(environment.brdf_lut, BRDF_LUT_SIZE);
+
+        Ok(environment)
+    }
+
+    /// The environment itself, in linear HDR
+    pub fn cubemap(&self) -> GLuint {
+        self.cubemap
+    }
+
+    /// Binds the maps to the units of `IBL_SAMPLERS`
+    pub fn bind(&self) {
+        let targets = [
+            (gl::TEXTURE_CUBE_MAP, self.irradiance),
+            (gl::TEXTURE_CUBE_MAP, self.prefiltered),
+            (gl::TEXTURE_2D, self.brdf_lut),
+        ];
+
+        unsafe {
+            for ((_, unit), (target, texture)) in IBL_SAMPLERS.iter().zip(targets) {
+                gl::ActiveTexture(gl::TEXTURE0 + *unit as GLenum);
+                gl::BindTexture(target, texture);
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(4, [self.cubemap, self.irradiance, self.prefiltered, self.brdf_lut].as_ptr());
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::HashMap, fs, time::{Duration, Instant, SystemTime}};
+
+/// Polls modification times of a set of files
+pub struct FileWatcher {
+    files : HashMap<String, Option<SystemTime>>,
+    interval : Duration,
+    last_poll : Instant,
+}
+
+fn mtime(path : &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl FileWatcher {
+    pub fn new(interval : Duration) -> Self {
+        FileWatcher { files : HashMap::new(), interval, last_poll : Instant::now() }
+    }
+
+    pub fn watch(&mut self, path : &str) {
+        self.files.insert(path.to_string(), mtime(path));
+    }
+
+    pub fn unwatch(&mut self, path : &str) {
+        self.files.remove(path);
+    }
+
+    /// Files modified since the last call. Checks at most once per interval
+    /// so it can be called every frame.
+    pub fn changed(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < self.interval {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+
+        for (path, last) in self.files.iter_mut() {
+            let current = mtime(path);
+
+            // A missing file is usually an editor saving through a rename
+            if current.is_some() && current != *last {
+                *last = current;
+                changed.push(path.clone());
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::Path, thread};
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    /// Rewrites the file with an mtime `secs` after the epoch, the file system
+    /// clock may be too coarse to tell apart writes made within a test
+    fn write(path : &Path, secs : u64) {
+        fs::write(path, secs.to_string()).unwrap();
+        File::options().write(true).open(path).unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    }
+
+    fn watched(name : &str, interval : Duration) -> (std::path::PathBuf, String, FileWatcher) {
+        let dir = temp_dir(name);
+        let path = dir.join("shader.glsl");
+        write(&path, 1000);
+
+        let mut watcher = FileWatcher::new(interval);
+        watcher.watch(&path.to_string_lossy());
+
+        (dir, path.to_string_lossy().into_owned(), watcher)
+    }
+
+    #[test]
+    fn changes_are_reported_once_after_the_interval() {
+        let interval = Duration::from_millis(250);
+        let (dir, path, mut watcher) = watched("watcher-debounce", interval);
+
+        write(Path::new(&path), 2000);
+        assert!(watcher.changed().is_empty(), "Polled before the interval");
+
+        thread::sleep(interval);
+        assert_eq!(watcher.changed(), vec![path.clone()]);
+
+        thread::sleep(interval);
+        assert!(watcher.changed().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unchanged_files_are_not_reported() {
+        let (dir, _, mut watcher) = watched("watcher-unchanged", Duration::ZERO);
+
+        for _ in 0..3 {
+            assert!(watcher.changed().is_empty());
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recreated_files_are_reported_once_they_exist_again() {
+        let (dir, path, mut watcher) = watched("watcher-recreated", Duration::ZERO);
+
+        // Editors saving through a rename briefly leave no file behind
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed().is_empty());
+
+        write(Path::new(&path), 3000);
+        assert_eq!(watcher.changed(), vec![path.clone()]);
+        assert!(watcher.changed().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn paths_watched_twice_are_reported_once() {
+        let (dir, path, mut watcher) = watched("watcher-twice", Duration::ZERO);
+        watcher.watch(&path);
+
+        write(Path::new(&path), 2000);
+        assert_eq!(watcher.changed(), vec![path.clone()]);
+
+        watcher.unwatch(&path);
+        write(Path::new(&path), 3000);
+        assert!(watcher.changed().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,41 @@
+use gl::types::GLuint;
+
+/// Vertex shader of the fullscreen passes, passes `screenCoord` from 0 to 1
+pub const FULLSCREEN_VERTEX_SHADER_PATH : &str = "shaders/fullscreen.glsl";
+
+/// Vertex array without attributes, for passes that build their verticies
+/// from gl_VertexID. The mesh attributes live on vertex array 0, drawing
+/// with it bound would read them past the end of small meshes.
+pub struct EmptyVertexArray {
+    id : GLuint,
+}
+
+impl EmptyVertexArray {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenVertexArrays(1, &mut id) };
+
+        EmptyVertexArray { id }
+    }
+
+    /// Draws the single triangle of `shaders/fullscreen.glsl` covering the viewport
+    pub fn draw_fullscreen(&self) {
+        unsafe {
+            gl::BindVertexArray(self.id);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::BindVertexArray(0);
+        }
+    }
+}
+
+impl Default for EmptyVertexArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for EmptyVertexArray {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteVertexArrays(1, &self.id) };
+    }
+}
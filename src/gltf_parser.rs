@@ -0,0 +1,574 @@
+use std::io;
+
+use gltf::{image::Format, material::AlphaMode, mesh::Mode};
+
+use crate::triangles::{
+    MaterialGroup, TriangleMesh, VertexAttribute, VertexAttributeLayout,
+    POSITION_ATTRIB, NORMAL_ATTRIB, TEXCOORD_ATTRIB, TANGENT_ATTRIB, TEXCOORD1_ATTRIB, COLOR_ATTRIB
+};
+
+type Mat4 = [[f32; 4]; 4];
+
+const IDENTITY : Mat4 = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.]
+];
+
+/// Column major product `a * b`, same convention as glTF node matrices
+fn mat_mul(a : &Mat4, b : &Mat4) -> Mat4 {
+    let mut m = [[0.; 4]; 4];
+
+    for (col, b_col) in b.iter().enumerate() {
+        for row in 0..4 {
+            m[col][row] = (0..4).map(|k| a[k][row] * b_col[k]).sum();
+        }
+    }
+
+    m
+}
+
+fn transform_point(m : &Mat4, p : [f32; 3]) -> [f32; 3] {
+    let mut out = [0.; 3];
+
+    for (row, x) in out.iter_mut().enumerate() {
+        *x = m[0][row] * p[0] + m[1][row] * p[1] + m[2][row] * p[2] + m[3][row];
+    }
+
+    out
+}
+
+fn transform_dir(m : &[[f32; 3]; 3], d : [f32; 3]) -> [f32; 3] {
+    let mut out = [0.; 3];
+
+    for (row, x) in out.iter_mut().enumerate() {
+        *x = m[0][row] * d[0] + m[1][row] * d[1] + m[2][row] * d[2];
+    }
+
+    let len = (out[0] * out[0] + out[1] * out[1] + out[2] * out[2]).sqrt();
+    if len > 0. {
+        out.iter_mut().for_each(|x| *x /= len);
+    }
+
+    out
+}
+
+/// Upper 3x3, used to transform tangents which lie in the surface
+fn direction_matrix(m : &Mat4) -> [[f32; 3]; 3] {
+    [
+        [m[0][0], m[0][1], m[0][2]],
+        [m[1][0], m[1][1], m[1][2]],
+        [m[2][0], m[2][1], m[2][2]]
+    ]
+}
+
+/// Of the upper 3x3, below 0 when the transform mirrors the mesh
+fn determinant(m : &Mat4) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+        - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+        + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2])
+}
+
+/// Inverse transpose of the upper 3x3, used to transform normals
+fn normal_matrix(m : &Mat4) -> [[f32; 3]; 3] {
+    let a = |c : usize, r : usize| m[c][r];
+
+    // Cofactor matrix equals the inverse transpose scaled by the determinant
+    let mut cof = [[0.; 3]; 3];
+    for (c, col) in cof.iter_mut().enumerate() {
+        for (r, x) in col.iter_mut().enumerate() {
+            let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+            let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+            *x = a(c0, r0) * a(c1, r1) - a(c1, r0) * a(c0, r1);
+        }
+    }
+
+    let det = a(0, 0) * cof[0][0] + a(0, 1) * cof[0][1] + a(0, 2) * cof[0][2];
+    let inv_det = if det != 0. { 1. / det } else { 1. };
+
+    cof.map(|col| col.map(|x| x * inv_det))
+}
+
+pub struct GltfNode {
+    pub name : Option<String>,
+    pub parent : Option<usize>,
+    pub children : Vec<usize>,
+    pub mesh : Option<usize>,
+    /// Column major local and world transforms
+    pub local_transform : Mat4,
+    pub world_transform : Mat4,
+}
+
+/// glTF metallic-roughness material, textures are indicies into `GltfScene::images`
+pub struct PbrMaterial {
+    pub name : String,
+    pub base_color_factor : [f32; 4],
+    pub metallic_factor : f32,
+    pub roughness_factor : f32,
+    pub emissive_factor : [f32; 3],
+    pub base_color_texture : Option<usize>,
+    pub metallic_roughness_texture : Option<usize>,
+    pub normal_texture : Option<usize>,
+    pub occlusion_texture : Option<usize>,
+    pub emissive_texture : Option<usize>,
+    pub alpha_mode : AlphaMode,
+    pub alpha_cutoff : f32,
+    pub double_sided : bool,
+}
+
+/// Decoded image converted to RGBA8, ready for `Texture::from_rgba`
+pub struct GltfImage {
+    pub name : Option<String>,
+    pub width : u32,
+    pub height : u32,
+    pub pixels : Vec<u8>,
+}
+
+pub struct GltfScene {
+    pub mesh : TriangleMesh,
+    pub nodes : Vec<GltfNode>,
+    pub materials : Vec<PbrMaterial>,
+    pub images : Vec<GltfImage>,
+}
+
+#[derive(Default)]
+struct Attributes {
+    normals : bool,
+    tangents : bool,
+    tex_coords : bool,
+    tex_coords1 : bool,
+    colors : bool,
+}
+
+impl Attributes {
+    fn vertex_attrib_layout(&self) -> VertexAttributeLayout {
+        let mut v = vec![VertexAttribute::float(POSITION_ATTRIB, 3)];
+
+        if self.normals {
+            v.push(VertexAttribute::float(NORMAL_ATTRIB, 3));
+        }
+        if self.tex_coords {
+            v.push(VertexAttribute::float(TEXCOORD_ATTRIB, 2));
+        }
+        if self.tangents {
+            v.push(VertexAttribute::float(TANGENT_ATTRIB, 4));
+        }
+        if self.tex_coords1 {
+            v.push(VertexAttribute::float(TEXCOORD1_ATTRIB, 2));
+        }
+        if self.colors {
+            v.push(VertexAttribute::float(COLOR_ATTRIB, 4));
+        }
+
+        VertexAttributeLayout::new(v)
+    }
+}
+
+fn rgba8(image : gltf::image::Data) -> Vec<u8> {
+    let to_u8 = |x : u16| (x >> 8) as u8;
+    let f32_to_u8 = |x : f32| (x.clamp(0., 1.) * 255.).round() as u8;
+    let p = &image.pixels;
+
+    match image.format {
+        Format::R8G8B8A8 => image.pixels,
+        Format::R8 => p.iter().flat_map(|&r| [r, r, r, 255]).collect(),
+        Format::R8G8 => p.chunks_exact(2).flat_map(|c| [c[0], c[1], 0, 255]).collect(),
+        Format::R8G8B8 => p.chunks_exact(3).flat_map(|c| [c[0], c[1], c[2], 255]).collect(),
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => {
+            let channels = match image.format {
+                Format::R16 => 1,
+                Format::R16G16 => 2,
+                Format::R16G16B16 => 3,
+                _ => 4
+            };
+            let values : Vec<u16> = p.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+
+            values.chunks_exact(channels).flat_map(|c| match channels {
+                1 => [to_u8(c[0]), to_u8(c[0]), to_u8(c[0]), 255],
+                2 => [to_u8(c[0]), to_u8(c[1]), 0, 255],
+                3 => [to_u8(c[0]), to_u8(c[1]), to_u8(c[2]), 255],
+                _ => [to_u8(c[0]), to_u8(c[1]), to_u8(c[2]), to_u8(c[3])]
+            }).collect()
+        }
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => {
+            let channels = if image.format == Format::R32G32B32FLOAT { 3 } else { 4 };
+            let values : Vec<f32> = p.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+
+            values.chunks_exact(channels).flat_map(|c| [
+                f32_to_u8(c[0]), f32_to_u8(c[1]), f32_to_u8(c[2]),
+                if channels == 4 { f32_to_u8(c[3]) } else { 255 }
+            ]).collect()
+        }
+    }
+}
+
+fn material_name(material : &gltf::Material) -> String {
+    match (material.name(), material.index()) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(i)) => format!("material{}", i),
+        (None, None) => "default".to_string()
+    }
+}
+
+fn collect_nodes(node : &gltf::Node, parent : Option<usize>, parent_transform : &Mat4, nodes : &mut Vec<GltfNode>) {
+    let local_transform = node.transform().matrix();
+    let world_transform = mat_mul(parent_transform, &local_transform);
+    let index = nodes.len();
+
+    nodes.push(GltfNode {
+        name : node.name().map(|n| n.to_string()),
+        parent,
+        children : Vec::new(),
+        mesh : node.mesh().map(|m| m.index()),
+        local_transform,
+        world_transform,
+    });
+
+    for child in node.children() {
+        let child_index = nodes.len();
+        nodes[index].children.push(child_index);
+        collect_nodes(&child, Some(index), &world_transform, nodes);
+    }
+}
+
+fn invalid_data(msg : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Loads the default scene of a .gltf or .glb file, flattening every
+/// triangle primitive into one mesh in world space
+pub fn read_gltf(filename : &str) -> io::Result<GltfScene> {
+    let (document, buffers, images) = gltf::import(filename)
+        .map_err(|e| invalid_data(e.to_string()))?;
+
+    let scene = document.default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| invalid_data("No scene in file".to_string()))?;
+
+    let mut nodes = Vec::new();
+    for node in scene.nodes() {
+        collect_nodes(&node, None, &IDENTITY, &mut nodes);
+    }
+
+    let mesh_nodes : Vec<(&GltfNode, gltf::Mesh)> = nodes.iter()
+        .filter_map(|node| Some((node, document.meshes().nth(node.mesh?)?)))
+        .collect();
+
+    // Every primitive shares one layout, attributes missing on some primitives get defaults
+    let mut attributes = Attributes::default();
+    for (_, mesh) in &mesh_nodes {
+        for primitive in mesh.primitives().filter(|p| p.mode() == Mode::Triangles) {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            attributes.normals |= reader.read_normals().is_some();
+            attributes.tangents |= reader.read_tangents().is_some();
+            attributes.tex_coords |= reader.read_tex_coords(0).is_some();
+            attributes.tex_coords1 |= reader.read_tex_coords(1).is_some();
+            attributes.colors |= reader.read_colors(0).is_some();
+        }
+    }
+
+    let mut verticies : Vec<f32> = Vec::new();
+    let mut vertex_indicies : Vec<u32> = Vec::new();
+    let mut materials : Vec<MaterialGroup> = Vec::new();
+    let mut base_vertex = 0;
+
+    for (node, mesh) in &mesh_nodes {
+        let normal_mat = normal_matrix(&node.world_transform);
+        let tangent_mat = direction_matrix(&node.world_transform);
+        // A mirroring transform turns the triangles inside out and flips the
+        // handedness of the tangent frame
+        let mirrored = determinant(&node.world_transform) < 0.;
+        let handedness = if mirrored { -1. } else { 1. };
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                println!("Skipping {:?} primitive in {}", primitive.mode(), filename);
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let positions : Vec<[f32; 3]> = match reader.read_positions() {
+                Some(p) => p.map(|p| transform_point(&node.world_transform, p)).collect(),
+                None => continue
+            };
+            let normals : Option<Vec<[f32; 3]>> = reader.read_normals()
+                .map(|n| n.map(|n| transform_dir(&normal_mat, n)).collect());
+            let tangents : Option<Vec<[f32; 4]>> = reader.read_tangents()
+                .map(|t| t.map(|t| {
+                    let [x, y, z] = transform_dir(&tangent_mat, [t[0], t[1], t[2]]);
+                    [x, y, z, t[3] * handedness]
+                }).collect());
+            let tex_coords : Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+            let tex_coords1 : Option<Vec<[f32; 2]>> = reader.read_tex_coords(1).map(|t| t.into_f32().collect());
+            let colors : Option<Vec<[f32; 4]>> = reader.read_colors(0).map(|c| c.into_rgba_f32().collect());
+
+            let mut indicies : Vec<u32> = match reader.read_indices() {
+                Some(i) => i.into_u32().collect(),
+                None => (0..positions.len() as u32).collect()
+            };
+            if mirrored {
+                indicies.chunks_exact_mut(3).for_each(|tri| tri.swap(1, 2));
+            }
+
+            let counts = [
+                normals.as_ref().map(|n| n.len()),
+                tangents.as_ref().map(|t| t.len()),
+                tex_coords.as_ref().map(|t| t.len()),
+                tex_coords1.as_ref().map(|t| t.len()),
+                colors.as_ref().map(|c| c.len()),
+            ];
+            if counts.into_iter().flatten().any(|count| count != positions.len()) {
+                return Err(invalid_data(format!("Attribute counts differ in mesh {}", mesh.index())));
+            }
+            if let Some(i) = indicies.iter().find(|i| **i as usize >= positions.len()) {
+                return Err(invalid_data(format!("Index {} out of range (0..{}) in mesh {}", i, positions.len(), mesh.index())));
+            }
+
+            let first = vertex_indicies.len() as u32;
+
+            for i in indicies {
+                let i = i as usize;
+
+                verticies.extend(positions[i]);
+                if attributes.normals {
+                    verticies.extend(normals.as_ref().map_or([0.; 3], |n| n[i]));
+                }
+                // glTF puts the UV origin top left, the shaders expect OBJ's bottom left
+                if attributes.tex_coords {
+                    let [u, v] = tex_coords.as_ref().map_or([0.; 2], |t| t[i]);
+                    verticies.extend([u, 1. - v]);
+                }
+                if attributes.tangents {
+                    verticies.extend(tangents.as_ref().map_or([0., 0., 0., 1.], |t| t[i]));
+                }
+                if attributes.tex_coords1 {
+                    let [u, v] = tex_coords1.as_ref().map_or([0.; 2], |t| t[i]);
+                    verticies.extend([u, 1. - v]);
+                }
+                if attributes.colors {
+                    verticies.extend(colors.as_ref().map_or([1.; 4], |c| c[i]));
+                }
+
+                vertex_indicies.push(base_vertex + i as u32);
+            }
+
+            base_vertex += positions.len() as u32;
+
+            let name = material_name(&primitive.material());
+            let count = vertex_indicies.len() as u32 - first;
+
+            match materials.last_mut() {
+                Some(group) if group.name == name => group.count += count,
+                _ => materials.push(MaterialGroup { name, first, count })
+            }
+        }
+    }
+
+    let normal_indicies = if attributes.normals { vertex_indicies.clone() } else { Vec::new() };
+    let texture_indicies = if attributes.tex_coords { vertex_indicies.clone() } else { Vec::new() };
+
+    let mut mesh = TriangleMesh::from_array_indicies(
+        verticies,
+        vertex_indicies,
+        normal_indicies,
+        texture_indicies,
+        attributes.vertex_attrib_layout()
+    );
+    mesh.materials = materials;
+
+    let materials = document.materials().map(|m| {
+        let pbr = m.pbr_metallic_roughness();
+        let source = |info : Option<gltf::texture::Texture>| info.map(|t| t.source().index());
+
+        PbrMaterial {
+            name : material_name(&m),
+            base_color_factor : pbr.base_color_factor(),
+            metallic_factor : pbr.metallic_factor(),
+            roughness_factor : pbr.roughness_factor(),
+            emissive_factor : m.emissive_factor(),
+            base_color_texture : source(pbr.base_color_texture().map(|i| i.texture())),
+            metallic_roughness_texture : source(pbr.metallic_roughness_texture().map(|i| i.texture())),
+            normal_texture : source(m.normal_texture().map(|i| i.texture())),
+            occlusion_texture : source(m.occlusion_texture().map(|i| i.texture())),
+            emissive_texture : source(m.emissive_texture().map(|i| i.texture())),
+            alpha_mode : m.alpha_mode(),
+            alpha_cutoff : m.alpha_cutoff().unwrap_or(0.5),
+            double_sided : m.double_sided(),
+        }
+    }).collect();
+
+    let images = document.images().zip(images).map(|(info, data)| GltfImage {
+        name : info.name().map(|n| n.to_string()),
+        width : data.width,
+        height : data.height,
+        pixels : rgba8(data),
+    }).collect();
+
+    Ok(GltfScene { mesh, nodes, materials, images })
+}
+
+pub fn gltf_to_scene(filename : &str) -> GltfScene {
+    read_gltf(filename)
+        .unwrap_or_else(|e| panic!("Could not read file: {}: {}", filename, e))
+}
+
+pub fn gltf_to_mesh(filename : &str) -> TriangleMesh {
+    gltf_to_scene(filename).mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::temp_dir;
+    use crate::triangles::face_normal;
+
+    const QUAD : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/objects/quad.gltf");
+
+    fn assert_close(a : &[f32], b : &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-6, "{:?} != {:?}", a, b);
+        }
+    }
+
+    /// One triangle with a diagonal tangent under a node with `scale`
+    fn write_triangle(dir : &std::path::Path, scale : [f32; 3], indicies : [u16; 3]) -> String {
+        let d = std::f32::consts::FRAC_1_SQRT_2;
+        let floats = [
+            0., 0., 0., 1., 0., 0., 0., 1., 0.,
+            0., 0., 1., 0., 0., 1., 0., 0., 1.,
+            d, d, 0., 1., d, d, 0., 1., d, d, 0., 1.,
+        ];
+        let mut bin : Vec<u8> = floats.iter().flat_map(|x : &f32| x.to_le_bytes()).collect();
+        bin.extend(indicies.iter().flat_map(|i| i.to_le_bytes()));
+        bin.extend([0, 0]);
+        fs::write(dir.join("triangle.bin"), &bin).unwrap();
+
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0, "scale": [{}, {}, {}] }}],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 1, "TANGENT": 2 }}, "indices": 3 }}] }}],
+            "buffers": [{{ "uri": "triangle.bin", "byteLength": {} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 72, "byteLength": 48 }},
+                {{ "buffer": 0, "byteOffset": 120, "byteLength": 6 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4" }},
+                {{ "bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ]
+        }}"#, scale[0], scale[1], scale[2], bin.len());
+
+        let path = dir.join("triangle.gltf");
+        fs::write(&path, json).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn quad_node_transforms_are_applied() {
+        let scene = read_gltf(QUAD).unwrap();
+
+        assert_eq!(scene.nodes.len(), 2);
+        assert_eq!(scene.nodes[0].children, vec![1]);
+        assert_eq!(scene.nodes[1].parent, Some(0));
+        assert_eq!(scene.nodes[1].mesh, Some(0));
+
+        // Translation of the root after the scale of the child
+        let world = scene.nodes[1].world_transform;
+        assert_close(&[world[0][0], world[1][1], world[2][2]], &[2., 2., 2.]);
+        assert_close(&world[3], &[0., 0.5, 0., 1.]);
+
+        let mesh = &scene.mesh;
+        assert_eq!(mesh.vertex_indicies, vec![0, 1, 2, 0, 2, 3]);
+        assert_close(&mesh.position(0), &[-1., -0.5, 0.]);
+        assert_close(&mesh.position(2), &[1., 1.5, 0.]);
+        assert_close(mesh.attribute(0, NORMAL_ATTRIB, 3).unwrap(), &[0., 0., 1.]);
+    }
+
+    #[test]
+    fn quad_material_factors_are_read() {
+        let scene = read_gltf(QUAD).unwrap();
+
+        assert_eq!(scene.materials.len(), 1);
+        let material = &scene.materials[0];
+
+        assert_eq!(material.name, "chess");
+        assert_eq!(material.base_color_factor, [1., 1., 1., 1.]);
+        assert_eq!(material.metallic_factor, 0.);
+        assert_eq!(material.roughness_factor, 0.5);
+        assert_eq!(material.base_color_texture, Some(0));
+        assert_eq!(material.metallic_roughness_texture, None);
+        assert!(material.double_sided);
+        assert!(material.alpha_mode == AlphaMode::Opaque);
+
+        assert_eq!(scene.mesh.materials, vec![MaterialGroup { name : "chess".to_string(), first : 0, count : 6 }]);
+        assert_eq!(scene.images.len(), 1);
+    }
+
+    #[test]
+    fn quad_uvs_are_flipped_to_a_bottom_left_origin() {
+        let mesh = read_gltf(QUAD).unwrap().mesh;
+
+        // The file stores (0, 1), (1, 1), (1, 0), (0, 0)
+        let uvs : Vec<&[f32]> = [0, 1, 2, 5]
+            .iter()
+            .map(|i| mesh.attribute(*i, TEXCOORD_ATTRIB, 2).unwrap())
+            .collect();
+
+        assert_eq!(uvs, vec![&[0., 0.][..], &[1., 0.], &[1., 1.], &[0., 1.]]);
+    }
+
+    #[test]
+    fn tangents_follow_non_uniform_scale() {
+        let dir = temp_dir("gltf-tangents");
+        let mesh = read_gltf(&write_triangle(&dir, [2., 1., 1.], [0, 1, 2])).unwrap().mesh;
+
+        // The diagonal (1, 1, 0) is stretched along x, not squashed like a normal
+        let t = mesh.attribute(0, TANGENT_ATTRIB, 4).unwrap();
+        let len = 5f32.sqrt();
+        assert_close(t, &[2. / len, 1. / len, 0., 1.]);
+        assert_close(mesh.attribute(0, NORMAL_ATTRIB, 3).unwrap(), &[0., 0., 1.]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mirrored_nodes_keep_their_winding_and_handedness() {
+        let dir = temp_dir("gltf-mirrored");
+        let mesh = read_gltf(&write_triangle(&dir, [-1., 1., 1.], [0, 1, 2])).unwrap().mesh;
+
+        // Still counter-clockwise seen from the normal
+        let [a, b, c] = [0, 1, 2].map(|i| mesh.position(i));
+        assert_close(&[a, b, c].concat(), &[0., 0., 0., 0., 1., 0., -1., 0., 0.]);
+        let n = mesh.attribute(0, NORMAL_ATTRIB, 3).unwrap();
+        assert_close(n, &[0., 0., 1.]);
+        assert_close(&face_normal(a, b, c), n);
+
+        let d = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(mesh.attribute(0, TANGENT_ATTRIB, 4).unwrap(), &[-d, d, 0., -1.]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn out_of_range_index_is_an_error() {
+        let dir = temp_dir("gltf-bad-index");
+        let result = read_gltf(&write_triangle(&dir, [1., 1., 1.], [0, 1, 7]));
+
+        let err = result.err().expect("Out of range index was accepted");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
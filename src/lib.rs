@@ -0,0 +1,35 @@
+// Enum variants follow the GL naming (INT, VEC3, VERTEX, ...)
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod set_uniform;
+pub mod opengl_handler;
+pub mod triangles;
+pub mod tangent_space;
+pub mod obj_parser;
+pub mod texture;
+pub mod moving;
+pub mod mesh_export;
+pub mod mesh_cache;
+pub mod gltf_parser;
+pub mod ply_parser;
+pub mod stl_parser;
+pub mod mesh_loader;
+pub mod mtl_parser;
+pub mod asset_loader;
+pub mod file_watcher;
+pub mod shader_preprocessor;
+pub mod shader_program;
+pub mod material;
+pub mod oit;
+pub mod fullscreen;
+pub mod environment;
+pub mod background;
+pub mod shadow;
+pub mod post_process;
+pub mod multisample;
+pub mod ssao;
+pub mod deferred;
+pub mod uniform_buffer;
+
+#[cfg(test)]
+mod test_util;
@@ -4,24 +4,26 @@ extern crate gl;
 use glutin::event_loop::{EventLoop, ControlFlow};
 use glutin::window::WindowBuilder;
 use glutin::ContextBuilder;
-use glutin::event::{Event, WindowEvent};
-use moving::center_obj_fn;
-use std::time::Instant;
+use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use rendering::moving::center_mesh_fn;
+use std::time::{Duration, Instant};
 
-mod set_uniform;
-mod opengl_handler;
-mod triangles;
-mod obj_parser;
-mod texture;
-mod moving;
-
-use obj_parser::{obj_to_mesh, FaceLayout};
-use opengl_handler::{CameraHandler, OpenGLHandler};
+use rendering::asset_loader::{AssetLoader, LoadedAsset};
+use rendering::background::Background;
+use rendering::deferred::GBufferChannel;
+use rendering::file_watcher::FileWatcher;
+use rendering::mesh_loader::load_mesh;
+use rendering::obj_parser::FaceLayout;
+use rendering::opengl_handler::{CameraHandler, OpenGLHandler};
+use rendering::post_process::{PostPass, Tonemap};
+use rendering::shadow::ShadowSettings;
+use rendering::ssao::SsaoSettings;
+use rendering::uniform_buffer::{FogBlock, FogMode};
 
 fn main() {
     // Define the size of the viewport (width and height in pixels)
-    let mut width = 1000;   
-    let mut height = 1000; 
+    let width = 1000;
+    let height = 1000;
 
     let event_loop = EventLoop::new();
     let window_builder = WindowBuilder::new().with_title("OpenGL Window");
@@ -36,21 +38,80 @@ fn main() {
 
     let obj = "objects/Scaniverse.obj";
     let tex = "textures/Scaniverse.jpg";
+    // Optional, PBR materials fall back to a constant ambient light without it
+    let environment = "textures/environment.hdr";
+
+    // Drawn until the real mesh and texture are uploaded
+    let placeholder = "objects/full_quad.obj";
 
-    let triangles = obj_to_mesh(obj, &face_layout);
-    let tex_path = Some(tex);
+    let mut asset_loader = AssetLoader::new(2);
+    let mut result = asset_loader.load_mesh(obj, face_layout.clone())
+        .and_then(|_| asset_loader.load_texture(tex));
+    if std::path::Path::new(environment).exists() {
+        result = result.and_then(|_| asset_loader.load_environment(environment));
+    }
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+
+    let triangles = load_mesh(placeholder, &FaceLayout::new(Some(0), Some(1), None));
 
     let mut opengl_handler = OpenGLHandler::new();
     opengl_handler.init_shaders();
     opengl_handler.init_buffers(Some(&triangles));
-    opengl_handler.init_textures(tex_path);
+    opengl_handler.init_textures(None);
+    opengl_handler.resize(width, height);
+    if let Err(e) = opengl_handler.set_shadows(Some(ShadowSettings::default())) {
+        println!("{}", e);
+    }
+    // Toggled with the O key
+    let mut ssao_enabled = true;
+    // Toggled with the D key, G cycles through the G-buffer channels.
+    // The lights only change PBR materials, others keep the fixed lighting.
+    let mut deferred_enabled = false;
+    // Toggled with the T key, transparent groups are sorted back to front without it
+    let mut oit_enabled = false;
+    // Cycled with the F key, the mesh sits about 2.2 units in front of the camera
+    let fog_modes = [
+        FogMode::NONE,
+        FogMode::LINEAR { start : 1.5, end : 4. },
+        FogMode::EXPONENTIAL(0.3),
+        FogMode::EXPONENTIAL_SQUARED(0.4),
+    ];
+    let mut fog_mode = 0;
+    // Samples per pixel of multisample anti-aliasing, cycled with the M key.
+    // A count above what the driver supports wraps around to 0.
+    let msaa_samples = [0, 2, 4, 8, 16];
+    let mut msaa = 2;
+    if let Err(e) = opengl_handler.set_ssao(Some(SsaoSettings::default())) {
+        println!("{}", e);
+    }
+    if let Err(e) = opengl_handler.set_multisampling(msaa_samples[msaa]) {
+        msaa = 0;
+        println!("{}", e);
+    }
+    if let Err(e) = opengl_handler.set_post_processing(Some(vec![PostPass::TONEMAP(Tonemap::ACES), PostPass::FXAA])) {
+        println!("{}", e);
+    }
 
-    let movement_fn = center_obj_fn(obj, 0., 0.5, -2.2);
-    
-    let fov = 3.1415 / 3.;
+    let mut file_watcher = FileWatcher::new(Duration::from_millis(250));
+    for path in [obj, tex, environment] {
+        file_watcher.watch(path);
+    }
+    for path in opengl_handler.shader_files() {
+        file_watcher.watch(&path);
+    }
+    // MTL files of the mesh, watched once it is loaded
+    let mut material_files : Vec<String> = Vec::new();
+
+    let mut movement_fn : Box<dyn Fn(&mut CameraHandler)> = Box::new(center_mesh_fn(&triangles, 0., 0., -2.2));
+
+    let fov = std::f32::consts::PI / 3.;
     let (n, f) = (0.1, 10.);
     
-    opengl_handler.camera_handler = CameraHandler::perspective(fov, width as f32 / height as f32, n, f);
+    let mut aspect = width as f32 / height as f32;
+
+    opengl_handler.camera_handler = CameraHandler::perspective(fov, aspect, n, f);
     movement_fn(&mut opengl_handler.camera_handler);
 
     let mut t : f32 = 0.0;
@@ -62,19 +123,125 @@ fn main() {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                 WindowEvent::Resized(new_size) => {
-                    (width, height) = new_size.into();
-                    unsafe {
-                        gl::Viewport(0, 0, width as i32, height as i32);
+                    let (width, height) : (i32, i32) = new_size.into();
+                    aspect = width as f32 / height as f32;
+                    opengl_handler.resize(width, height);
 
-                        opengl_handler.camera_handler = CameraHandler::perspective(fov, width as f32 / height as f32, n, f);
-                        movement_fn(&mut opengl_handler.camera_handler);                    
+                    opengl_handler.camera_handler = CameraHandler::perspective(fov, aspect, n, f);
+                    movement_fn(&mut opengl_handler.camera_handler);
+                }
+                WindowEvent::KeyboardInput {
+                    input : KeyboardInput { state : ElementState::Pressed, virtual_keycode : Some(VirtualKeyCode::O), .. }, ..
+                } => {
+                    ssao_enabled = !ssao_enabled;
+                    if let Err(e) = opengl_handler.set_ssao(ssao_enabled.then(SsaoSettings::default)) {
+                        println!("{}", e);
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input : KeyboardInput { state : ElementState::Pressed, virtual_keycode : Some(VirtualKeyCode::D), .. }, ..
+                } => {
+                    deferred_enabled = !deferred_enabled;
+                    if let Err(e) = opengl_handler.set_deferred_shading(deferred_enabled) {
+                        deferred_enabled = !deferred_enabled;
+                        println!("{}", e);
                     }
                 }
+                WindowEvent::KeyboardInput {
+                    input : KeyboardInput { state : ElementState::Pressed, virtual_keycode : Some(VirtualKeyCode::T), .. }, ..
+                } => {
+                    oit_enabled = !oit_enabled;
+                    if let Err(e) = opengl_handler.set_order_independent_transparency(oit_enabled) {
+                        oit_enabled = false;
+                        println!("{}", e);
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input : KeyboardInput { state : ElementState::Pressed, virtual_keycode : Some(VirtualKeyCode::F), .. }, ..
+                } => {
+                    fog_mode = (fog_mode + 1) % fog_modes.len();
+                    println!("Fog: {:?}", fog_modes[fog_mode]);
+                    opengl_handler.set_fog(FogBlock { mode : fog_modes[fog_mode], ..FogBlock::default() });
+                }
+                WindowEvent::KeyboardInput {
+                    input : KeyboardInput { state : ElementState::Pressed, virtual_keycode : Some(VirtualKeyCode::M), .. }, ..
+                } => {
+                    msaa = (msaa + 1) % msaa_samples.len();
+                    if opengl_handler.set_multisampling(msaa_samples[msaa]).is_err() {
+                        msaa = 0;
+                        if let Err(e) = opengl_handler.set_multisampling(0) {
+                            println!("{}", e);
+                        }
+                    }
+                    println!("MSAA: {} samples", msaa_samples[msaa]);
+                }
+                WindowEvent::KeyboardInput {
+                    input : KeyboardInput { state : ElementState::Pressed, virtual_keycode : Some(VirtualKeyCode::G), .. }, ..
+                } if deferred_enabled => {
+                    let channel = GBufferChannel::cycle(opengl_handler.gbuffer_view());
+                    println!("G-buffer view: {:?}", channel);
+                    opengl_handler.set_gbuffer_view(channel);
+                }
                 _ => (),
             },
             Event::MainEventsCleared => {
                 let start = Instant::now();
 
+                for path in file_watcher.changed() {
+                    println!("Reloading {}", path);
+
+                    // The materials are read along with the mesh
+                    let result = if path == obj || material_files.contains(&path) {
+                        asset_loader.load_mesh(obj, face_layout.clone())
+                    } else if path == tex {
+                        asset_loader.load_texture(tex)
+                    } else if path == environment {
+                        asset_loader.load_environment(environment)
+                    } else {
+                        opengl_handler.reload_shaders().map(|_| {
+                            // An edit may have added includes
+                            for path in opengl_handler.shader_files() {
+                                file_watcher.watch(&path);
+                            }
+                        })
+                    };
+
+                    if let Err(e) = result {
+                        println!("{}", e);
+                    }
+                }
+
+                for asset in asset_loader.poll() {
+                    match asset {
+                        LoadedAsset::Mesh(_, mesh, materials) => {
+                            opengl_handler.init_buffers(Some(&mesh));
+
+                            if let Some(materials) = materials {
+                                if let Err(e) = opengl_handler.set_mesh_materials(&materials) {
+                                    println!("{}", e);
+                                }
+                                for path in materials.files() {
+                                    file_watcher.watch(path);
+                                }
+                                material_files = materials.files().to_vec();
+                            }
+
+                            movement_fn = Box::new(center_mesh_fn(&mesh, 0., 0.5, -2.2));
+                            opengl_handler.camera_handler = CameraHandler::perspective(fov, aspect, n, f);
+                            movement_fn(&mut opengl_handler.camera_handler);
+                        }
+                        LoadedAsset::Texture(_, img) => opengl_handler.set_texture(&img),
+                        LoadedAsset::Environment(_, img) => {
+                            let result = opengl_handler.set_environment(Some(&img))
+                                .and_then(|_| opengl_handler.set_background(Background::ENVIRONMENT));
+                            if let Err(e) = result {
+                                println!("{}", e);
+                            }
+                        }
+                        LoadedAsset::Failed(path, e) => println!("Could not load {}: {}", path, e)
+                    }
+                }
+
                 opengl_handler.draw();
                 opengl_handler.camera_handler.rotate(-t, [0., 1., 0.]);
                 context.swap_buffers().unwrap();
@@ -84,7 +251,12 @@ fn main() {
 
                 t = 1.0 / fps as f32;
 
-                context.window().set_title(&format!("FPS: {}", fps.round()));
+                if asset_loader.is_loading() {
+                    let (done, total) = asset_loader.progress();
+                    context.window().set_title(&format!("FPS: {} - Loading {}/{}", fps.round(), done, total));
+                } else {
+                    context.window().set_title(&format!("FPS: {}", fps.round()));
+                }
             }
             _ => (),
         }
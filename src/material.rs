@@ -0,0 +1,279 @@
+use gl::types::GLuint;
+
+use crate::gltf_parser::PbrMaterial;
+use crate::mtl_parser::MtlMaterial;
+use crate::opengl_handler::{FRAGMENT_SHADER_PATH, VERTEX_SHADER_PATH};
+use crate::set_uniform::UniformType;
+use crate::shader_preprocessor::ShaderFeatures;
+use crate::uniform_buffer::MaterialBlock;
+
+/// Fixed function state a material is drawn with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RenderState {
+    pub blend : bool,
+    pub cull : bool,
+    pub depth_write : bool,
+}
+
+impl RenderState {
+    /// Sets the flags that differ from `current`, or all of them without one
+    pub fn apply(&self, current : Option<&RenderState>) {
+        let changed = |flag : fn(&RenderState) -> bool| current.is_none_or(|c| flag(c) != flag(self));
+
+        unsafe {
+            if changed(|s| s.blend) {
+                if self.blend {
+                    gl::Enable(gl::BLEND);
+                    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                } else {
+                    gl::Disable(gl::BLEND);
+                }
+            }
+
+            if changed(|s| s.cull) {
+                if self.cull {
+                    gl::Enable(gl::CULL_FACE);
+                } else {
+                    gl::Disable(gl::CULL_FACE);
+                }
+            }
+
+            if changed(|s| s.depth_write) {
+                gl::DepthMask(if self.depth_write { gl::TRUE } else { gl::FALSE });
+            }
+        }
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        RenderState { blend : false, cull : false, depth_write : true }
+    }
+}
+
+/// How the alpha of a material is used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    OPAQUE,
+    /// Fragments below the cutoff are discarded, the rest are opaque
+    MASK(f32),
+    /// Blended over what is behind, drawn back to front after opaque draws
+    BLEND,
+}
+
+impl AlphaMode {
+    /// From the MTL dissolve, `d` or `1 - Tr`
+    pub fn from_dissolve(dissolve : f32) -> Self {
+        if dissolve < 1. { AlphaMode::BLEND } else { AlphaMode::OPAQUE }
+    }
+
+    /// From the alpha channel of a texture: cut out when it only holds fully
+    /// transparent and fully opaque texels, blended when anything in between
+    pub fn from_image(img : &image::RgbaImage) -> Self {
+        let mut cutout = false;
+
+        for pixel in img.pixels() {
+            match pixel[3] {
+                255 => (),
+                0 => cutout = true,
+                _ => return AlphaMode::BLEND
+            }
+        }
+
+        if cutout { AlphaMode::MASK(0.5) } else { AlphaMode::OPAQUE }
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::MASK(cutoff) => *cutoff,
+            _ => 0.
+        }
+    }
+}
+
+impl From<gltf::material::AlphaMode> for AlphaMode {
+    fn from(mode : gltf::material::AlphaMode) -> Self {
+        match mode {
+            gltf::material::AlphaMode::Opaque => AlphaMode::OPAQUE,
+            gltf::material::AlphaMode::Mask => AlphaMode::MASK(0.5),
+            gltf::material::AlphaMode::Blend => AlphaMode::BLEND,
+        }
+    }
+}
+
+/// Sampler of the base colour, also used without PBR
+pub const BASE_COLOR_SAMPLER : &str = "texture0";
+/// Sampler of the tangent space normal map
+pub const NORMAL_MAP_SAMPLER : &str = "normalMap";
+/// Roughness is read from green and metallic from blue, as glTF packs them.
+/// Greyscale maps work in both.
+pub const ROUGHNESS_MAP_SAMPLER : &str = "roughnessMap";
+pub const METALLIC_MAP_SAMPLER : &str = "metallicMap";
+pub const OCCLUSION_MAP_SAMPLER : &str = "occlusionMap";
+pub const EMISSIVE_MAP_SAMPLER : &str = "emissiveMap";
+
+/// Samplers of the PBR path, bound to a white texture when the material has none
+pub const PBR_SAMPLERS : [&str; 5] = [
+    BASE_COLOR_SAMPLER, ROUGHNESS_MAP_SAMPLER, METALLIC_MAP_SAMPLER, OCCLUSION_MAP_SAMPLER, EMISSIVE_MAP_SAMPLER
+];
+
+/// What a material group of a mesh is drawn with. The program is the
+/// permutation of `vertex_shader` and `fragment_shader` matching the mesh.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name : String,
+    pub vertex_shader : String,
+    pub fragment_shader : String,
+    /// (sampler uniform, texture). `texture0` replaces the handler's texture on
+    /// unit 0, the others are bound from unit 1 on.
+    pub textures : Vec<(String, GLuint)>,
+    pub uniforms : Vec<(String, UniformType)>,
+    /// Contents of the `Material` uniform block
+    pub block : MaterialBlock,
+    pub state : RenderState,
+    pub alpha_mode : AlphaMode,
+    /// Shaded with the metallic-roughness model instead of the MTL colours
+    pub pbr : bool,
+}
+
+impl Material {
+    pub fn new(name : &str) -> Self {
+        Material {
+            name : name.to_string(),
+            vertex_shader : VERTEX_SHADER_PATH.to_string(),
+            fragment_shader : FRAGMENT_SHADER_PATH.to_string(),
+            textures : Vec::new(),
+            uniforms : Vec::new(),
+            block : MaterialBlock::default(),
+            state : RenderState::default(),
+            alpha_mode : AlphaMode::OPAQUE,
+            pbr : false,
+        }
+    }
+
+    /// Colours of an MTL material, its textures are left to the caller. Without
+    /// a dissolve the alpha mode comes from `diffuse_map`, the decoded `map_Kd`.
+    pub fn from_mtl(material : &MtlMaterial, diffuse_map : Option<&image::RgbaImage>) -> Self {
+        let alpha_mode = match (AlphaMode::from_dissolve(material.dissolve), diffuse_map) {
+            (AlphaMode::OPAQUE, Some(img)) => AlphaMode::from_image(img),
+            (alpha_mode, _) => alpha_mode
+        };
+
+        Material {
+            block : MaterialBlock::from_mtl(material),
+            alpha_mode,
+            pbr : material.is_pbr(),
+            ..Material::new(&material.name)
+        }
+    }
+
+    /// `textures` holds the GL texture of every image of the scene
+    pub fn from_gltf(material : &PbrMaterial, textures : &[GLuint]) -> Self {
+        let texture = |image : Option<usize>| image.and_then(|i| textures.get(i).copied());
+
+        let samplers = [
+            (BASE_COLOR_SAMPLER, material.base_color_texture),
+            (ROUGHNESS_MAP_SAMPLER, material.metallic_roughness_texture),
+            (METALLIC_MAP_SAMPLER, material.metallic_roughness_texture),
+            (NORMAL_MAP_SAMPLER, material.normal_texture),
+            (OCCLUSION_MAP_SAMPLER, material.occlusion_texture),
+            (EMISSIVE_MAP_SAMPLER, material.emissive_texture),
+        ];
+        let [r, g, b, a] = material.base_color_factor;
+
+        let alpha_mode = match AlphaMode::from(material.alpha_mode) {
+            AlphaMode::MASK(_) => AlphaMode::MASK(material.alpha_cutoff),
+            mode => mode
+        };
+
+        Material {
+            textures : samplers.iter()
+                .filter_map(|(sampler, image)| texture(*image).map(|t| (sampler.to_string(), t)))
+                .collect(),
+            block : MaterialBlock {
+                diffuse : [r, g, b, a],
+                emissive : material.emissive_factor,
+                metallic : material.metallic_factor,
+                roughness : material.roughness_factor,
+                ..MaterialBlock::default()
+            },
+            state : RenderState { cull : !material.double_sided, ..RenderState::default() },
+            alpha_mode,
+            pbr : true,
+            ..Material::new(&material.name)
+        }
+    }
+
+    pub fn has_normal_map(&self) -> bool {
+        self.textures.iter().any(|(sampler, _)| sampler == NORMAL_MAP_SAMPLER)
+    }
+
+    /// The permutation for a mesh with `mesh_features`. Normal mapping needs
+    /// both tangents on the mesh and a normal map on the material.
+    pub fn shader_features(&self, mesh_features : ShaderFeatures) -> ShaderFeatures {
+        ShaderFeatures {
+            normal_map : mesh_features.normal_map && self.has_normal_map(),
+            pbr : self.pbr,
+            ibl : mesh_features.ibl && self.pbr,
+            ..mesh_features
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.alpha_mode == AlphaMode::BLEND
+    }
+
+    /// `state`, with blending on and depth writes off for transparent materials
+    pub fn render_state(&self) -> RenderState {
+        RenderState {
+            blend : self.state.blend || self.is_transparent(),
+            depth_write : self.state.depth_write && !self.is_transparent(),
+            ..self.state
+        }
+    }
+
+    /// Orders draws so materials sharing textures and state end up next to each other
+    pub fn sort_key(&self) -> (Vec<GLuint>, RenderState, &str) {
+        (self.textures.iter().map(|(_, t)| *t).collect(), self.render_state(), &self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diffuse(alphas : [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_fn(2, 2, |x, y| image::Rgba([255, 255, 255, alphas[(y * 2 + x) as usize]]))
+    }
+
+    fn with_map(dissolve : f32) -> MtlMaterial {
+        let mut mtl = MtlMaterial::new("leaves");
+        mtl.dissolve = dissolve;
+        mtl.diffuse_map = Some("leaves.png".to_string());
+        mtl
+    }
+
+    #[test]
+    fn mtl_alpha_mode_follows_the_diffuse_map() {
+        let mtl = with_map(1.);
+
+        let opaque = diffuse([255; 4]);
+        let cutout = diffuse([255, 0, 0, 255]);
+        let translucent = diffuse([255, 128, 255, 255]);
+
+        assert_eq!(Material::from_mtl(&mtl, None).alpha_mode, AlphaMode::OPAQUE);
+        assert_eq!(Material::from_mtl(&mtl, Some(&opaque)).alpha_mode, AlphaMode::OPAQUE);
+        assert_eq!(Material::from_mtl(&mtl, Some(&cutout)).alpha_mode, AlphaMode::MASK(0.5));
+        assert_eq!(Material::from_mtl(&mtl, Some(&translucent)).alpha_mode, AlphaMode::BLEND);
+    }
+
+    #[test]
+    fn mtl_dissolve_blends_whatever_the_map() {
+        let cutout = diffuse([255, 0, 0, 255]);
+
+        let material = Material::from_mtl(&with_map(0.5), Some(&cutout));
+
+        assert_eq!(material.alpha_mode, AlphaMode::BLEND);
+        assert!(material.is_transparent());
+    }
+}
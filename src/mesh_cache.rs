@@ -0,0 +1,447 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, BufWriter, Seek, SeekFrom, Write}, sync::atomic::{AtomicUsize, Ordering}, time::UNIX_EPOCH};
+
+use crate::moving::BoundingBox;
+use crate::obj_parser::{obj_to_mesh, FaceLayout};
+use crate::triangles::{MaterialGroup, TriangleMesh, VertexAttribute, VertexAttributeLayout};
+
+// Binary mesh layout, all values little endian:
+//
+//   magic "RMSH", u32 version
+//   source stamp: u64 size, u64 mtime secs, u32 mtime nanos, u64 hash
+//   u32 attribute count, per attribute: u32 index, i32 size, i32 mem size, u32 type
+//   bounding box: 6 x f32 (x_min, x_max, y_min, y_max, z_min, z_max)
+//   u32 material count, per material: u32 name length, name bytes, u32 first, u32 count
+//   u64 lengths of verticies, vertex, normal and texture indicies
+//   padding up to a multiple of 4 bytes, then the four blobs in the same order
+const MAGIC : &[u8; 4] = b"RMSH";
+const VERSION : u32 = 1;
+const STAMP_OFFSET : u64 = 8;
+
+/// Identifies the source file a cache was built from
+#[derive(PartialEq, Debug)]
+pub struct SourceStamp {
+    pub size : u64,
+    pub mtime_secs : u64,
+    pub mtime_nanos : u32,
+    pub hash : u64,
+}
+
+impl SourceStamp {
+    pub fn of(path : &str) -> io::Result<Self> {
+        let (size, mtime_secs, mtime_nanos) = file_times(path)?;
+
+        Ok(SourceStamp { size, mtime_secs, mtime_nanos, hash : hash_file(path)? })
+    }
+
+    /// Cheap check on size and mtime first, falling back to the content hash
+    /// so a touched but unchanged file keeps its cache
+    pub fn check(&self, path : &str) -> SourceCheck {
+        match self.of_times(path) {
+            Ok(stamp) if stamp == *self => SourceCheck::UNCHANGED,
+            Ok(stamp) if hash_file(path).is_ok_and(|hash| hash == self.hash) => SourceCheck::TOUCHED(stamp),
+            _ => SourceCheck::CHANGED
+        }
+    }
+
+    pub fn matches(&self, path : &str) -> bool {
+        !matches!(self.check(path), SourceCheck::CHANGED)
+    }
+
+    /// Size and mtime, with the hash of this stamp so only those are compared
+    fn of_times(&self, path : &str) -> io::Result<Self> {
+        let (size, mtime_secs, mtime_nanos) = file_times(path)?;
+
+        Ok(SourceStamp { size, mtime_secs, mtime_nanos, hash : self.hash })
+    }
+}
+
+pub enum SourceCheck {
+    UNCHANGED,
+    /// Same content with a new mtime, the stamp to store instead
+    TOUCHED(SourceStamp),
+    CHANGED,
+}
+
+fn file_times(path : &str) -> io::Result<(u64, u64, u32)> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Ok((metadata.len(), mtime.as_secs(), mtime.subsec_nanos()))
+}
+
+/// 64 bit FNV-1a
+fn hash_file(path : &str) -> io::Result<u64> {
+    let content = fs::read(path)?;
+    let mut hash : u64 = 0xcbf29ce484222325;
+
+    for byte in content {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    Ok(hash)
+}
+
+pub struct MeshCache {
+    pub source : SourceStamp,
+    pub bounding_box : BoundingBox,
+    pub mesh : TriangleMesh,
+}
+
+pub fn cache_path(obj_file_path : &str) -> String {
+    format!("{}.meshcache", obj_file_path)
+}
+
+fn stamp_bytes(source : &SourceStamp) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    bytes.extend(source.size.to_le_bytes());
+    bytes.extend(source.mtime_secs.to_le_bytes());
+    bytes.extend(source.mtime_nanos.to_le_bytes());
+    bytes.extend(source.hash.to_le_bytes());
+
+    bytes
+}
+
+/// `source` should be taken before the mesh is parsed, so an edit made while
+/// parsing leaves a stamp that no longer matches rather than a stale cache
+pub fn write_mesh_cache(mesh : &TriangleMesh, source : &SourceStamp, cache_path : &str) -> io::Result<()> {
+    let bb = BoundingBox::from_mesh(mesh);
+
+    let mut header : Vec<u8> = Vec::new();
+
+    header.extend(MAGIC);
+    header.extend(VERSION.to_le_bytes());
+    header.extend(stamp_bytes(source));
+
+    let attributes = mesh.vertex_attrib_layout.attributes();
+    header.extend((attributes.len() as u32).to_le_bytes());
+    for attrib in attributes {
+        header.extend(attrib.index().to_le_bytes());
+        header.extend(attrib.size().to_le_bytes());
+        header.extend(attrib.mem_size().to_le_bytes());
+        header.extend(attrib.attrib_type().to_le_bytes());
+    }
+
+    for x in [bb.x_min, bb.x_max, bb.y_min, bb.y_max, bb.z_min, bb.z_max] {
+        header.extend(x.to_le_bytes());
+    }
+
+    header.extend((mesh.materials.len() as u32).to_le_bytes());
+    for group in &mesh.materials {
+        header.extend((group.name.len() as u32).to_le_bytes());
+        header.extend(group.name.as_bytes());
+        header.extend(group.first.to_le_bytes());
+        header.extend(group.count.to_le_bytes());
+    }
+
+    header.extend((mesh.verticies.len() as u64).to_le_bytes());
+    header.extend((mesh.vertex_indicies.len() as u64).to_le_bytes());
+    header.extend((mesh.normal_indicies.len() as u64).to_le_bytes());
+    header.extend((mesh.texture_indicies.len() as u64).to_le_bytes());
+
+    header.resize(header.len().next_multiple_of(4), 0);
+
+    // Written next to the cache and renamed over it, so a reader on another
+    // thread never sees a half written file
+    let tmp_path = format!("{}.{}.{}.tmp", cache_path, std::process::id(), NEXT_TMP.fetch_add(1, Ordering::Relaxed));
+
+    let result = (|| {
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+
+        out.write_all(&header)?;
+        for x in &mesh.verticies {
+            out.write_all(&x.to_le_bytes())?;
+        }
+        for indicies in [&mesh.vertex_indicies, &mesh.normal_indicies, &mesh.texture_indicies] {
+            for i in indicies {
+                out.write_all(&i.to_le_bytes())?;
+            }
+        }
+
+        out.flush()?;
+        drop(out);
+
+        fs::rename(&tmp_path, cache_path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// Replaces the stamp of an existing cache, for sources that were touched
+/// without changing so later loads skip hashing them again. A reader racing
+/// the write sees a stamp that does not match and rebuilds at worst.
+pub fn update_cache_stamp(cache_path : &str, source : &SourceStamp) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(cache_path)?;
+
+    file.seek(SeekFrom::Start(STAMP_OFFSET))?;
+    file.write_all(&stamp_bytes(source))
+}
+
+/// Tells apart the temporary files of concurrent writers
+static NEXT_TMP : AtomicUsize = AtomicUsize::new(0);
+
+fn invalid_data(msg : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Reader<'a> {
+    data : &'a [u8],
+    pos : usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n : usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid_data("Mesh cache is truncated"))?;
+        let bytes = &self.data[self.pos..end];
+
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    fn array<const N : usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> io::Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid_data("Mesh cache length overflows"))
+    }
+
+    fn f32_blob(&mut self, len : usize) -> io::Result<Vec<f32>> {
+        let bytes = self.bytes(len.checked_mul(4).ok_or_else(|| invalid_data("Mesh cache length overflows"))?)?;
+
+        Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    fn u32_blob(&mut self, len : usize) -> io::Result<Vec<u32>> {
+        let bytes = self.bytes(len.checked_mul(4).ok_or_else(|| invalid_data("Mesh cache length overflows"))?)?;
+
+        Ok(bytes.chunks_exact(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+}
+
+/// Reads the whole file rather than mapping it. Every blob is copied out into
+/// the mesh anyway, and a mapping faults if the file is truncated while it is
+/// read, which hot reloading and `update_cache_stamp` can cause.
+pub fn read_mesh_cache(cache_path : &str) -> io::Result<MeshCache> {
+    let data = fs::read(cache_path)?;
+    let mut r = Reader { data : &data, pos : 0 };
+
+    if r.bytes(4)? != MAGIC {
+        return Err(invalid_data("Not a mesh cache file"));
+    }
+    if r.u32()? != VERSION {
+        return Err(invalid_data("Unsupported mesh cache version"));
+    }
+
+    let source = SourceStamp {
+        size : r.u64()?,
+        mtime_secs : r.u64()?,
+        mtime_nanos : r.u32()?,
+        hash : r.u64()?,
+    };
+
+    let mut attributes = Vec::new();
+    for _ in 0..r.u32()? {
+        attributes.push(VertexAttribute::new(r.u32()?, r.i32()?, r.i32()?, r.u32()?));
+    }
+
+    let bounding_box = BoundingBox {
+        x_min : r.f32()?, x_max : r.f32()?,
+        y_min : r.f32()?, y_max : r.f32()?,
+        z_min : r.f32()?, z_max : r.f32()?,
+    };
+
+    let mut materials = Vec::new();
+    for _ in 0..r.u32()? {
+        let name_len = r.u32()? as usize;
+        let name = String::from_utf8(r.bytes(name_len)?.to_vec())
+            .map_err(|_| invalid_data("Material name is not UTF-8"))?;
+
+        materials.push(MaterialGroup { name, first : r.u32()?, count : r.u32()? });
+    }
+
+    let num_verticies = r.len()?;
+    let num_vertex_indicies = r.len()?;
+    let num_normal_indicies = r.len()?;
+    let num_texture_indicies = r.len()?;
+
+    r.bytes(r.pos.next_multiple_of(4) - r.pos)?;
+
+    let mut mesh = TriangleMesh::from_array_indicies(
+        r.f32_blob(num_verticies)?,
+        r.u32_blob(num_vertex_indicies)?,
+        r.u32_blob(num_normal_indicies)?,
+        r.u32_blob(num_texture_indicies)?,
+        VertexAttributeLayout::new(attributes)
+    );
+    mesh.materials = materials;
+
+    Ok(MeshCache { source, bounding_box, mesh })
+}
+
+/// Loads an OBJ through the cache next to it, rebuilding the cache when the
+/// OBJ or the requested layout changed
+pub fn load_obj_cached(filename : &str, face_layout : &FaceLayout) -> TriangleMesh {
+    let cache = cache_path(filename);
+
+    if let Ok(cached) = read_mesh_cache(&cache) {
+        if cached.mesh.vertex_attrib_layout == face_layout.vertex_attrib_layout() {
+            match cached.source.check(filename) {
+                SourceCheck::UNCHANGED => {
+                    println!("Loaded mesh cache {}", cache);
+                    return cached.mesh;
+                }
+                SourceCheck::TOUCHED(stamp) => {
+                    if let Err(e) = update_cache_stamp(&cache, &stamp) {
+                        println!("Could not update mesh cache {}: {}", cache, e);
+                    }
+                    println!("Loaded mesh cache {}", cache);
+                    return cached.mesh;
+                }
+                SourceCheck::CHANGED => ()
+            }
+        }
+    }
+
+    // Taken before parsing, an edit in between makes the next load rebuild
+    let stamp = SourceStamp::of(filename);
+    let mesh = obj_to_mesh(filename, face_layout);
+
+    match stamp.and_then(|stamp| write_mesh_cache(&mesh, &stamp, &cache)) {
+        Ok(()) => println!("Wrote mesh cache {}", cache),
+        Err(e) => println!("Could not write mesh cache {}: {}", cache, e)
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    const OBJ : &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+        vt 0 0\nvt 1 0\nvt 0 1\nvt 1 1\n\
+        usemtl a\nf 1/1 2/2 3/3\nusemtl b\nf 2/2 4/4 3/3\n";
+
+    fn write_source(dir : &std::path::Path) -> (String, FaceLayout, TriangleMesh) {
+        let obj = dir.join("mesh.obj").to_string_lossy().into_owned();
+        fs::write(&obj, OBJ).unwrap();
+
+        let face_layout = FaceLayout::new(Some(0), Some(1), None);
+        let mesh = obj_to_mesh(&obj, &face_layout);
+
+        (obj, face_layout, mesh)
+    }
+
+    #[test]
+    fn cache_round_trips() {
+        let dir = temp_dir("cache-round-trip");
+        let (obj, face_layout, mesh) = write_source(&dir);
+        let cache = cache_path(&obj);
+
+        write_mesh_cache(&mesh, &SourceStamp::of(&obj).unwrap(), &cache).unwrap();
+        let cached = read_mesh_cache(&cache).unwrap();
+
+        assert!(cached.source.matches(&obj));
+        assert_eq!(cached.source, SourceStamp::of(&obj).unwrap());
+        assert_eq!(cached.mesh.verticies, mesh.verticies);
+        assert_eq!(cached.mesh.vertex_indicies, mesh.vertex_indicies);
+        assert_eq!(cached.mesh.normal_indicies, mesh.normal_indicies);
+        assert_eq!(cached.mesh.texture_indicies, mesh.texture_indicies);
+        assert_eq!(cached.mesh.materials, mesh.materials);
+        assert_eq!(cached.mesh.vertex_attrib_layout, face_layout.vertex_attrib_layout());
+        assert_eq!((cached.bounding_box.x_min, cached.bounding_box.x_max), (0., 1.));
+        assert_eq!((cached.bounding_box.y_min, cached.bounding_box.y_max), (0., 1.));
+
+        // Only the cache itself is left behind
+        let leftovers : Vec<_> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stale_version_is_rejected() {
+        let dir = temp_dir("cache-stale-version");
+        let (obj, _, mesh) = write_source(&dir);
+        let cache = cache_path(&obj);
+
+        write_mesh_cache(&mesh, &SourceStamp::of(&obj).unwrap(), &cache).unwrap();
+
+        let mut data = fs::read(&cache).unwrap();
+        data[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        fs::write(&cache, data).unwrap();
+
+        let err = read_mesh_cache(&cache).err().expect("Stale cache was accepted");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changed_source_no_longer_matches() {
+        let dir = temp_dir("cache-changed-source");
+        let (obj, _, mesh) = write_source(&dir);
+        let cache = cache_path(&obj);
+
+        write_mesh_cache(&mesh, &SourceStamp::of(&obj).unwrap(), &cache).unwrap();
+        fs::write(&obj, format!("{}f 1/1 2/2 4/4\n", OBJ)).unwrap();
+
+        assert!(!read_mesh_cache(&cache).unwrap().source.matches(&obj));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn touched_source_keeps_the_cache_and_updates_the_stamp() {
+        let dir = temp_dir("cache-touched-source");
+        let (obj, face_layout, mesh) = write_source(&dir);
+        let cache = cache_path(&obj);
+
+        write_mesh_cache(&mesh, &SourceStamp::of(&obj).unwrap(), &cache).unwrap();
+
+        let mtime = fs::metadata(&obj).unwrap().modified().unwrap() + std::time::Duration::from_secs(60);
+        File::options().write(true).open(&obj).unwrap().set_modified(mtime).unwrap();
+
+        let stamp = read_mesh_cache(&cache).unwrap().source;
+        assert!(matches!(stamp.check(&obj), SourceCheck::TOUCHED(_)));
+
+        // Loading stores the new mtime, so the next load does not hash again
+        assert_eq!(load_obj_cached(&obj, &face_layout).verticies, mesh.verticies);
+        let stamp = read_mesh_cache(&cache).unwrap().source;
+        assert_eq!(stamp, SourceStamp::of(&obj).unwrap());
+        assert!(matches!(stamp.check(&obj), SourceCheck::UNCHANGED));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,524 @@
+use std::{collections::HashMap, env, fs::File, io::{self, BufWriter, Write}, path::{Component, Path, PathBuf}};
+use crate::mtl_parser::{is_map_statement, MtlMaterial};
+use crate::triangles::{face_normal, TriangleMesh, POSITION_ATTRIB, NORMAL_ATTRIB, TEXCOORD_ATTRIB, COLOR_ATTRIB};
+
+/// Writes the mesh positions as an ASCII STL file with one facet per triangle
+pub fn write_stl(mesh : &TriangleMesh, filename : &str) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(filename)?);
+
+    writeln!(out, "solid mesh")?;
+
+    for tri in 0..mesh.num_verticies() / 3 {
+        let a = mesh.position(3 * tri);
+        let b = mesh.position(3 * tri + 1);
+        let c = mesh.position(3 * tri + 2);
+        let n = face_normal(a, b, c);
+
+        writeln!(out, "  facet normal {} {} {}", n[0], n[1], n[2])?;
+        writeln!(out, "    outer loop")?;
+        for p in [a, b, c] {
+            writeln!(out, "      vertex {} {} {}", p[0], p[1], p[2])?;
+        }
+        writeln!(out, "    endloop")?;
+        writeln!(out, "  endfacet")?;
+    }
+
+    writeln!(out, "endsolid mesh")?;
+
+    out.flush()
+}
+
+/// Deduplicates attribute values by their bits, returning the unique values
+/// and the 0 based index of each vertex into them
+fn unique_values(mesh : &TriangleMesh, index : gl::types::GLuint, size : usize) -> Option<(Vec<&[f32]>, Vec<usize>)> {
+    mesh.vertex_attrib_layout.float_offset(index)?;
+
+    let mut values : Vec<&[f32]> = Vec::new();
+    let mut lookup : HashMap<Vec<u32>, usize> = HashMap::new();
+    let mut indicies = Vec::with_capacity(mesh.num_verticies());
+
+    for i in 0..mesh.num_verticies() {
+        let value = mesh.attribute(i, index, size).unwrap();
+        let key = value.iter().map(|x| x.to_bits()).collect();
+
+        let next = values.len();
+        let j = *lookup.entry(key).or_insert(next);
+        if j == next {
+            values.push(value);
+        }
+
+        indicies.push(j);
+    }
+
+    Some((values, indicies))
+}
+
+fn write_mtl(materials : &[MtlMaterial], filename : &str) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(filename)?);
+
+    for material in materials {
+        let [ar, ag, ab] = material.ambient;
+        let [dr, dg, db] = material.diffuse;
+        let [sr, sg, sb] = material.specular;
+
+        writeln!(out, "newmtl {}", material.name)?;
+        writeln!(out, "Ka {} {} {}", ar, ag, ab)?;
+        writeln!(out, "Kd {} {} {}", dr, dg, db)?;
+        writeln!(out, "Ks {} {} {}", sr, sg, sb)?;
+        writeln!(out, "Ns {}", material.shininess)?;
+        writeln!(out, "d {}", material.dissolve)?;
+        if let Some(map) = &material.diffuse_map {
+            writeln!(out, "map_Kd {}", map)?;
+        }
+        if let Some(map) = &material.normal_map {
+            writeln!(out, "map_Bump {}", map)?;
+        }
+        if material.emissive != [0., 0., 0.] {
+            let [er, eg, eb] = material.emissive;
+            writeln!(out, "Ke {} {} {}", er, eg, eb)?;
+        }
+        if let Some(map) = &material.emissive_map {
+            writeln!(out, "map_Ke {}", map)?;
+        }
+        if let Some(roughness) = material.roughness {
+            writeln!(out, "Pr {}", roughness)?;
+        }
+        if let Some(metallic) = material.metallic {
+            writeln!(out, "Pm {}", metallic)?;
+        }
+        if let Some(map) = &material.roughness_map {
+            writeln!(out, "map_Pr {}", map)?;
+        }
+        if let Some(map) = &material.metallic_map {
+            writeln!(out, "map_Pm {}", map)?;
+        }
+        for statement in &material.other {
+            writeln!(out, "{}", statement)?;
+        }
+        writeln!(out)?;
+    }
+
+    out.flush()
+}
+
+/// `path` with `.` and `..` folded away without touching the file system,
+/// so missing textures can still be pointed at
+fn normalized(path : &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => { out.pop(); }
+            c => out.push(c)
+        }
+    }
+
+    out
+}
+
+/// `path` relative to the directory `base`, both absolute
+fn relative_path(path : &Path, base : &Path) -> PathBuf {
+    let path = normalized(path);
+    let base = normalized(base);
+
+    let common = path.components().zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut out = PathBuf::new();
+    for _ in base.components().skip(common) {
+        out.push("..");
+    }
+    for component in path.components().skip(common) {
+        out.push(component);
+    }
+
+    out
+}
+
+/// Writes the mesh as OBJ with shared `v`/`vt`/`vn` records and its material
+/// groups as `usemtl`. When `materials` is not empty they are written to an
+/// MTL file next to the OBJ and referenced with `mtllib`. Their texture paths
+/// are relative to `texture_dir`, usually the directory of the MTL file they
+/// were read from, and are rewritten relative to the new MTL file.
+pub fn write_obj(mesh : &TriangleMesh, materials : &[MtlMaterial], texture_dir : &str, filename : &str) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(filename)?);
+
+    if !materials.is_empty() {
+        let mtl_path = Path::new(filename).with_extension("mtl");
+        let mtl_name = mtl_path.file_name().unwrap().to_string_lossy().into_owned();
+        let cwd = env::current_dir()?;
+        let mtl_dir = cwd.join(mtl_path.parent().unwrap_or(Path::new("")));
+        let texture_dir = cwd.join(texture_dir);
+
+        let mut materials = materials.to_vec();
+        for material in &mut materials {
+            material.map_paths(|map| relative_path(&texture_dir.join(map), &mtl_dir).to_string_lossy().into_owned());
+        }
+
+        write_mtl(&materials, &mtl_path.to_string_lossy())?;
+        writeln!(out, "mtllib {}", mtl_name)?;
+    }
+
+    let (positions, position_indicies) = unique_values(mesh, POSITION_ATTRIB, 3)
+        .expect("Mesh has no position attribute");
+    let tex_coords = unique_values(mesh, TEXCOORD_ATTRIB, 2);
+    let normals = unique_values(mesh, NORMAL_ATTRIB, 3);
+
+    for p in &positions {
+        writeln!(out, "v {} {} {}", p[0], p[1], p[2])?;
+    }
+    if let Some((values, _)) = &tex_coords {
+        for t in values {
+            writeln!(out, "vt {} {}", t[0], t[1])?;
+        }
+    }
+    if let Some((values, _)) = &normals {
+        for n in values {
+            writeln!(out, "vn {} {} {}", n[0], n[1], n[2])?;
+        }
+    }
+
+    let corner = |i : usize| match (&tex_coords, &normals) {
+        (Some((_, t)), Some((_, n))) => format!("{}/{}/{}", position_indicies[i] + 1, t[i] + 1, n[i] + 1),
+        (Some((_, t)), None) => format!("{}/{}", position_indicies[i] + 1, t[i] + 1),
+        (None, Some((_, n))) => format!("{}//{}", position_indicies[i] + 1, n[i] + 1),
+        (None, None) => format!("{}", position_indicies[i] + 1)
+    };
+
+    let mut groups = mesh.materials.iter().peekable();
+
+    for tri in 0..mesh.num_verticies() / 3 {
+        let i = 3 * tri;
+
+        if let Some(group) = groups.next_if(|group| group.first as usize <= i) {
+            writeln!(out, "usemtl {}", group.name)?;
+        }
+
+        writeln!(out, "f {} {} {}", corner(i), corner(i + 1), corner(i + 2))?;
+    }
+
+    out.flush()
+}
+
+/// Places the images side by side along the bottom of one atlas. Returns the
+/// atlas and the `[x, y, width, height]` pixel rectangle of every image.
+pub fn merge_images(images : &[&image::RgbaImage]) -> (image::RgbaImage, Vec<[u32; 4]>) {
+    let width = images.iter().map(|img| img.width()).sum();
+    let height = images.iter().map(|img| img.height()).max().unwrap_or(0);
+
+    let mut atlas = image::RgbaImage::new(width, height);
+    let mut rects = Vec::with_capacity(images.len());
+    let mut x = 0;
+
+    for img in images {
+        let y = height - img.height();
+        image::imageops::replace(&mut atlas, *img, x as i64, y as i64);
+        rects.push([x, y, img.width(), img.height()]);
+        x += img.width();
+    }
+
+    (atlas, rects)
+}
+
+/// Merges the diffuse maps of the materials into one atlas saved as `atlas_path`
+/// and moves the texture coordinates of every material group into the place of
+/// its map. Map paths are relative to `texture_dir`, coordinates outside 0..1 wrap
+/// first like the repeating sampler would. Materials with maps besides map_Kd
+/// are refused, those maps would no longer line up.
+pub fn atlas_diffuse_maps(
+    mesh : &mut TriangleMesh,
+    materials : &mut [MtlMaterial],
+    texture_dir : &str,
+    atlas_path : &str
+) -> io::Result<()> {
+    let mut paths : Vec<&str> = Vec::new();
+    for material in materials.iter() {
+        let has_other_maps = [&material.normal_map, &material.emissive_map, &material.roughness_map, &material.metallic_map]
+            .iter().any(|map| map.is_some())
+            || material.other.iter().any(|statement| statement.split_whitespace().next().is_some_and(is_map_statement));
+
+        if material.diffuse_map.is_some() && has_other_maps {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Material {} has maps besides map_Kd, only diffuse maps can be merged", material.name
+            )));
+        }
+        if let Some(map) = &material.diffuse_map {
+            if !paths.contains(&map.as_str()) {
+                paths.push(map);
+            }
+        }
+    }
+
+    let images = paths.iter()
+        .map(|map| image::open(Path::new(texture_dir).join(map)).map(|img| img.into_rgba8()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io::Error::other)?;
+    let (atlas, rects) = merge_images(&images.iter().collect::<Vec<_>>());
+    atlas.save(atlas_path).map_err(io::Error::other)?;
+
+    // UVs have their origin at the bottom left, the images sit on the bottom edge
+    let (width, height) = (atlas.width() as f32, atlas.height() as f32);
+    let wrap = |x : f32| if (0. ..=1.).contains(&x) { x } else { x.rem_euclid(1.) };
+
+    let layout = &mesh.vertex_attrib_layout;
+    if let Some(offset) = layout.float_offset(TEXCOORD_ATTRIB) {
+        let stride = layout.float_stride();
+
+        for group in &mesh.materials {
+            let rect = materials.iter()
+                .find(|material| material.name == group.name)
+                .and_then(|material| material.diffuse_map.as_deref())
+                .and_then(|map| paths.iter().position(|p| *p == map))
+                .map(|i| rects[i]);
+            let Some([x, _, w, h]) = rect else { continue };
+
+            for i in group.first as usize..(group.first + group.count) as usize {
+                let uv = &mut mesh.verticies[i * stride + offset..i * stride + offset + 2];
+                uv[0] = (x as f32 + wrap(uv[0]) * w as f32) / width;
+                uv[1] = wrap(uv[1]) * h as f32 / height;
+            }
+        }
+    }
+
+    let atlas_path = env::current_dir()?.join(atlas_path).to_string_lossy().into_owned();
+    for material in materials.iter_mut().filter(|material| material.diffuse_map.is_some()) {
+        material.diffuse_map = Some(atlas_path.clone());
+    }
+
+    Ok(())
+}
+
+/// Writes the mesh as binary little endian PLY with welded verticies.
+/// Colours are stored as uchar, PLY has no place for material groups.
+pub fn write_ply(mesh : &TriangleMesh, filename : &str) -> io::Result<()> {
+    let layout = &mesh.vertex_attrib_layout;
+
+    let attributes : Vec<(gl::types::GLuint, usize)> = [
+        (POSITION_ATTRIB, 3), (NORMAL_ATTRIB, 3), (TEXCOORD_ATTRIB, 2), (COLOR_ATTRIB, 4)
+    ].into_iter()
+        .filter(|(index, _)| layout.float_offset(*index).is_some())
+        .collect();
+    let has = |index : gl::types::GLuint| attributes.iter().any(|(i, _)| *i == index);
+
+    // Weld corners sharing every written attribute
+    let mut verticies : Vec<Vec<f32>> = Vec::new();
+    let mut lookup : HashMap<Vec<u32>, u32> = HashMap::new();
+    let mut indicies : Vec<u32> = Vec::with_capacity(mesh.num_verticies());
+
+    for i in 0..mesh.num_verticies() {
+        let vertex : Vec<f32> = attributes.iter()
+            .flat_map(|(index, size)| mesh.attribute(i, *index, *size).unwrap().iter().copied())
+            .collect();
+        let key = vertex.iter().map(|x| x.to_bits()).collect();
+
+        let next = verticies.len() as u32;
+        let j = *lookup.entry(key).or_insert(next);
+        if j == next {
+            verticies.push(vertex);
+        }
+
+        indicies.push(j);
+    }
+
+    let mut out = BufWriter::new(File::create(filename)?);
+
+    writeln!(out, "ply")?;
+    writeln!(out, "format binary_little_endian 1.0")?;
+    writeln!(out, "element vertex {}", verticies.len())?;
+    writeln!(out, "property float x\nproperty float y\nproperty float z")?;
+    if has(NORMAL_ATTRIB) {
+        writeln!(out, "property float nx\nproperty float ny\nproperty float nz")?;
+    }
+    if has(TEXCOORD_ATTRIB) {
+        writeln!(out, "property float s\nproperty float t")?;
+    }
+    if has(COLOR_ATTRIB) {
+        writeln!(out, "property uchar red\nproperty uchar green\nproperty uchar blue\nproperty uchar alpha")?;
+    }
+    writeln!(out, "element face {}", indicies.len() / 3)?;
+    writeln!(out, "property list uchar uint vertex_indices")?;
+    writeln!(out, "end_header")?;
+
+    let color_start : usize = attributes.iter()
+        .filter(|(index, _)| *index != COLOR_ATTRIB)
+        .map(|(_, size)| size)
+        .sum();
+
+    for vertex in &verticies {
+        for x in &vertex[..color_start] {
+            out.write_all(&x.to_le_bytes())?;
+        }
+        for x in &vertex[color_start..] {
+            out.write_all(&[(x.clamp(0., 1.) * 255.).round() as u8])?;
+        }
+    }
+
+    for tri in indicies.chunks_exact(3) {
+        out.write_all(&[3])?;
+        for i in tri {
+            out.write_all(&i.to_le_bytes())?;
+        }
+    }
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::obj_parser::{obj_to_mesh, read_obj, FaceLayout};
+    use crate::ply_parser::ply_to_mesh;
+    use crate::test_util::temp_dir;
+    use crate::triangles::{MaterialGroup, VertexAttribute, VertexAttributeLayout};
+
+    const OBJ : &str = "v -1 -1 0.25\nv 1 -1 0.25\nv 1 1 0.25\nv -1 1 -0.125\n\
+        vt 0 0\nvt 1 0\nvt 1 1\nvt 0 0.333\n\
+        vn 0 0 1\nvn 0.6 0 0.8\n\
+        usemtl red\nf 1/1/1 2/2/1 3/3/2\n\
+        usemtl blue\nf 1/1/1 3/3/2 4/4/2\nf 4/4/2 3/3/1 2/2/1\n";
+
+    fn full_layout() -> FaceLayout {
+        FaceLayout::new(Some(0), Some(1), Some(2))
+    }
+
+    #[test]
+    fn obj_round_trips() {
+        let dir = temp_dir("export-obj");
+        let mesh = read_obj(OBJ.as_bytes(), &full_layout()).unwrap();
+        let materials = [MtlMaterial::new("red"), MtlMaterial::new("blue")];
+        let path = dir.join("out.obj").to_string_lossy().into_owned();
+
+        write_obj(&mesh, &materials, ".", &path).unwrap();
+        let read = obj_to_mesh(&path, &full_layout());
+
+        // Positions, normals and UVs of every corner in the same order
+        assert_eq!(read.vertex_attrib_layout, mesh.vertex_attrib_layout);
+        assert_eq!(read.verticies, mesh.verticies);
+        assert_eq!(read.materials, vec![
+            MaterialGroup { name : "red".to_string(), first : 0, count : 3 },
+            MaterialGroup { name : "blue".to_string(), first : 3, count : 6 },
+        ]);
+        assert!(dir.join("out.mtl").is_file());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn obj_texture_paths_follow_the_output() {
+        let dir = temp_dir("export-obj-textures");
+        let source = dir.join("source");
+        let output = dir.join("output/nested");
+        fs::create_dir_all(source.join("tex")).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        fs::write(source.join("tex/albedo.png"), []).unwrap();
+
+        let mut material = MtlMaterial::new("red");
+        material.diffuse_map = Some("tex/albedo.png".to_string());
+        material.other.push("map_Ks -o 0.5 0.5 ./tex/../tex/albedo.png".to_string());
+
+        let mesh = read_obj(OBJ.as_bytes(), &full_layout()).unwrap();
+        let path = output.join("out.obj").to_string_lossy().into_owned();
+        write_obj(&mesh, &[material], &source.to_string_lossy(), &path).unwrap();
+
+        let mtl = fs::read_to_string(output.join("out.mtl")).unwrap();
+        assert!(mtl.contains("map_Kd ../../source/tex/albedo.png\n"), "{}", mtl);
+        assert!(mtl.contains("map_Ks -o 0.5 0.5 ../../source/tex/albedo.png\n"), "{}", mtl);
+        assert!(output.join("../../source/tex/albedo.png").is_file());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn diffuse_maps_are_merged_into_an_atlas() {
+        let dir = temp_dir("export-atlas");
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255])).save(dir.join("red.png")).unwrap();
+        image::RgbaImage::from_pixel(6, 4, image::Rgba([0, 0, 255, 255])).save(dir.join("blue.png")).unwrap();
+
+        let mut materials = vec![MtlMaterial::new("red"), MtlMaterial::new("blue")];
+        materials[0].diffuse_map = Some("red.png".to_string());
+        materials[1].diffuse_map = Some("blue.png".to_string());
+
+        let mut mesh = read_obj(OBJ.as_bytes(), &full_layout()).unwrap();
+        let atlas_path = dir.join("atlas.png");
+        atlas_diffuse_maps(&mut mesh, &mut materials, &dir.to_string_lossy(), &atlas_path.to_string_lossy()).unwrap();
+
+        // Red on the bottom left, blue next to it and twice as tall
+        let atlas = image::open(&atlas_path).unwrap().into_rgba8();
+        assert_eq!(atlas.dimensions(), (8, 4));
+        assert_eq!(atlas.get_pixel(0, 3).0, [255, 0, 0, 255]);
+        assert_eq!(atlas.get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(atlas.get_pixel(2, 0).0, [0, 0, 255, 255]);
+
+        let uv = |i : usize| mesh.attribute(i, TEXCOORD_ATTRIB, 2).unwrap().to_vec();
+        // The red triangle had (0, 0), (1, 0), (1, 1)
+        assert_eq!([uv(0), uv(1), uv(2)], [vec![0., 0.], vec![0.25, 0.], vec![0.25, 0.5]]);
+        // Blue fills the rest of the width
+        assert_eq!([uv(3), uv(4)], [vec![0.25, 0.], vec![1., 1.]]);
+
+        for material in &materials {
+            assert_eq!(material.diffuse_map.as_deref().map(Path::new), Some(atlas_path.as_path()));
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn atlases_refuse_other_maps() {
+        let mut material = MtlMaterial::new("red");
+        material.diffuse_map = Some("red.png".to_string());
+        material.other.push("bump red_bump.png".to_string());
+
+        let mut mesh = read_obj(OBJ.as_bytes(), &full_layout()).unwrap();
+        let err = atlas_diffuse_maps(&mut mesh, &mut [material], ".", "atlas.png").unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn ply_round_trips() {
+        let dir = temp_dir("export-ply");
+        let layout = VertexAttributeLayout::new(vec![
+            VertexAttribute::float(POSITION_ATTRIB, 3),
+            VertexAttribute::float(NORMAL_ATTRIB, 3),
+            VertexAttribute::float(TEXCOORD_ATTRIB, 2),
+            VertexAttribute::float(COLOR_ATTRIB, 4),
+        ]);
+        let verticies = vec![
+            -1., -1., 0.,  0., 0., 1.,  0., 0.,  1., 0., 0., 1.,
+             1., -1., 0.,  0., 0., 1.,  1., 0.,  0., 1., 0., 0.2,
+             1.,  1., 0.,  0., 0., 1.,  1., 1.,  0., 0., 1., 1.,
+             1.,  1., 0.,  0., 0., 1.,  1., 1.,  0., 0., 1., 1.,
+            -1.,  1., 0.5, 0.6, 0., 0.8,  0., 1.,  0.4, 0.4, 0.4, 1.,
+            -1., -1., 0.,  0., 0., 1.,  0., 0.,  1., 0., 0., 1.,
+        ];
+        let indicies = vec![0, 1, 2, 3, 4, 5];
+        let mesh = TriangleMesh::from_array_indicies(verticies, indicies.clone(), indicies.clone(), indicies, layout.clone());
+        let path = dir.join("out.ply").to_string_lossy().into_owned();
+
+        write_ply(&mesh, &path).unwrap();
+        let read = ply_to_mesh(&path);
+
+        assert_eq!(read.vertex_attrib_layout, layout);
+        assert_eq!(read.num_verticies(), mesh.num_verticies());
+        for i in 0..mesh.num_verticies() {
+            for (index, size) in [(POSITION_ATTRIB, 3), (NORMAL_ATTRIB, 3), (TEXCOORD_ATTRIB, 2)] {
+                assert_eq!(read.attribute(i, index, size), mesh.attribute(i, index, size));
+            }
+
+            // Colours are stored as uchar
+            let read_color = read.attribute(i, COLOR_ATTRIB, 4).unwrap();
+            let color = mesh.attribute(i, COLOR_ATTRIB, 4).unwrap();
+            for (a, b) in read_color.iter().zip(color) {
+                assert!((a - b).abs() <= 0.5 / 255., "{:?} != {:?}", read_color, color);
+            }
+        }
+        // The repeated corners are welded
+        assert_eq!(read.vertex_indicies, vec![0, 1, 2, 2, 3, 0]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
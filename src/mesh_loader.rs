@@ -0,0 +1,146 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::gltf_parser::{gltf_to_mesh, gltf_to_scene, GltfImage, PbrMaterial};
+use crate::mesh_cache::load_obj_cached;
+use crate::mtl_parser::{mtl_to_materials, MtlMaterial};
+use crate::obj_parser::{obj_material_libs, FaceLayout};
+use crate::ply_parser::ply_to_mesh;
+use crate::stl_parser::stl_to_mesh;
+use crate::texture::Texture;
+use crate::triangles::TriangleMesh;
+
+/// Materials that come with a mesh file, with their images decoded so only
+/// the upload is left for the GL thread
+pub enum MeshMaterials {
+    /// Materials of the `mtllib` files, map paths are relative to the working
+    /// directory and key the decoded `images`
+    Mtl {
+        libs : Vec<String>,
+        materials : Vec<MtlMaterial>,
+        images : HashMap<String, image::RgbaImage>,
+    },
+    Gltf {
+        materials : Vec<PbrMaterial>,
+        images : Vec<GltfImage>,
+    },
+}
+
+impl MeshMaterials {
+    /// Files besides the mesh the materials were read from
+    pub fn files(&self) -> &[String] {
+        match self {
+            MeshMaterials::Mtl { libs, .. } => libs,
+            MeshMaterials::Gltf { .. } => &[]
+        }
+    }
+}
+
+fn extension(filename : &str) -> Option<String> {
+    Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
+
+/// Picks the loader from the file extension, `face_layout` only applies to OBJ files
+pub fn load_mesh(filename : &str, face_layout : &FaceLayout) -> TriangleMesh {
+    match extension(filename).as_deref() {
+        Some("gltf") | Some("glb") => gltf_to_mesh(filename),
+        Some("ply") => ply_to_mesh(filename),
+        Some("stl") => stl_to_mesh(filename),
+        _ => load_obj_cached(filename, face_layout)
+    }
+}
+
+fn load_mtl_materials(obj_filename : &str) -> Option<MeshMaterials> {
+    let libs : Vec<String> = obj_material_libs(obj_filename).ok()?
+        .into_iter()
+        .filter(|lib| Path::new(lib).is_file())
+        .collect();
+
+    if libs.is_empty() {
+        return None;
+    }
+
+    let mut materials = Vec::new();
+    for lib in &libs {
+        let lib_dir = Path::new(lib).parent().unwrap_or(Path::new(""));
+
+        for mut material in mtl_to_materials(lib) {
+            material.map_paths(|map| lib_dir.join(map).to_string_lossy().into_owned());
+            materials.push(material);
+        }
+    }
+
+    let mut images = HashMap::new();
+    for material in &materials {
+        let maps = [
+            &material.diffuse_map, &material.normal_map, &material.emissive_map,
+            &material.roughness_map, &material.metallic_map
+        ];
+
+        for map in maps.into_iter().flatten() {
+            images.entry(map.clone()).or_insert_with(|| Texture::decode(map));
+        }
+    }
+
+    Some(MeshMaterials::Mtl { libs, materials, images })
+}
+
+/// Like `load_mesh`, also reading the materials of OBJ and glTF files
+pub fn load_mesh_with_materials(filename : &str, face_layout : &FaceLayout) -> (TriangleMesh, Option<MeshMaterials>) {
+    match extension(filename).as_deref() {
+        Some("gltf") | Some("glb") => {
+            let scene = gltf_to_scene(filename);
+            let materials = MeshMaterials::Gltf { materials : scene.materials, images : scene.images };
+
+            (scene.mesh, Some(materials))
+        }
+        Some("ply") | Some("stl") => (load_mesh(filename, face_layout), None),
+        _ => (load_obj_cached(filename, face_layout), load_mtl_materials(filename))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn obj_materials_are_loaded_with_their_maps() {
+        let dir = temp_dir("mesh-loader");
+        fs::create_dir_all(dir.join("materials/maps")).unwrap();
+
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]))
+            .save(dir.join("materials/maps/red.png")).unwrap();
+        fs::write(dir.join("materials/quad.mtl"), "newmtl red\nKd 1 0 0\nmap_Kd maps/red.png\n").unwrap();
+        fs::write(dir.join("quad.obj"), "mtllib materials/quad.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n").unwrap();
+
+        let obj = dir.join("quad.obj").to_string_lossy().into_owned();
+        let (mesh, materials) = load_mesh_with_materials(&obj, &FaceLayout::new(Some(0), None, None));
+
+        assert_eq!(mesh.num_verticies(), 3);
+        let Some(MeshMaterials::Mtl { libs, materials, images }) = materials else {
+            panic!("No MTL materials loaded");
+        };
+
+        let map = dir.join("materials/maps/red.png").to_string_lossy().into_owned();
+        assert_eq!(libs, vec![dir.join("materials/quad.mtl").to_string_lossy().into_owned()]);
+        assert_eq!(materials.len(), 1);
+        assert_eq!(materials[0].diffuse_map.as_ref(), Some(&map));
+        assert_eq!(images[&map].get_pixel(1, 1), &image::Rgba([255, 0, 0, 255]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn gltf_materials_are_loaded() {
+        let quad = concat!(env!("CARGO_MANIFEST_DIR"), "/objects/quad.gltf");
+        let (mesh, materials) = load_mesh_with_materials(quad, &FaceLayout::new(Some(0), None, None));
+
+        assert_eq!(mesh.num_verticies(), 6);
+        assert!(matches!(materials, Some(MeshMaterials::Gltf { materials, images }) if materials.len() == 1 && images.len() == 1));
+    }
+}
@@ -1,40 +1,74 @@
-use std::fs;
+use std::{fs::File, io::{BufRead, BufReader}};
 
+use crate::obj_parser::parse_f32;
 use crate::opengl_handler::CameraHandler;
-
-struct BoundingBox {
-    x_min : f32,
-    x_max : f32,
-    y_min : f32,
-    y_max : f32,
-    z_min : f32,
-    z_max : f32,
+use crate::triangles::TriangleMesh;
+
+pub struct BoundingBox {
+    pub x_min : f32,
+    pub x_max : f32,
+    pub y_min : f32,
+    pub y_max : f32,
+    pub z_min : f32,
+    pub z_max : f32,
 }
 
 impl BoundingBox {
-    fn mean_x(&self) -> f32 {
+    pub fn from_mesh(mesh : &TriangleMesh) -> Self {
+        let mut bb = BoundingBox {
+            x_min : f32::MAX, x_max : f32::MIN,
+            y_min : f32::MAX, y_max : f32::MIN,
+            z_min : f32::MAX, z_max : f32::MIN,
+        };
+
+        for i in 0..mesh.num_verticies() {
+            let [x, y, z] = mesh.position(i);
+
+            bb.x_min = x.min(bb.x_min);
+            bb.x_max = x.max(bb.x_max);
+            bb.y_min = y.min(bb.y_min);
+            bb.y_max = y.max(bb.y_max);
+            bb.z_min = z.min(bb.z_min);
+            bb.z_max = z.max(bb.z_max);
+        }
+
+        bb
+    }
+
+    pub fn mean_x(&self) -> f32 {
         (self.x_min + self.x_max) / 2.
     }
-    fn mean_y(&self) -> f32 {
+    pub fn mean_y(&self) -> f32 {
         (self.y_min + self.y_max) / 2.
     }
-    fn mean_z(&self) -> f32 {
+    pub fn mean_z(&self) -> f32 {
         (self.z_min + self.z_max) / 2.
     }
-    fn max_dim(&self) -> f32 {
+    pub fn max_dim(&self) -> f32 {
         let dx = self.x_max - self.x_min;
         let dy = self.y_max - self.y_min;
         let dz = self.z_max - self.z_min;
 
         dx.max(dy).max(dz)
     }
-}
+    pub fn center(&self) -> [f32; 3] {
+        [self.mean_x(), self.mean_y(), self.mean_z()]
+    }
+    /// Radius of the sphere around `center` holding the box
+    pub fn radius(&self) -> f32 {
+        let dx = self.x_max - self.x_min;
+        let dy = self.y_max - self.y_min;
+        let dz = self.z_max - self.z_min;
 
-fn get_bounding_box(obj_file_path : &str) -> BoundingBox {
-    let content = fs::read_to_string(obj_file_path)
-        .expect(&format!("Could not read file: {}", obj_file_path));
+        (dx * dx + dy * dy + dz * dz).sqrt() / 2.
+    }
+}
 
-    let rows = content.split("\n");
+pub fn get_bounding_box(obj_file_path : &str) -> BoundingBox {
+    let file = File::open(obj_file_path)
+        .unwrap_or_else(|_| panic!("Could not read file: {}", obj_file_path));
+    let mut reader = BufReader::with_capacity(1 << 16, file);
+    let mut row = String::new();
 
     let mut x_min = f32::MAX;
     let mut x_max = f32::MIN;
@@ -43,22 +77,12 @@ fn get_bounding_box(obj_file_path : &str) -> BoundingBox {
     let mut z_min = f32::MAX;
     let mut z_max = f32::MIN;
 
-    let mut x_tot = 0.;
-    let mut y_tot = 0.;
-    let mut z_tot = 0.;
-    let mut n = 0.;
-
-    for elm in rows {
-        let items : Vec<&str> = elm.split_whitespace().collect();
-
-        if items.len() == 0 {
-            continue;
-        }
+    while reader.read_line(&mut row).unwrap_or(0) > 0 {
+        let mut items = row.split_ascii_whitespace();
 
-        if items[0] == "v" {
-            let x = items[1].parse::<f32>().unwrap();
-            let y = items[2].parse::<f32>().unwrap();
-            let z = items[3].parse::<f32>().unwrap();
+        if items.next() == Some("v") {
+            let mut coord = || items.next().and_then(parse_f32).unwrap();
+            let (x, y, z) = (coord(), coord(), coord());
 
             x_min = x.min(x_min);
             x_max = x.max(x_max);
@@ -66,29 +90,28 @@ fn get_bounding_box(obj_file_path : &str) -> BoundingBox {
             y_max = y.max(y_max);
             z_min = z.min(z_min);
             z_max = z.max(z_max);
-
-            x_tot += x;
-            y_tot += y;
-            z_tot += z;
-            n += 1.;
         }
-    }
 
-    println!("{}, {}, {}", y_min, y_max, y_tot / n);
+        row.clear();
+    }
 
     BoundingBox{x_min, x_max, y_min, y_max, z_min, z_max}
 }
 
-pub fn center_obj_fn(obj_file_path : &str, x_adjust : f32, y_adjust : f32, z_adjust : f32) -> impl Fn(&mut CameraHandler) -> () {
-    let bounding_box = get_bounding_box(obj_file_path);
+pub fn center_obj_fn(obj_file_path : &str, x_adjust : f32, y_adjust : f32, z_adjust : f32) -> impl Fn(&mut CameraHandler) {
+    center_bounding_box_fn(get_bounding_box(obj_file_path), x_adjust, y_adjust, z_adjust)
+}
 
+pub fn center_mesh_fn(mesh : &TriangleMesh, x_adjust : f32, y_adjust : f32, z_adjust : f32) -> impl Fn(&mut CameraHandler) {
+    center_bounding_box_fn(BoundingBox::from_mesh(mesh), x_adjust, y_adjust, z_adjust)
+}
+
+pub fn center_bounding_box_fn(bounding_box : BoundingBox, x_adjust : f32, y_adjust : f32, z_adjust : f32) -> impl Fn(&mut CameraHandler) {
     let scaling = 2. / bounding_box.max_dim();
     let dx = -bounding_box.mean_x() / scaling;
     let dy = -bounding_box.mean_y() / scaling;
     let dz = -bounding_box.mean_z() / scaling;
 
-    println!("{}, {}, {}, {}", scaling, dx, dy, dz);
-
     move |camera_handler| {
         camera_handler.translate(dx + x_adjust, dy + y_adjust, dz + z_adjust);
         camera_handler.scale(scaling, scaling, scaling)
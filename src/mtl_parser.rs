@@ -0,0 +1,141 @@
+use std::fs;
+
+/// A `newmtl` block of an MTL file
+#[derive(Clone, Debug)]
+pub struct MtlMaterial {
+    pub name : String,
+    pub ambient : [f32; 3],
+    pub diffuse : [f32; 3],
+    pub specular : [f32; 3],
+    pub shininess : f32,
+    /// `d`, or `1 - Tr`
+    pub dissolve : f32,
+    pub diffuse_map : Option<String>,
+    /// `map_Bump`, `bump` or `norm`, a tangent space normal map
+    pub normal_map : Option<String>,
+    /// `Ke`
+    pub emissive : [f32; 3],
+    pub emissive_map : Option<String>,
+    /// PBR extension, `Pr` and `Pm`
+    pub roughness : Option<f32>,
+    pub metallic : Option<f32>,
+    pub roughness_map : Option<String>,
+    pub metallic_map : Option<String>,
+    /// Statements without a field above, kept verbatim so they survive a rewrite
+    pub other : Vec<String>,
+}
+
+impl MtlMaterial {
+    pub fn new(name : &str) -> Self {
+        MtlMaterial {
+            name : name.to_string(),
+            ambient : [1., 1., 1.],
+            diffuse : [1., 1., 1.],
+            specular : [0., 0., 0.],
+            shininess : 0.,
+            dissolve : 1.,
+            diffuse_map : None,
+            normal_map : None,
+            emissive : [0., 0., 0.],
+            emissive_map : None,
+            roughness : None,
+            metallic : None,
+            roughness_map : None,
+            metallic_map : None,
+            other : Vec::new()
+        }
+    }
+
+    /// Replaces every texture path with `f(path)`, the map fields above as well
+    /// as map statements kept in `other`
+    pub fn map_paths(&mut self, mut f : impl FnMut(&str) -> String) {
+        let fields = [
+            &mut self.diffuse_map, &mut self.normal_map, &mut self.emissive_map,
+            &mut self.roughness_map, &mut self.metallic_map
+        ];
+
+        for map in fields.into_iter().flatten() {
+            *map = f(map);
+        }
+
+        for statement in &mut self.other {
+            let elms : Vec<&str> = statement.split_whitespace().collect();
+
+            if elms.len() > 1 && is_map_statement(elms[0]) {
+                let path = f(elms[elms.len() - 1]);
+                *statement = format!("{} {}", elms[..elms.len() - 1].join(" "), path);
+            }
+        }
+    }
+
+    /// Whether the material uses the PBR extension
+    pub fn is_pbr(&self) -> bool {
+        self.roughness.is_some() || self.metallic.is_some() ||
+            self.roughness_map.is_some() || self.metallic_map.is_some()
+    }
+}
+
+/// Statements ending in a texture path, e.g. `map_Ks -o 0 0 spec.png` or `disp height.png`
+pub(crate) fn is_map_statement(keyword : &str) -> bool {
+    keyword.starts_with("map_") || matches!(keyword, "bump" | "norm" | "disp" | "decal" | "refl")
+}
+
+fn vec3(elms : &[&str]) -> [f32; 3] {
+    let x = |i : usize| elms.get(i).and_then(|x| x.parse::<f32>().ok());
+
+    match (x(1), x(2), x(3)) {
+        (Some(r), Some(g), Some(b)) => [r, g, b],
+        // A single value applies to all channels
+        (Some(r), _, _) => [r, r, r],
+        _ => [0., 0., 0.]
+    }
+}
+
+fn scalar(elms : &[&str]) -> f32 {
+    elms.get(1).and_then(|x| x.parse::<f32>().ok()).unwrap_or(0.)
+}
+
+pub fn parse_mtl(content : &str) -> Vec<MtlMaterial> {
+    let mut materials : Vec<MtlMaterial> = Vec::new();
+
+    for row in content.split("\n") {
+        let elms : Vec<&str> = row.split_whitespace().collect();
+
+        if elms.is_empty() || elms[0].starts_with('#') {continue;}
+
+        if elms[0] == "newmtl" {
+            materials.push(MtlMaterial::new(&elms[1..].join(" ")));
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {continue;};
+
+        match elms[0] {
+            "Ka" => material.ambient = vec3(&elms),
+            "Kd" => material.diffuse = vec3(&elms),
+            "Ks" => material.specular = vec3(&elms),
+            "Ns" => material.shininess = scalar(&elms),
+            "d" => material.dissolve = scalar(&elms),
+            "Tr" => material.dissolve = 1. - scalar(&elms),
+            // Texture options come before the path, e.g. `map_Kd -s 1 1 1 tex.png`
+            "map_Kd" if elms.len() > 1 => material.diffuse_map = Some(elms[elms.len() - 1].to_string()),
+            "map_Bump" | "map_bump" | "bump" | "norm" if elms.len() > 1 => material.normal_map = Some(elms[elms.len() - 1].to_string()),
+            "Ke" => material.emissive = vec3(&elms),
+            "map_Ke" if elms.len() > 1 => material.emissive_map = Some(elms[elms.len() - 1].to_string()),
+            "Pr" => material.roughness = Some(scalar(&elms)),
+            "Pm" => material.metallic = Some(scalar(&elms)),
+            "map_Pr" if elms.len() > 1 => material.roughness_map = Some(elms[elms.len() - 1].to_string()),
+            "map_Pm" if elms.len() > 1 => material.metallic_map = Some(elms[elms.len() - 1].to_string()),
+            _ => material.other.push(row.trim().to_string())
+        }
+    }
+
+    materials
+}
+
+pub fn mtl_to_materials(filename : &str) -> Vec<MtlMaterial> {
+    let content = fs::read_to_string(filename)
+        .unwrap_or_else(|_| panic!("Could not read file: {}", filename));
+
+    parse_mtl(&content)
+}
@@ -0,0 +1,112 @@
+use gl::types::{GLenum, GLuint};
+
+/// Most samples per pixel the driver supports for renderbuffers
+pub fn max_samples() -> i32 {
+    let mut samples = 0;
+    unsafe { gl::GetIntegerv(gl::MAX_SAMPLES, &mut samples) };
+
+    samples
+}
+
+/// Errors unless `samples` is between 0 and `max_samples`
+pub fn check_samples(samples : i32) -> Result<(), String> {
+    let max = max_samples();
+
+    if !(0..=max).contains(&samples) {
+        return Err(format!("Cannot multisample with {} samples per pixel, the driver supports 0 to {}", samples, max));
+    }
+
+    Ok(())
+}
+
+/// Multisampled colour and depth renderbuffers. Drawn into like a
+/// framebuffer, then resolved into a single sampled one before it is read.
+pub struct MultisampleTarget {
+    fbo : GLuint,
+    color : GLuint,
+    depth : GLuint,
+    samples : i32,
+    width : i32,
+    height : i32,
+}
+
+impl MultisampleTarget {
+    /// `samples` above `max_samples` is an error
+    pub fn new(width : i32, height : i32, samples : i32, color_format : GLenum) -> Result<Self, String> {
+        check_samples(samples)?;
+        let samples = samples.max(1);
+        let mut fbo = 0;
+        let mut renderbuffers = [0; 2];
+
+        unsafe {
+            gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[0]);
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, color_format, width, height);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[1]);
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, gl::DEPTH24_STENCIL8, width, height);
+
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, renderbuffers[0]);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, renderbuffers[1]);
+        }
+
+        let target = MultisampleTarget { fbo, color : renderbuffers[0], depth : renderbuffers[1], samples, width, height };
+
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) };
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Multisampled framebuffer with {} samples is incomplete: 0x{:X}", samples, status));
+        }
+
+        Ok(target)
+    }
+
+    pub fn fbo(&self) -> GLuint {
+        self.fbo
+    }
+
+    /// Samples per pixel, at least 1
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Averages the samples of the colour into `target_fbo`, which must have the same size
+    pub fn resolve(&self, target_fbo : GLuint) {
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_fbo);
+            gl::BlitFramebuffer(
+                0, 0, self.width, self.height,
+                0, 0, self.width, self.height,
+                gl::COLOR_BUFFER_BIT, gl::NEAREST
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target_fbo);
+        }
+    }
+}
+
+impl Drop for MultisampleTarget {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteRenderbuffers(2, [self.color, self.depth].as_ptr());
+        }
+    }
+}
+
+/// Turns multisampled rasterization on or off for the offscreen targets
+pub fn set_multisample_rasterization(enabled : bool) {
+    unsafe {
+        if enabled {
+            gl::Enable(gl::MULTISAMPLE);
+        } else {
+            gl::Disable(gl::MULTISAMPLE);
+        }
+    }
+}
@@ -1,13 +1,18 @@
-use std::{collections::HashMap, fs};
-use crate::triangles::{TriangleMesh, VertexAttribute, VertexAttributeLayout};
+use std::{collections::HashMap, fmt, fs::{self, File}, io::{self, BufRead, BufReader}, path::Path, thread};
 
-#[derive(Eq, Hash, PartialEq)]
+use crate::triangles::{
+    MaterialGroup, TriangleMesh, VertexAttribute, VertexAttributeLayout,
+    POSITION_ATTRIB, NORMAL_ATTRIB, TEXCOORD_ATTRIB
+};
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub enum ObjType {
     VERTEX,
     NORMAL,
     TEXTURE
 }
 
+#[derive(Clone)]
 pub struct FaceLayout {
     map : HashMap<ObjType, usize>
 }
@@ -29,6 +34,40 @@ impl FaceLayout {
         FaceLayout { map }
     }
 
+    /// Layout of a face record, e.g. `f 1/2/3 ...` or `f 1//3 ...`
+    fn of_face(row : &str) -> Option<Self> {
+        let mut elms = row.split_whitespace();
+
+        if elms.next() != Some("f") {
+            return None;
+        }
+
+        let parts : Vec<&str> = elms.next()?.split("/").collect();
+        let has = |i : usize| parts.get(i).is_some_and(|x| !x.is_empty());
+
+        Some(FaceLayout::new(
+            Some(0),
+            if has(1) {Some(1)} else {None},
+            if has(2) {Some(2)} else {None}
+        ))
+    }
+
+    /// Guesses the layout from the first face record, reading no further
+    pub fn detect(filename : &str) -> Self {
+        let mut layout = None;
+
+        for_each_row(filename, |row| {
+            layout = FaceLayout::of_face(row);
+            layout.is_none()
+        }).unwrap_or_else(|e| panic!("Could not read file: {}: {}", filename, e));
+
+        layout.unwrap_or_else(|| FaceLayout::new(Some(0), None, None))
+    }
+
+    pub fn contains(&self, obj_type : ObjType) -> bool {
+        self.map.contains_key(&obj_type)
+    }
+
     pub fn vertex_attrib_layout(&self) -> VertexAttributeLayout {
         let vec3_size = 3 * std::mem::size_of::<f32>() as i32;
         let vec2_size = 2 * std::mem::size_of::<f32>() as i32;
@@ -36,133 +75,740 @@ impl FaceLayout {
         let mut v = Vec::new();
 
         if self.map.contains_key(&ObjType::VERTEX) {
-            v.push(VertexAttribute::new(0, 3, vec3_size, gl::FLOAT));
+            v.push(VertexAttribute::new(POSITION_ATTRIB, 3, vec3_size, gl::FLOAT));
         }
         if self.map.contains_key(&ObjType::NORMAL) {
-            v.push(VertexAttribute::new(1, 3, vec3_size, gl::FLOAT));
+            v.push(VertexAttribute::new(NORMAL_ATTRIB, 3, vec3_size, gl::FLOAT));
         }
         if self.map.contains_key(&ObjType::TEXTURE) {
-            v.push(VertexAttribute::new(2, 2, vec2_size, gl::FLOAT));
+            v.push(VertexAttribute::new(TEXCOORD_ATTRIB, 2, vec2_size, gl::FLOAT));
         }
 
         VertexAttributeLayout::new(v)
     }
 
-    fn update_indicies(&self, verts : &mut Vec<u32>, norms : &mut Vec<u32>, tex : &mut Vec<u32>, indicies : &Vec<u32>) {
-        if let Some(indx) = self.map.get(&ObjType::VERTEX) {
-            verts.push(indicies[*indx])
+    /// Position of the vertex, normal and texture index in a face element
+    fn slots(&self) -> [Option<usize>; 3] {
+        [
+            self.map.get(&ObjType::VERTEX).copied(),
+            self.map.get(&ObjType::NORMAL).copied(),
+            self.map.get(&ObjType::TEXTURE).copied()
+        ]
+    }
+}
+
+impl fmt::Display for FaceLayout {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        if self.contains(ObjType::VERTEX) {
+            parts.push("v");
+        }
+        if self.contains(ObjType::TEXTURE) {
+            parts.push("vt");
+        }
+        if self.contains(ObjType::NORMAL) {
+            parts.push("vn");
+        }
+
+        write!(f, "{}", parts.join("/"))
+    }
+}
+
+const POW10 : [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11,
+    1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20, 1e21, 1e22
+];
+
+/// Float parser for the plain decimal numbers OBJ files are made of.
+/// Mantissas of up to 15 digits with small exponents are computed directly,
+/// everything else (inf, nan, long mantissas, f32 ties) goes through `str::parse`.
+pub fn parse_f32(s : &str) -> Option<f32> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    let negative = match bytes.first() {
+        Some(b'-') => { i += 1; true }
+        Some(b'+') => { i += 1; false }
+        _ => false
+    };
+
+    let mut mantissa : u64 = 0;
+    let mut digits = 0;
+    let mut exponent : i32 = 0;
+    let mut any_digit = false;
+
+    while let Some(d) = bytes.get(i).filter(|b| b.is_ascii_digit()) {
+        mantissa = mantissa.wrapping_mul(10).wrapping_add((d - b'0') as u64);
+        if mantissa != 0 { digits += 1; }
+        any_digit = true;
+        i += 1;
+    }
+
+    if bytes.get(i) == Some(&b'.') {
+        i += 1;
+        while let Some(d) = bytes.get(i).filter(|b| b.is_ascii_digit()) {
+            mantissa = mantissa.wrapping_mul(10).wrapping_add((d - b'0') as u64);
+            if mantissa != 0 { digits += 1; }
+            exponent -= 1;
+            any_digit = true;
+            i += 1;
+        }
+    }
+
+    if matches!(bytes.get(i), Some(b'e') | Some(b'E')) {
+        i += 1;
+        let exp_negative = match bytes.get(i) {
+            Some(b'-') => { i += 1; true }
+            Some(b'+') => { i += 1; false }
+            _ => false
+        };
+
+        let mut exp : i32 = 0;
+        let start = i;
+        while let Some(d) = bytes.get(i).filter(|b| b.is_ascii_digit()) {
+            exp = exp.saturating_mul(10).saturating_add((d - b'0') as i32);
+            i += 1;
+        }
+        if i == start {
+            return s.parse::<f32>().ok();
+        }
+
+        exponent = exponent.saturating_add(if exp_negative { -exp } else { exp });
+    }
+
+    if !any_digit || i != bytes.len() || digits > 15 || !(-22..=22).contains(&exponent) {
+        return s.parse::<f32>().ok();
+    }
+
+    let value = if exponent < 0 {
+        mantissa as f64 / POW10[(-exponent) as usize]
+    } else {
+        mantissa as f64 * POW10[exponent as usize]
+    };
+
+    // The f64 result is correctly rounded, rounding it again to f32 only
+    // differs from `str::parse` when it lands exactly halfway between two f32
+    if value.to_bits() & 0x1FFF_FFFF == 0x1000_0000 {
+        return s.parse::<f32>().ok();
+    }
+
+    Some(if negative { -value as f32 } else { value as f32 })
+}
+
+fn parse_coordinate<const N : usize>(elms : &mut std::str::SplitAsciiWhitespace) -> [f32; N] {
+    let mut v = [0.; N];
+
+    for x in v.iter_mut() {
+        *x = elms.next()
+            .and_then(parse_f32)
+            .expect("Invalid coordinate in OBJ file");
+    }
+
+    v
+}
+
+/// Records of an OBJ file with the face elements split up per `FaceLayout`
+struct ObjRecords {
+    slots : [Option<usize>; 3],
+    verts : Vec<[f32; 3]>,
+    norms : Vec<[f32; 3]>,
+    tex : Vec<[f32; 2]>,
+    vertex_indicies : Vec<u32>,
+    norm_indicies : Vec<u32>,
+    tex_indicies : Vec<u32>,
+    materials : Vec<MaterialGroup>,
+}
+
+impl ObjRecords {
+    fn new(face_layout : &FaceLayout) -> Self {
+        ObjRecords {
+            slots : face_layout.slots(),
+            verts : Vec::new(),
+            norms : Vec::new(),
+            tex : Vec::new(),
+            vertex_indicies : Vec::new(),
+            norm_indicies : Vec::new(),
+            tex_indicies : Vec::new(),
+            materials : Vec::new(),
         }
-        if let Some(indx) = self.map.get(&ObjType::NORMAL) {
-            norms.push(indicies[*indx])
+    }
+
+    fn parse_line(&mut self, row : &str) {
+        let mut elms = row.split_ascii_whitespace();
+
+        match elms.next() {
+            Some("v") => self.verts.push(parse_coordinate(&mut elms)),
+            Some("vn") => self.norms.push(parse_coordinate(&mut elms)),
+            Some("vt") => self.tex.push(parse_coordinate(&mut elms)),
+            Some("usemtl") => {
+                if let Some(name) = elms.next() {
+                    self.materials.push(MaterialGroup {
+                        name : name.to_string(),
+                        first : self.vertex_indicies.len() as u32,
+                        count : 0
+                    });
+                }
+            }
+            Some("f") => {
+                // Only the first three elements (a/b/c) are used
+                let mut face = [""; 3];
+                let mut n = 0;
+                for (f, slot) in elms.zip(face.iter_mut()) {
+                    *slot = f;
+                    n += 1;
+                }
+
+                // A partial triangle would shift every following one
+                if n < 3 {
+                    return;
+                }
+
+                for f in face {
+                    // indicies = [a, b, c]
+                    let mut indicies = [0u32; 3];
+                    for (x, indx) in f.split('/').zip(indicies.iter_mut()) {
+                        *indx = x.parse::<u32>().map_or(0, |n| n.saturating_sub(1));
+                    }
+
+                    let [vertex, normal, texture] = self.slots;
+                    if let Some(i) = vertex {
+                        self.vertex_indicies.push(indicies[i]);
+                    }
+                    if let Some(i) = normal {
+                        self.norm_indicies.push(indicies[i]);
+                    }
+                    if let Some(i) = texture {
+                        self.tex_indicies.push(indicies[i]);
+                    }
+                }
+            }
+            _ => ()
         }
-        if let Some(indx) = self.map.get(&ObjType::TEXTURE) {
-            tex.push(indicies[*indx])
+    }
+
+    /// Appends the records of the chunk following this one. Face indicies in
+    /// OBJ are global so only the material groups need offsetting.
+    fn append(&mut self, mut other : ObjRecords) {
+        let offset = self.vertex_indicies.len() as u32;
+
+        for group in &mut other.materials {
+            group.first += offset;
         }
+
+        self.verts.append(&mut other.verts);
+        self.norms.append(&mut other.norms);
+        self.tex.append(&mut other.tex);
+        self.vertex_indicies.append(&mut other.vertex_indicies);
+        self.norm_indicies.append(&mut other.norm_indicies);
+        self.tex_indicies.append(&mut other.tex_indicies);
+        self.materials.append(&mut other.materials);
     }
 
-    fn make_verticies(&self, 
-        verts : &mut Vec<Vec<f32>>, norms : &mut Vec<Vec<f32>>, tex : &mut Vec<Vec<f32>>,
-        vert_indicies : &Vec<u32>, norm_indicies : &Vec<u32>, tex_indicies : &Vec<u32>
-    ) -> Vec<f32> {
-        let mut verticies = Vec::new();
+    fn into_mesh(self, face_layout : &FaceLayout) -> TriangleMesh {
+        let vertex_attrib_layout = face_layout.vertex_attrib_layout();
+        let [vertex, normal, texture] = self.slots.map(|slot| slot.is_some());
 
-        for i in 0..vert_indicies.len() {
-            if self.map.contains_key(&ObjType::VERTEX) {
-                verticies.extend(verts[vert_indicies[i] as usize].clone());
+        let mut verticies = Vec::with_capacity(self.vertex_indicies.len() * vertex_attrib_layout.float_stride());
+
+        for i in 0..self.vertex_indicies.len() {
+            if vertex {
+                verticies.extend_from_slice(&self.verts[self.vertex_indicies[i] as usize]);
             }
-            if self.map.contains_key(&ObjType::NORMAL) {
-                verticies.extend(norms[norm_indicies[i] as usize].clone());
+            if normal {
+                verticies.extend_from_slice(&self.norms[self.norm_indicies[i] as usize]);
             }
-            if self.map.contains_key(&ObjType::TEXTURE) {
-                verticies.extend(tex[tex_indicies[i] as usize].clone());
+            if texture {
+                verticies.extend_from_slice(&self.tex[self.tex_indicies[i] as usize]);
             }
         }
 
-        verticies
+        // Each group runs until the next `usemtl`
+        let mut materials = self.materials;
+        let mut end = self.vertex_indicies.len() as u32;
+        for group in materials.iter_mut().rev() {
+            group.count = end - group.first;
+            end = group.first;
+        }
+        materials.retain(|group| group.count > 0);
+
+        let mut mesh = TriangleMesh::from_array_indicies(
+            verticies,
+            self.vertex_indicies,
+            self.norm_indicies,
+            self.tex_indicies,
+            vertex_attrib_layout
+        );
+        mesh.materials = materials;
+
+        mesh
+    }
+}
+
+/// Parses OBJ records line by line from any reader
+pub fn read_obj<R : BufRead>(mut reader : R, face_layout : &FaceLayout) -> io::Result<TriangleMesh> {
+    let mut records = ObjRecords::new(face_layout);
+    let mut row = String::new();
+
+    while reader.read_line(&mut row)? > 0 {
+        records.parse_line(&row);
+        row.clear();
+    }
+
+    Ok(records.into_mesh(face_layout))
+}
+
+/// Splits `data` into about `n` chunks, each ending at a line break
+fn line_chunks(data : &[u8], n : usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::with_capacity(n);
+    let mut start = 0;
+
+    for i in 1..=n {
+        let target = (data.len() * i / n).max(start);
+        let end = match data[target..].iter().position(|b| *b == b'\n') {
+            Some(p) if i < n => target + p + 1,
+            _ => data.len()
+        };
+
+        if end > start {
+            chunks.push(&data[start..end]);
+        }
+        start = end;
     }
+
+    chunks
+}
+
+/// Parses chunks of the file on `threads` threads and merges them in order,
+/// producing the same mesh as `read_obj`. The file is read into memory rather
+/// than mapped, a mapping would fault if the file got truncated while parsing,
+/// which hot reloading does all the time.
+pub fn obj_to_mesh_parallel(filename : &str, face_layout : &FaceLayout, threads : usize) -> TriangleMesh {
+    let data = fs::read(filename)
+        .unwrap_or_else(|e| panic!("Could not read file: {}: {}", filename, e));
+
+    parse_obj_parallel(&data, face_layout, threads)
+        .unwrap_or_else(|_| panic!("File is not UTF-8: {}", filename))
 }
 
-fn add_coordinate(elms : &Vec<&str>, arr : &mut Vec<Vec<f32>>, dim : usize) {
-    let v = &elms[1..=dim];
-    let v_f32 : Vec<f32> = v.iter()
-        .map(|x| x.parse::<f32>().unwrap())
-        .collect();
+fn parse_obj_parallel(data : &[u8], face_layout : &FaceLayout, threads : usize) -> Result<TriangleMesh, std::str::Utf8Error> {
+    let chunks = line_chunks(data, threads.max(1));
+
+    let parsed : Vec<ObjRecords> = thread::scope(|scope| {
+        let handles : Vec<_> = chunks.iter().map(|chunk| scope.spawn(|| {
+            let text = std::str::from_utf8(chunk)?;
+            let mut records = ObjRecords::new(face_layout);
+
+            for row in text.split('\n') {
+                records.parse_line(row);
+            }
+
+            Ok(records)
+        })).collect();
+
+        handles.into_iter().map(|h| h.join().unwrap()).collect::<Result<_, _>>()
+    })?;
 
-    arr.push(v_f32);
+    let mut records = ObjRecords::new(face_layout);
+    for chunk in parsed {
+        records.append(chunk);
+    }
+
+    Ok(records.into_mesh(face_layout))
 }
 
+/// Files above this size are parsed on all cores
+const PARALLEL_THRESHOLD : u64 = 32 << 20;
+
 pub fn obj_to_mesh(filename : &str, face_layout : &FaceLayout) -> TriangleMesh {
+    let file = File::open(filename)
+        .unwrap_or_else(|_| panic!("Could not read file: {}", filename));
 
-    let content = fs::read_to_string(filename)
-        .expect(&format!("Could not read file: {}", filename));
+    let size = file.metadata().map_or(0, |m| m.len());
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
 
-    let rows = content.split("\n");
+    if size > PARALLEL_THRESHOLD && threads > 1 {
+        return obj_to_mesh_parallel(filename, face_layout, threads);
+    }
 
-    let mut verts : Vec<Vec<f32>> = Vec::new();
-    let mut norms : Vec<Vec<f32>> = Vec::new();
-    let mut tex : Vec<Vec<f32>> = Vec::new();
+    read_obj(BufReader::with_capacity(1 << 16, file), face_layout)
+        .unwrap_or_else(|e| panic!("Could not read file: {}: {}", filename, e))
+}
 
-    let mut vertex_indicies : Vec<u32> = Vec::new();
-    let mut norm_indicies : Vec<u32> = Vec::new();
-    let mut tex_indicies : Vec<u32> = Vec::new();
+/// Record counts of an OBJ file and the material libraries it references
+pub struct ObjInfo {
+    pub verticies : usize,
+    pub normals : usize,
+    pub texture_coords : usize,
+    pub faces : usize,
+    pub materials : usize,
+    pub material_libs : Vec<String>,
+    pub face_layout : FaceLayout,
+}
 
-    for row in rows {
-        let elms : Vec<&str> = row.split_whitespace().collect();
+/// Calls `f` with every line of the file, without its line break, until it
+/// returns false. Only one line is held in memory at a time.
+fn for_each_row(filename : &str, mut f : impl FnMut(&str) -> bool) -> io::Result<()> {
+    let mut reader = BufReader::with_capacity(1 << 16, File::open(filename)?);
+    let mut row = String::new();
 
-        if elms.len() == 0 {continue;}
+    while reader.read_line(&mut row)? > 0 {
+        if !f(row.trim_end_matches(['\n', '\r'])) {
+            break;
+        }
+        row.clear();
+    }
+
+    Ok(())
+}
+
+/// Reads a `mtllib` statement, the path is relative to the OBJ file
+fn material_lib(obj_dir : &Path, row : &str) -> Option<String> {
+    row.trim().strip_prefix("mtllib ")
+        .map(|lib| obj_dir.join(lib.trim()).to_string_lossy().into_owned())
+}
+
+/// `mtllib` paths of an OBJ file, relative to the working directory. Only
+/// scans for the statement, the rest of the file is not parsed.
+pub fn obj_material_libs(filename : &str) -> io::Result<Vec<String>> {
+    let obj_dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+    let mut libs = Vec::new();
+
+    for_each_row(filename, |row| {
+        libs.extend(material_lib(obj_dir, row));
+        true
+    })?;
+
+    Ok(libs)
+}
 
-        match elms[0] {
-            "v" => {
-                add_coordinate(&elms, &mut verts, 3);
-            },
-            "vn" => {
-                add_coordinate(&elms, &mut norms, 3);
-            },
-            "vt" => {
-                add_coordinate(&elms, &mut tex, 2);
+fn material_names(mtl_content : &str) -> Vec<String> {
+    mtl_content.split("\n")
+        .filter_map(|row| row.trim().strip_prefix("newmtl "))
+        .map(|name| name.trim().to_string())
+        .collect()
+}
+
+pub fn obj_info(filename : &str) -> ObjInfo {
+    let obj_dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+
+    // v, vt, vn, f
+    let mut counts = [0usize; 4];
+    let mut material_libs = Vec::new();
+    let mut face_layout = None;
+
+    for_each_row(filename, |row| {
+        match row.split_whitespace().next() {
+            Some("v") => counts[0] += 1,
+            Some("vt") => counts[1] += 1,
+            Some("vn") => counts[2] += 1,
+            Some("f") => {
+                counts[3] += 1;
+                if face_layout.is_none() {
+                    face_layout = FaceLayout::of_face(row);
+                }
             }
-            "f" => {
-                let face_elms = &elms[1..4];
-                
-                // for each (a/b/c)
-                for f in face_elms {
-                    // indicies = [a, b, c]
-                    let indicies : Vec<u32> = f
-                        .split("/")
-                        .map(|x| 
-                            if let Ok(n) = x.parse::<u32>() {
-                                n - 1
-                            } else {
-                                0
-                            })
-                        .collect();
-
-                    face_layout.update_indicies(
-                        &mut vertex_indicies,
-                        &mut norm_indicies,
-                        &mut tex_indicies,
-                        &indicies
-                    );
+            Some("mtllib") => material_libs.extend(material_lib(obj_dir, row)),
+            _ => ()
+        }
+        true
+    }).unwrap_or_else(|e| panic!("Could not read file: {}: {}", filename, e));
+
+    let materials = material_libs.iter()
+        .filter_map(|lib| fs::read_to_string(lib).ok())
+        .map(|mtl| material_names(&mtl).len())
+        .sum();
+
+    ObjInfo {
+        verticies : counts[0],
+        normals : counts[2],
+        texture_coords : counts[1],
+        faces : counts[3],
+        materials,
+        material_libs,
+        face_layout : face_layout.unwrap_or_else(|| FaceLayout::new(Some(0), None, None)),
+    }
+}
+
+/// Checks face indices, material libraries and texture references.
+/// Returns a description of every problem found, empty if the file is valid.
+pub fn validate_obj(filename : &str) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let obj_dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+
+    let mut counts = [0usize; 3];
+    let mut used_materials = Vec::new();
+    let mut libs = Vec::new();
+    let mut line = 0;
+
+    let result = for_each_row(filename, |row| {
+        let mut elms = row.split_whitespace();
+        line += 1;
+
+        match elms.next() {
+            Some("v") => counts[0] += 1,
+            Some("vt") => counts[1] += 1,
+            Some("vn") => counts[2] += 1,
+            Some("usemtl") => used_materials.extend(elms.next().map(|name| (line, name.to_string()))),
+            Some("mtllib") => libs.extend(material_lib(obj_dir, row)),
+            Some("f") => {
+                let num_corners = elms.clone().count();
+
+                // Quads and larger polygons are fan triangulated when loaded
+                if num_corners < 3 {
+                    problems.push(format!("line {}: face has fewer than 3 verticies", line));
+                    return true;
                 }
 
+                for f in elms {
+                    for (i, x) in f.split("/").enumerate().take(3) {
+                        if x.is_empty() {continue;}
 
+                        match x.parse::<u32>() {
+                            Ok(n) if n >= 1 && (n as usize) <= counts[i] => (),
+                            Ok(n) => problems.push(format!("line {}: index {} out of range (1..={})", line, n, counts[i])),
+                            Err(_) => problems.push(format!("line {}: invalid index '{}'", line, x)),
+                        }
+                    }
+                }
             }
             _ => ()
         }
+        true
+    });
+
+    if let Err(e) = result {
+        return vec![format!("Could not read file {}: {}", filename, e)];
+    }
+
+    let mut defined_materials = Vec::new();
+
+    for lib in libs {
+        let mtl = match fs::read_to_string(&lib) {
+            Ok(c) => c,
+            Err(_) => {
+                problems.push(format!("material library {} not found", lib));
+                continue;
+            }
+        };
+        let mtl_dir = Path::new(&lib).parent().unwrap_or(Path::new(""));
+
+        for row in mtl.split("\n") {
+            let elms : Vec<&str> = row.split_whitespace().collect();
+
+            // Texture options come before the path, e.g. `map_Kd -s 1 1 1 tex.png`
+            if elms.len() > 1 && (elms[0].starts_with("map_") || elms[0] == "bump" || elms[0] == "norm") {
+                let tex = elms[elms.len() - 1];
+
+                if !mtl_dir.join(tex).exists() {
+                    problems.push(format!("{}: texture {} not found", lib, tex));
+                }
+            }
+        }
+
+        defined_materials.extend(material_names(&mtl));
+    }
+
+    for (line, name) in used_materials {
+        if !defined_materials.contains(&name) {
+            problems.push(format!("line {}: material {} is not defined", line, name));
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, time::Instant};
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn assert_parses_like_std(s : &str) {
+        let expected = s.parse::<f32>().ok().map(f32::to_bits);
+        assert_eq!(parse_f32(s).map(f32::to_bits), expected, "parsing '{}'", s);
+    }
+
+    #[test]
+    fn parse_f32_matches_str_parse() {
+        for s in [
+            "0", "-0", "1", "-1", "+1", ".5", "+.5", "-.5", "5.", "0.000001",
+            "1e3", "1E3", "1e-3", "-2.5e+2", "1.5e22", "1.5e-22", "3.4e38", "1e-40",
+            "123456789", "0.123456789", "-12.3456789012", "1.00000005960464477539",
+            "16777217", "0.1", "0.2", "0.3", "inf", "-inf", "NaN",
+            "", "-", "+", ".", "e5", "1e", "1e+", "1.2.3", "1x",
+        ] {
+            assert_parses_like_std(s);
+        }
     }
 
-    let verticies = face_layout.make_verticies(
-        &mut verts, &mut norms, &mut tex,
-        &vertex_indicies, &norm_indicies, &tex_indicies
-    );
+    #[test]
+    fn parse_f32_matches_str_parse_on_long_mantissas() {
+        // Deterministic LCG, the sequence only has to cover many digit patterns
+        let mut state : u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            state >> 11
+        };
 
-    let vertex_attrib_layout = face_layout.vertex_attrib_layout();
+        for _ in 0..200_000 {
+            let digits = 9 + next() % 7;
+            let mantissa = next() % 10u64.pow(digits as u32);
+            let exponent = (next() % 45) as i32 - 22;
+            let sign = if next() % 2 == 0 { "" } else { "-" };
 
-    TriangleMesh::from_array_indicies(
-        verticies,
-        vertex_indicies,
-        norm_indicies,
-        tex_indicies,
-        vertex_attrib_layout
-    )
-}
\ No newline at end of file
+            assert_parses_like_std(&format!("{}{}e{}", sign, mantissa, exponent));
+
+            let point = (next() % digits) as usize;
+            let text = format!("{:0width$}", mantissa, width = digits as usize);
+            assert_parses_like_std(&format!("{}{}.{}", sign, &text[..point], &text[point..]));
+        }
+    }
+
+    #[test]
+    fn parse_f32_falls_back_on_f32_ties() {
+        // Each rounds to an f64 exactly halfway between two f32 while the
+        // decimal itself is not, rounding twice would pick the wrong neighbour
+        for s in ["1.00000399351120", "1.00000661611557", "1.00001460313797", "1.00001722574234"] {
+            assert_parses_like_std(s);
+        }
+    }
+
+    /// Material groups, comments, blank and CRLF lines so that chunk
+    /// boundaries land on every kind of record for some thread count
+    const GROUPED_OBJ : &str = "# exported quad pair\r\n\
+        mtllib pair.mtl\r\n\
+        v -1 -1 0\r\nv 1 -1 0\r\nv 1 1 0\r\nv -1 1 0\n\
+        v -1 -1 1\nv 1 -1 1\r\nv 1 1 1\nv -1 1 1\r\n\
+        vt 0 0\r\nvt 1 0\nvt 1 1\r\nvt 0 1\n\
+        vn 0 0 1\r\nvn 0 0 -1\n\
+        \r\n\
+        usemtl front\r\n\
+        f 1/1/1 2/2/1 3/3/1\r\n\
+        # second half\n\
+        f 1/1/1 3/3/1 4/4/1\n\
+        usemtl unused\r\n\
+        usemtl back\n\
+        f 5/1/2 7/3/2 6/2/2\r\n\
+        f 5/1/2 8/4/2 7/3/2 6/2/2\r\n\
+        usemtl front\r\n\
+        f 2/2/1 6/2/2 7/3/2";
+
+    #[test]
+    fn line_chunks_end_at_line_breaks() {
+        let data = GROUPED_OBJ.as_bytes();
+
+        for n in 1..=data.len() {
+            let chunks = line_chunks(data, n);
+
+            assert_eq!(chunks.concat(), data);
+            for chunk in &chunks[..chunks.len() - 1] {
+                assert_eq!(chunk.last(), Some(&b'\n'));
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_parse_matches_read_obj() {
+        for face_layout in [
+            FaceLayout::new(Some(0), Some(1), Some(2)),
+            FaceLayout::new(Some(0), Some(1), None),
+            FaceLayout::new(Some(0), None, None),
+        ] {
+            let expected = read_obj(GROUPED_OBJ.as_bytes(), &face_layout).unwrap();
+            assert_eq!(expected.materials.len(), 3);
+
+            for threads in 1..=GROUPED_OBJ.len() {
+                let mesh = parse_obj_parallel(GROUPED_OBJ.as_bytes(), &face_layout, threads).unwrap();
+
+                let bits = |v : &[f32]| v.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+                assert_eq!(bits(&mesh.verticies), bits(&expected.verticies), "{} threads", threads);
+                assert_eq!(mesh.vertex_indicies, expected.vertex_indicies, "{} threads", threads);
+                assert_eq!(mesh.normal_indicies, expected.normal_indicies, "{} threads", threads);
+                assert_eq!(mesh.texture_indicies, expected.texture_indicies, "{} threads", threads);
+                assert_eq!(mesh.materials, expected.materials, "{} threads", threads);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_with_fewer_than_three_verticies_are_skipped() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2\nf 1 2 3\nf 3\nf 3 2 1\n";
+        let mesh = read_obj(obj.as_bytes(), &FaceLayout::new(Some(0), None, None)).unwrap();
+
+        assert_eq!(mesh.vertex_indicies, vec![0, 1, 2, 2, 1, 0]);
+    }
+
+    #[test]
+    fn validate_obj_accepts_triangles_quads_and_polygons() {
+        let dir = temp_dir("obj-validate");
+        let path = dir.join("faces.obj");
+        fs::write(&path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0.5 1.5 0\n\
+            f 1 2 3\nf 1 2 3 4\nf 1 2 5 3 4\n").unwrap();
+
+        assert_eq!(validate_obj(&path.to_string_lossy()), Vec::<String>::new());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validate_obj_reports_bad_faces_and_materials() {
+        let dir = temp_dir("obj-validate-problems");
+        let path = dir.join("faces.obj");
+        fs::write(&path, "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+            f 1 2\nf 1 2 3 5\nf 1 2 x\nusemtl red\nf 1 2 3 4\n").unwrap();
+
+        let lib = dir.join("missing.mtl").to_string_lossy().into_owned();
+        assert_eq!(validate_obj(&path.to_string_lossy()), vec![
+            "line 6: face has fewer than 3 verticies".to_string(),
+            "line 7: index 5 out of range (1..=4)".to_string(),
+            "line 8: invalid index 'x'".to_string(),
+            format!("material library {} not found", lib),
+            "line 9: material red is not defined".to_string(),
+        ]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Times the scans on a generated 1000 x 1000 grid, about 130 MB.
+    /// Run with `cargo test --release -- --ignored --nocapture scan_benchmark`
+    #[test]
+    #[ignore]
+    fn scan_benchmark() {
+        const N : usize = 1000;
+
+        let dir = temp_dir("obj-scan-benchmark");
+        let path = dir.join("grid.obj");
+        let mut out = io::BufWriter::new(File::create(&path).unwrap());
+
+        for i in 0..N {
+            for j in 0..N {
+                writeln!(out, "v {} {} 0.5\nvt {} {}", i, j, i as f32 / N as f32, j as f32 / N as f32).unwrap();
+            }
+        }
+        writeln!(out, "vn 0 0 1").unwrap();
+        for i in 0..N - 1 {
+            for j in 0..N - 1 {
+                let [a, b, c, d] = [i * N + j + 1, i * N + j + 2, (i + 1) * N + j + 2, (i + 1) * N + j + 1];
+                writeln!(out, "f {a}/{a}/1 {b}/{b}/1 {c}/{c}/1\nf {a}/{a}/1 {c}/{c}/1 {d}/{d}/1").unwrap();
+            }
+        }
+        out.flush().unwrap();
+        drop(out);
+
+        let path = path.to_string_lossy().into_owned();
+        let time = |name : &str, f : &dyn Fn()| {
+            let start = Instant::now();
+            f();
+            println!("{:<14} {:?}", name, start.elapsed());
+        };
+
+        time("detect", &|| assert!(FaceLayout::detect(&path).contains(ObjType::NORMAL)));
+        time("obj_info", &|| assert_eq!(obj_info(&path).faces, 2 * (N - 1) * (N - 1)));
+        time("validate_obj", &|| assert!(validate_obj(&path).is_empty()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
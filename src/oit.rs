@@ -0,0 +1,251 @@
+use gl::types::{GLenum, GLint, GLuint};
+
+use crate::fullscreen::{EmptyVertexArray, FULLSCREEN_VERTEX_SHADER_PATH};
+use crate::shader_preprocessor::ShaderFeatures;
+use crate::shader_program::ShaderProgram;
+
+pub const COMPOSITE_VERTEX_SHADER_PATH : &str = FULLSCREEN_VERTEX_SHADER_PATH;
+pub const COMPOSITE_FRAGMENT_SHADER_PATH : &str = "shaders/oit_composite.glsl";
+
+fn color_texture(internal_format : GLenum, format : GLenum, width : i32, height : i32, samples : i32) -> GLuint {
+    let mut id = 0;
+
+    unsafe {
+        gl::GenTextures(1, &mut id);
+
+        if samples > 0 {
+            gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, id);
+            gl::TexImage2DMultisample(gl::TEXTURE_2D_MULTISAMPLE, samples, internal_format, width, height, gl::TRUE);
+        } else {
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as GLint, width, height, 0, format, gl::FLOAT, std::ptr::null());
+        }
+    }
+
+    id
+}
+
+/// Sized depth format with the given bits, `None` if GL has no such format
+fn depth_internal_format(depth_bits : i32, stencil_bits : i32, float : bool) -> Option<GLenum> {
+    match (depth_bits, stencil_bits > 0, float) {
+        (32, true, true) => Some(gl::DEPTH32F_STENCIL8),
+        (32, false, true) => Some(gl::DEPTH_COMPONENT32F),
+        (24, true, false) => Some(gl::DEPTH24_STENCIL8),
+        (24, false, false) => Some(gl::DEPTH_COMPONENT24),
+        (16, false, false) => Some(gl::DEPTH_COMPONENT16),
+        (32, false, false) => Some(gl::DEPTH_COMPONENT32),
+        _ => None
+    }
+}
+
+fn has_stencil(format : GLenum) -> bool {
+    format == gl::DEPTH24_STENCIL8 || format == gl::DEPTH32F_STENCIL8
+}
+
+/// Depth format and sample count of `fbo`, 0 samples if it is single sampled
+fn depth_layout(fbo : GLuint) -> Result<(GLenum, i32), String> {
+    // The default framebuffer names its attachments differently
+    let (depth, stencil) = if fbo == 0 {
+        (gl::DEPTH, gl::STENCIL)
+    } else {
+        (gl::DEPTH_ATTACHMENT, gl::STENCIL_ATTACHMENT)
+    };
+
+    let mut object_type = 0;
+    let (mut depth_bits, mut stencil_bits, mut component_type, mut samples) = (0, 0, 0, 0);
+
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::GetFramebufferAttachmentParameteriv(gl::FRAMEBUFFER, depth, gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE, &mut object_type);
+
+        if object_type != gl::NONE as GLint {
+            gl::GetFramebufferAttachmentParameteriv(gl::FRAMEBUFFER, depth, gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE, &mut depth_bits);
+            gl::GetFramebufferAttachmentParameteriv(gl::FRAMEBUFFER, depth, gl::FRAMEBUFFER_ATTACHMENT_COMPONENT_TYPE, &mut component_type);
+            gl::GetFramebufferAttachmentParameteriv(gl::FRAMEBUFFER, stencil, gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE, &mut object_type);
+            if object_type != gl::NONE as GLint {
+                gl::GetFramebufferAttachmentParameteriv(gl::FRAMEBUFFER, stencil, gl::FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE, &mut stencil_bits);
+            }
+        }
+
+        gl::GetIntegerv(gl::SAMPLES, &mut samples);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    depth_internal_format(depth_bits, stencil_bits, component_type == gl::FLOAT as GLint)
+        .map(|format| (format, samples))
+        .ok_or_else(|| format!(
+            "Framebuffer {} has no depth buffer the OIT pass can copy ({} depth bits, {} stencil bits)",
+            fbo, depth_bits, stencil_bits
+        ))
+}
+
+/// Weighted blended order independent transparency (McGuire and Bavoil 2013).
+/// Transparent draws accumulate into two targets in any order, which are then
+/// composited over the opaque image.
+pub struct OitTarget {
+    fbo : GLuint,
+    accum : GLuint,
+    reveal : GLuint,
+    depth : GLuint,
+    samples : i32,
+    width : i32,
+    height : i32,
+    screen : EmptyVertexArray,
+}
+
+impl OitTarget {
+    /// `scene_fbo` is the framebuffer the opaque draws go to. The targets get
+    /// its depth format and sample count, so its depth can be blitted over.
+    pub fn new(width : i32, height : i32, scene_fbo : GLuint) -> Result<Self, String> {
+        let (depth_format, samples) = depth_layout(scene_fbo)?;
+        let mut fbo = 0;
+        let mut depth = 0;
+
+        let accum = color_texture(gl::RGBA16F, gl::RGBA, width, height, samples);
+        let reveal = color_texture(gl::R16F, gl::RED, width, height, samples);
+        let texture_target = if samples > 0 { gl::TEXTURE_2D_MULTISAMPLE } else { gl::TEXTURE_2D };
+        let depth_attachment = if has_stencil(depth_format) { gl::DEPTH_STENCIL_ATTACHMENT } else { gl::DEPTH_ATTACHMENT };
+
+        unsafe {
+            gl::GenRenderbuffers(1, &mut depth);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, depth_format, width, height);
+
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, texture_target, accum, 0);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT1, texture_target, reveal, 0);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, depth_attachment, gl::RENDERBUFFER, depth);
+
+            let attachments = [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1];
+            gl::DrawBuffers(2, attachments.as_ptr());
+        }
+
+        let target = OitTarget { fbo, accum, reveal, depth, samples, width, height, screen : EmptyVertexArray::new() };
+
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        // Drivers may round the sample count up, which a blit does not allow
+        let mut allocated = 0;
+        unsafe {
+            gl::GetIntegerv(gl::SAMPLES, &mut allocated);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("OIT framebuffer is incomplete: 0x{:X}", status));
+        }
+        if allocated != samples {
+            return Err(format!("OIT framebuffer has {} samples, the scene target {}", allocated, samples));
+        }
+
+        Ok(target)
+    }
+
+    /// Samples per pixel of the targets, 0 if they are single sampled
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
+
+    /// Permutation of the composite shader that reads these targets
+    pub fn composite_features(&self) -> ShaderFeatures {
+        ShaderFeatures { multisample : self.samples > 0, ..Default::default() }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Binds the accumulation targets for the transparent draws. `opaque_fbo`
+    /// holds the depth of the opaque draws, which transparent ones are tested against.
+    pub fn begin(&self, opaque_fbo : GLuint) {
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, opaque_fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo);
+            gl::BlitFramebuffer(
+                0, 0, self.width, self.height,
+                0, 0, self.width, self.height,
+                gl::DEPTH_BUFFER_BIT, gl::NEAREST
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+
+            gl::ClearBufferfv(gl::COLOR, 0, [0f32, 0., 0., 0.].as_ptr());
+            gl::ClearBufferfv(gl::COLOR, 1, [1f32, 0., 0., 0.].as_ptr());
+
+            gl::DepthMask(gl::FALSE);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunci(0, gl::ONE, gl::ONE);
+            gl::BlendFunci(1, gl::ZERO, gl::ONE_MINUS_SRC_COLOR);
+        }
+    }
+
+    /// Blends the accumulated colour over `opaque_fbo`. `program` must be
+    /// compiled with `composite_features`.
+    pub fn composite(&self, program : &ShaderProgram, opaque_fbo : GLuint) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, opaque_fbo);
+
+            gl::Disable(gl::DEPTH_TEST);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            let target = if self.samples > 0 { gl::TEXTURE_2D_MULTISAMPLE } else { gl::TEXTURE_2D };
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(target, self.accum);
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(target, self.reveal);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+
+        program.use_program();
+        program.set_uniform("accumTexture", 0);
+        program.set_uniform("revealTexture", 1);
+
+        self.screen.draw_fullscreen();
+
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+}
+
+impl Drop for OitTarget {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(2, [self.accum, self.reveal].as_ptr());
+            gl::DeleteRenderbuffers(1, &self.depth);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_formats_match_the_source_bits() {
+        assert_eq!(depth_internal_format(24, 8, false), Some(gl::DEPTH24_STENCIL8));
+        assert_eq!(depth_internal_format(24, 0, false), Some(gl::DEPTH_COMPONENT24));
+        assert_eq!(depth_internal_format(32, 0, true), Some(gl::DEPTH_COMPONENT32F));
+        assert_eq!(depth_internal_format(32, 8, true), Some(gl::DEPTH32F_STENCIL8));
+        assert_eq!(depth_internal_format(16, 0, false), Some(gl::DEPTH_COMPONENT16));
+        assert_eq!(depth_internal_format(32, 0, false), Some(gl::DEPTH_COMPONENT32));
+    }
+
+    #[test]
+    fn missing_or_unknown_depth_has_no_format() {
+        assert_eq!(depth_internal_format(0, 0, false), None);
+        assert_eq!(depth_internal_format(16, 8, false), None);
+        assert_eq!(depth_internal_format(24, 0, true), None);
+    }
+
+    #[test]
+    fn stencil_formats_use_the_combined_attachment() {
+        assert!(has_stencil(gl::DEPTH24_STENCIL8));
+        assert!(has_stencil(gl::DEPTH32F_STENCIL8));
+        assert!(!has_stencil(gl::DEPTH_COMPONENT32F));
+    }
+}
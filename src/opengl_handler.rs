@@ -1,8 +1,40 @@
-use std::{ffi::CString, fs};
-use crate::triangles::TriangleMesh;
+use std::collections::HashMap;
+
+use crate::triangles::{MaterialGroup, TriangleMesh, COLOR_ATTRIB};
 use glm::{self, Vector3};
-use crate::set_uniform::{set_uniform, UniformType};
+use crate::background::Background;
+use crate::deferred::{
+    GBuffer, GBufferChannel, DEFERRED_COMPOSITE_SHADER_PATH, DEFERRED_DEBUG_SHADER_PATH, DEFERRED_LIGHT_SHADER_PATH
+};
+use crate::environment::{Environment, IBL_SAMPLERS};
+use crate::fullscreen::{EmptyVertexArray, FULLSCREEN_VERTEX_SHADER_PATH};
+use crate::gltf_parser::{GltfImage, PbrMaterial};
+use crate::mesh_loader::MeshMaterials;
+use crate::material::{
+    AlphaMode, Material, RenderState, BASE_COLOR_SAMPLER, EMISSIVE_MAP_SAMPLER, METALLIC_MAP_SAMPLER,
+    NORMAL_MAP_SAMPLER, PBR_SAMPLERS, ROUGHNESS_MAP_SAMPLER
+};
+use crate::moving::BoundingBox;
+use crate::mtl_parser::MtlMaterial;
+use crate::multisample::{check_samples, set_multisample_rasterization};
+use crate::post_process::{PostPass, PostProcess};
+use crate::oit::{OitTarget, COMPOSITE_FRAGMENT_SHADER_PATH, COMPOSITE_VERTEX_SHADER_PATH};
+use crate::set_uniform::UniformType;
+use crate::shader_preprocessor::ShaderFeatures;
+use crate::shader_program::{ShaderCache, ShaderProgram};
+use crate::shadow::{
+    ShadowMaps, ShadowSettings, SHADOW_FRAGMENT_SHADER_PATH, SHADOW_MAP_UNIT, SHADOW_SAMPLER, SHADOW_VERTEX_SHADER_PATH
+};
+use crate::ssao::{
+    Ssao, SsaoSettings, SSAO_BLUR_SHADER_PATH, SSAO_PREPASS_FRAGMENT_SHADER_PATH, SSAO_PREPASS_VERTEX_SHADER_PATH,
+    SSAO_SAMPLER, SSAO_SHADER_PATH, SSAO_UNIT
+};
 use crate::texture::Texture;
+use crate::uniform_buffer::{
+    CameraBlock, FogBlock, Light, LightsBlock, MaterialBlock, ShadowsBlock, UniformBuffer, CAMERA_BINDING,
+    CAMERA_BLOCK, FOG_BINDING, FOG_BLOCK, LIGHTS_BINDING, LIGHTS_BLOCK, MATERIAL_BINDING, MATERIAL_BLOCK,
+    SHADOWS_BINDING, SHADOWS_BLOCK
+};
 
 struct GlBuffer {
     id : u32,
@@ -22,23 +54,32 @@ impl GlBuffer {
         GlBuffer { id, target, offset }
     }
 
-    fn set_data<T>(&mut self, data : &Vec<T>, usage : gl::types::GLenum) {
+    fn set_data<T>(&mut self, data : &[T], usage : gl::types::GLenum) {
         unsafe {
             //TODO : clear buffer data
             gl::BufferData(
                 self.target,
-                (data.len() * std::mem::size_of::<T>()) as isize,
+                std::mem::size_of_val(data) as isize,
                 data.as_ptr() as *const gl::types::GLvoid,
                 usage
             );
         }
 
-        self.offset = data.len() * std::mem::size_of::<T>();
+        self.offset = std::mem::size_of_val(data);
     }
 }
 
+impl Drop for GlBuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id) };
+    }
+}
+
+/// Projection and view of the camera. The view also holds the placement of
+/// the mesh, there is no separate model matrix.
 pub struct CameraHandler {
-    transform_mat : glm::Matrix4<f32>
+    projection : glm::Matrix4<f32>,
+    view : glm::Matrix4<f32>
 }
 
 impl CameraHandler {
@@ -49,93 +90,573 @@ impl CameraHandler {
             0., 0., 1., 0., 
             0., 0., 0., 1.);
 
-        CameraHandler { transform_mat : identity_mat }
+        CameraHandler { projection : identity_mat, view : identity_mat }
     }
 
     pub fn perspective(fov_rad : f32, aspect : f32, near : f32, far : f32) -> Self {
-        let mut transform_mat = glm::ext::perspective(fov_rad, aspect, near, far);
+        let projection = glm::ext::perspective(fov_rad, aspect, near, far);
     
-        CameraHandler { transform_mat }
+        CameraHandler { projection, ..CameraHandler::new() }
     }
 
     pub fn translate(&mut self, x : f32, y : f32, z : f32) {
-        self.transform_mat = glm::ext::translate(&self.transform_mat, Vector3::new(x, y, z));
+        self.view = glm::ext::translate(&self.view, Vector3::new(x, y, z));
     }
 
     pub fn scale(&mut self, x : f32,y : f32, z : f32) {
-        self.transform_mat = glm::ext::scale(&self.transform_mat, Vector3::new(x, y, z));
+        self.view = glm::ext::scale(&self.view, Vector3::new(x, y, z));
     }
 
     pub fn rotate(&mut self, angle : f32, axis : [f32;3]) {
-        self.transform_mat = glm::ext::rotate(&self.transform_mat, angle, Vector3::new(axis[0], axis[1], axis[2]));
+        self.view = glm::ext::rotate(&self.view, angle, Vector3::new(axis[0], axis[1], axis[2]));
+    }
+
+    pub fn projection(&self) -> glm::Matrix4<f32> {
+        self.projection
+    }
+
+    /// Mesh space to view space
+    pub fn view(&self) -> glm::Matrix4<f32> {
+        self.view
+    }
+
+    /// Mesh space to clip space
+    pub fn transform(&self) -> glm::Matrix4<f32> {
+        self.projection * self.view
     }
     
 }
 
+impl Default for CameraHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The SSAO prepass only needs to know whether the mesh has normals
+fn prepass_features(features : ShaderFeatures) -> ShaderFeatures {
+    ShaderFeatures { normals : features.normals, ..ShaderFeatures::default() }
+}
+
+/// The deferred light pass samples shadow maps like the materials do
+fn deferred_light_features(features : ShaderFeatures) -> ShaderFeatures {
+    ShaderFeatures { shadows : features.shadows, ..ShaderFeatures::default() }
+}
+
+/// The deferred composite and debug passes finish colours like the materials do
+fn deferred_composite_features(features : ShaderFeatures) -> ShaderFeatures {
+    ShaderFeatures { hdr_output : features.hdr_output, ..ShaderFeatures::default() }
+}
+
+pub const VERTEX_SHADER_PATH : &str = "shaders/vertex.glsl";
+pub const FRAGMENT_SHADER_PATH : &str = "shaders/fragment.glsl";
+
+/// A material group with the center of its verticies, to sort transparent draws
+struct DrawGroup {
+    group : MaterialGroup,
+    center : glm::Vector4<f32>,
+}
+
+type Draw<'a> = (&'a ShaderProgram, &'a Material, &'a DrawGroup);
+
+/// Which permutation of the material shaders a draw uses
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    FORWARD,
+    /// Transparent draws accumulated with weighted blended OIT
+    OIT,
+    /// Opaque draws writing the G-buffer of the deferred path
+    GEOMETRY,
+}
+
+impl Pass {
+    fn features(self, features : ShaderFeatures) -> ShaderFeatures {
+        ShaderFeatures { oit : self == Pass::OIT, deferred : self == Pass::GEOMETRY, ..features }
+    }
+}
+
 pub struct OpenGLHandler {
-    shader_program : u32,
+    shader_cache : ShaderCache,
+    shader_features : ShaderFeatures,
+    camera_buffer : Option<UniformBuffer>,
+    material_buffer : Option<UniformBuffer>,
+    lights_buffer : Option<UniformBuffer>,
+    shadows_buffer : Option<UniformBuffer>,
+    fog_buffer : Option<UniformBuffer>,
+    fog : FogBlock,
+    lights : LightsBlock,
+    materials : HashMap<String, Material>,
+    default_material : Material,
+    /// Textures loaded for `materials`
+    material_textures : Vec<u32>,
+    texture : u32,
+    /// 1x1 white, stands in for the maps a PBR material does not have
+    white_texture : u32,
     vbo : Option<GlBuffer>,
     ebo : Option<GlBuffer>,
-    num_triangles : u32,
+    draw_groups : Vec<DrawGroup>,
+    /// Bounds of the uploaded mesh, shadow maps are fitted to them
+    bounds : Option<BoundingBox>,
+    viewport : (i32, i32),
+    oit_enabled : bool,
+    oit : Option<OitTarget>,
+    /// Ambient light of PBR materials
+    environment : Option<Environment>,
+    background : Background,
+    /// For the background pass
+    screen : Option<EmptyVertexArray>,
+    shadows : Option<ShadowMaps>,
+    ssao : Option<Ssao>,
+    /// Opaque draws go through the G-buffer when set
+    deferred : Option<GBuffer>,
+    /// Shown instead of the lit image with the deferred path
+    gbuffer_view : Option<GBufferChannel>,
+    /// The scene is drawn into its HDR target when set, then through its passes
+    post_process : Option<PostProcess>,
+    /// Samples per pixel of the offscreen scene target, 0 without MSAA
+    samples : i32,
     pub camera_handler : CameraHandler
 }
 
 impl OpenGLHandler {
     pub fn new() -> Self {
         OpenGLHandler {  
-            shader_program : 0,
+            shader_cache : ShaderCache::new(),
+            shader_features : ShaderFeatures::default(),
+            camera_buffer : None,
+            material_buffer : None,
+            lights_buffer : None,
+            shadows_buffer : None,
+            fog_buffer : None,
+            fog : FogBlock::default(),
+            lights : LightsBlock {
+                // Matches the light direction of the non PBR shading
+                lights : vec![Light { casts_shadows : true, ..Light::directional([0., 0.707, 0.707], [1., 1., 1.], 3.) }]
+            },
+            materials : HashMap::new(),
+            default_material : Material::new(""),
+            material_textures : Vec::new(),
+            texture : 0,
+            white_texture : 0,
             vbo : None,
             ebo : None,
-            num_triangles : 0,
+            draw_groups : Vec::new(),
+            bounds : None,
+            viewport : (0, 0),
+            oit_enabled : false,
+            oit : None,
+            environment : None,
+            background : Background::default(),
+            screen : None,
+            shadows : None,
+            ssao : None,
+            deferred : None,
+            gbuffer_view : None,
+            post_process : None,
+            samples : 0,
             camera_handler : CameraHandler::new()
         }
     }
 
-    fn load_shader(&self, source_path: &str, shader_type: u32) -> u32 {
-        let source = fs::read_to_string(source_path).expect("Failed to read shader file");
-        let shader = unsafe {gl::CreateShader(shader_type)};
-        let c_str = CString::new(source.as_bytes()).unwrap();
-        unsafe {
-            gl::ShaderSource(shader, 1, &c_str.as_ptr(), std::ptr::null());
-            gl::CompileShader(shader);
+    /// Recompiles every shader permutation. The current programs stay in use
+    /// if anything fails.
+    pub fn reload_shaders(&mut self) -> Result<(), String> {
+        self.shader_cache.reload()?;
+
+        self.set_shader_features(self.shader_features)
+    }
+
+    /// Switches to the shader permutations for `features`, compiling them on first use.
+    /// Image based lighting, shadows, HDR output and ambient occlusion follow whether
+    /// an environment, shadow maps, post-processing and SSAO are set.
+    pub fn set_shader_features(&mut self, features : ShaderFeatures) -> Result<(), String> {
+        let features = ShaderFeatures {
+            ibl : self.environment.is_some(),
+            shadows : self.shadows.is_some(),
+            hdr_output : self.post_process.is_some(),
+            ssao : self.ssao.is_some(),
+            ..features
+        };
+        let camera = self.camera_block().std140();
+        let material = MaterialBlock::default().std140();
+        let lights = self.lights.std140();
+        let shadows = ShadowsBlock::default().std140();
+        let fog = self.fog.std140();
+
+        let mut permutations = vec![features];
+        if self.oit_enabled {
+            permutations.push(ShaderFeatures { oit : true, ..features });
         }
-        
-        let mut success = gl::FALSE as i32;
-        unsafe {
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+        if let Some(oit) = &self.oit {
+            self.shader_cache.get_or_compile(COMPOSITE_VERTEX_SHADER_PATH, COMPOSITE_FRAGMENT_SHADER_PATH, oit.composite_features())?;
         }
-        if success != gl::TRUE as i32 {
-            let mut log_length = 0;
-            unsafe {
-                gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut log_length);
-                let log = vec![0u8; log_length as usize];
-                gl::GetShaderInfoLog(shader, log_length, std::ptr::null_mut(), log.as_ptr() as *mut i8);
-                let log_string = String::from_utf8_lossy(&log);
-                panic!("Failed to compile shader: {}", log_string);
+        if self.deferred.is_some() {
+            permutations.push(Pass::GEOMETRY.features(features));
+            let passes = [
+                (DEFERRED_LIGHT_SHADER_PATH, deferred_light_features(features)),
+                (DEFERRED_COMPOSITE_SHADER_PATH, deferred_composite_features(features)),
+                (DEFERRED_DEBUG_SHADER_PATH, deferred_composite_features(features)),
+            ];
+            for (fragment_path, pass_features) in passes {
+                let program = self.shader_cache.get_or_compile(FULLSCREEN_VERTEX_SHADER_PATH, fragment_path, pass_features)?;
+                program.check_block(LIGHTS_BLOCK, &lights)?;
             }
-        } else {
-            println!("Shader compiled successfully!")
         }
-    
-        shader
+        if let Some((vertex_path, fragment_path)) = self.background.shaders() {
+            self.shader_cache.get_or_compile(vertex_path, fragment_path, ShaderFeatures::default())?;
+        }
+        if self.shadows.is_some() {
+            self.shader_cache.get_or_compile(SHADOW_VERTEX_SHADER_PATH, SHADOW_FRAGMENT_SHADER_PATH, ShaderFeatures::default())?;
+        }
+        if self.ssao.is_some() {
+            self.shader_cache.get_or_compile(SSAO_PREPASS_VERTEX_SHADER_PATH, SSAO_PREPASS_FRAGMENT_SHADER_PATH, prepass_features(features))?;
+            for fragment_path in [SSAO_SHADER_PATH, SSAO_BLUR_SHADER_PATH] {
+                self.shader_cache.get_or_compile(FULLSCREEN_VERTEX_SHADER_PATH, fragment_path, ShaderFeatures::default())?;
+            }
+        }
+        for fragment_path in self.post_process.iter().flat_map(|post| post.fragment_shaders()) {
+            self.shader_cache.get_or_compile(FULLSCREEN_VERTEX_SHADER_PATH, fragment_path, ShaderFeatures::default())?;
+        }
+
+        for m in std::iter::once(&self.default_material).chain(self.materials.values()) {
+            for permutation in &permutations {
+                let program = self.shader_cache.get_or_compile(&m.vertex_shader, &m.fragment_shader, m.shader_features(*permutation))?;
+
+                program.check_block(CAMERA_BLOCK, &camera)?;
+                program.check_block(MATERIAL_BLOCK, &material)?;
+                program.check_block(LIGHTS_BLOCK, &lights)?;
+                program.check_block(SHADOWS_BLOCK, &shadows)?;
+                program.check_block(FOG_BLOCK, &fog)?;
+            }
+        }
+        self.shader_features = Pass::FORWARD.features(features);
+
+        Ok(())
     }
-    
+
+    /// Adds or replaces the material drawn for the groups named `material.name`
+    pub fn add_material(&mut self, material : Material) -> Result<(), String> {
+        let features = material.shader_features(self.shader_features);
+
+        self.shader_cache.get_or_compile(&material.vertex_shader, &material.fragment_shader, features)?;
+        if self.oit_enabled {
+            self.shader_cache.get_or_compile(&material.vertex_shader, &material.fragment_shader, Pass::OIT.features(features))?;
+        }
+        if self.deferred.is_some() {
+            self.shader_cache.get_or_compile(&material.vertex_shader, &material.fragment_shader, Pass::GEOMETRY.features(features))?;
+        }
+        self.materials.insert(material.name.clone(), material);
+
+        Ok(())
+    }
+
+    /// Replaces the materials with those of a glTF scene, uploading its images
+    pub fn set_gltf_materials(&mut self, materials : &[PbrMaterial], images : &[GltfImage]) -> Result<(), String> {
+        self.clear_materials();
+
+        self.material_textures = images.iter()
+            .map(|img| Texture::from_rgba(img.width, img.height, &img.pixels))
+            .collect();
+
+        for material in materials {
+            self.add_material(Material::from_gltf(material, &self.material_textures))?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the materials with those of MTL files. `images` holds the
+    /// decoded maps, keyed by the paths in the materials.
+    pub fn set_mtl_materials(&mut self, materials : &[MtlMaterial], images : &HashMap<String, image::RgbaImage>) -> Result<(), String> {
+        self.clear_materials();
+
+        // Materials sharing a map share its texture
+        let mut uploaded : HashMap<&str, u32> = HashMap::new();
+
+        for mtl in materials {
+            let diffuse_map = mtl.diffuse_map.as_ref().and_then(|map| images.get(map));
+            let mut material = Material::from_mtl(mtl, diffuse_map);
+
+            let maps = [
+                (BASE_COLOR_SAMPLER, &mtl.diffuse_map),
+                (NORMAL_MAP_SAMPLER, &mtl.normal_map),
+                (EMISSIVE_MAP_SAMPLER, &mtl.emissive_map),
+                (ROUGHNESS_MAP_SAMPLER, &mtl.roughness_map),
+                (METALLIC_MAP_SAMPLER, &mtl.metallic_map),
+            ];
+
+            for (sampler, map) in maps {
+                let Some((path, img)) = map.as_ref().and_then(|map| images.get_key_value(map)) else {continue;};
+
+                let texture = *uploaded.entry(path).or_insert_with(|| {
+                    let texture = Texture::from_rgba(img.width(), img.height(), img.as_raw());
+                    self.material_textures.push(texture);
+                    texture
+                });
+                material.textures.push((sampler.to_string(), texture));
+            }
+
+            self.add_material(material)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the materials with those loaded along with a mesh
+    pub fn set_mesh_materials(&mut self, materials : &MeshMaterials) -> Result<(), String> {
+        match materials {
+            MeshMaterials::Mtl { materials, images, .. } => self.set_mtl_materials(materials, images),
+            MeshMaterials::Gltf { materials, images } => self.set_gltf_materials(materials, images)
+        }
+    }
+
+    fn clear_materials(&mut self) {
+        self.materials.clear();
+
+        if !self.material_textures.is_empty() {
+            unsafe { gl::DeleteTextures(self.material_textures.len() as i32, self.material_textures.as_ptr()) };
+            self.material_textures.clear();
+        }
+    }
+
+    /// Lights of the PBR path, in the same space as the mesh
+    pub fn set_lights(&mut self, lights : LightsBlock) {
+        self.lights = lights;
+
+        if let Some(lights_buffer) = &self.lights_buffer {
+            lights_buffer.update(&self.lights.std140());
+        }
+    }
+
+    /// Lights PBR materials with an equirectangular environment map, `None` goes
+    /// back to a constant ambient term. The previous environment stays if the
+    /// shaders fail to compile. `Background::ENVIRONMENT` draws it behind the mesh.
+    pub fn set_environment(&mut self, img : Option<&image::Rgb32FImage>) -> Result<(), String> {
+        let environment = match img {
+            Some(img) => Some(Environment::from_equirect(img, &mut self.shader_cache)?),
+            None => None
+        };
+        let previous = std::mem::replace(&mut self.environment, environment);
+
+        if let Err(e) = self.set_shader_features(self.shader_features) {
+            self.environment = previous;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Sets what is drawn where no mesh is
+    pub fn set_background(&mut self, background : Background) -> Result<(), String> {
+        if let Some((vertex_path, fragment_path)) = background.shaders() {
+            self.shader_cache.get_or_compile(vertex_path, fragment_path, ShaderFeatures::default())?;
+        }
+        self.background = background;
+
+        Ok(())
+    }
+
+    /// Renders shadow maps of the lights casting shadows, `None` turns shadows off.
+    /// The previous maps stay if the shaders fail to compile.
+    pub fn set_shadows(&mut self, settings : Option<ShadowSettings>) -> Result<(), String> {
+        let shadows = match settings {
+            Some(settings) => Some(ShadowMaps::new(settings)?),
+            None => None
+        };
+        let previous = std::mem::replace(&mut self.shadows, shadows);
+
+        if let Err(e) = self.set_shader_features(self.shader_features) {
+            self.shadows = previous;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Darkens the ambient light with screen space ambient occlusion, `None` turns it off.
+    /// Setting it again applies new settings. The previous SSAO stays if the shaders fail to compile.
+    pub fn set_ssao(&mut self, settings : Option<SsaoSettings>) -> Result<(), String> {
+        let ssao = match settings {
+            Some(settings) => Some(Ssao::new(self.viewport.0, self.viewport.1, settings)?),
+            None => None
+        };
+        let previous = std::mem::replace(&mut self.ssao, ssao);
+
+        if let Err(e) = self.set_shader_features(self.shader_features) {
+            self.ssao = previous;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Draws the scene in linear HDR and runs `passes` over it, which should
+    /// include a `PostPass::TONEMAP`. `None` draws straight to the window again.
+    /// The previous chain stays if the shaders fail to compile.
+    pub fn set_post_processing(&mut self, passes : Option<Vec<PostPass>>) -> Result<(), String> {
+        let post_process = match passes {
+            Some(passes) => Some(PostProcess::new(self.viewport.0, self.viewport.1, self.samples, passes)?),
+            None => None
+        };
+        let previous = std::mem::replace(&mut self.post_process, post_process);
+
+        if let Err(e) = self.set_shader_features(self.shader_features) {
+            self.post_process = previous;
+            return Err(e);
+        }
+
+        self.rebuild_oit()
+    }
+
+    /// Multisample anti-aliasing with `samples` per pixel, 0 or 1 turns it off.
+    /// The post-processing target gets multisampled renderbuffers resolved
+    /// before the passes, the window itself is single sampled. More samples
+    /// than `GL_MAX_SAMPLES` is an error and keeps the previous count.
+    pub fn set_multisampling(&mut self, samples : i32) -> Result<(), String> {
+        check_samples(samples)?;
+        set_multisample_rasterization(samples > 1);
+        self.samples = samples;
+
+        if let Some(post_process) = &mut self.post_process {
+            post_process.set_samples(samples)?;
+        }
+
+        self.rebuild_oit()
+    }
+
+    /// Fog over the view space depth of the mesh, the background is left clear
+    pub fn set_fog(&mut self, fog : FogBlock) {
+        self.fog = fog;
+
+        if let Some(fog_buffer) = &self.fog_buffer {
+            fog_buffer.update(&self.fog.std140());
+        }
+    }
+
+    /// The material for a group, the default one for unknown names
+    pub fn material(&self, name : &str) -> &Material {
+        self.materials.get(name).unwrap_or(&self.default_material)
+    }
+
+    fn shader_program(&self, material : &Material, pass : Pass) -> Option<&ShaderProgram> {
+        let features = pass.features(material.shader_features(self.shader_features));
+
+        self.shader_cache.get(&material.vertex_shader, &material.fragment_shader, features)
+    }
+
+    /// Shader files and their includes, to watch for changes
+    pub fn shader_files(&self) -> Vec<String> {
+        self.shader_cache.files().cloned().collect()
+    }
+
+    fn camera_block(&self) -> CameraBlock {
+        CameraBlock::new(self.camera_handler.projection(), self.camera_handler.view())
+    }
+
     pub fn init_shaders(&mut self) {
-        // Load and compile shaders
-        let vertex_shader = self.load_shader("shaders/vertex.glsl", gl::VERTEX_SHADER);
-        let fragment_shader = self.load_shader("shaders/fragment.glsl", gl::FRAGMENT_SHADER);
-    
-        // Create shader program
-        let shader_program = unsafe { gl::CreateProgram() };
-    
-        unsafe {
-            gl::AttachShader(shader_program, vertex_shader);
-            gl::AttachShader(shader_program, fragment_shader);
-            gl::LinkProgram(shader_program);
-            gl::UseProgram(shader_program);
+        self.camera_buffer = Some(UniformBuffer::from_block(CAMERA_BINDING, &self.camera_block().std140()));
+        self.material_buffer = Some(UniformBuffer::from_block(MATERIAL_BINDING, &MaterialBlock::default().std140()));
+        self.lights_buffer = Some(UniformBuffer::from_block(LIGHTS_BINDING, &self.lights.std140()));
+        self.shadows_buffer = Some(UniformBuffer::from_block(SHADOWS_BINDING, &ShadowsBlock::default().std140()));
+        self.fog_buffer = Some(UniformBuffer::from_block(FOG_BINDING, &self.fog.std140()));
+        self.screen = Some(EmptyVertexArray::new());
+
+        if let Err(e) = self.reload_shaders() {
+            panic!("{}", e);
+        }
+    }
+
+    /// Sets the viewport and resizes the offscreen targets to match the window
+    pub fn resize(&mut self, width : i32, height : i32) {
+        unsafe { gl::Viewport(0, 0, width, height) };
+        self.viewport = (width, height);
+
+        if let Some(post_process) = &mut self.post_process {
+            if let Err(e) = post_process.resize(width, height) {
+                println!("{}", e);
+            }
+        }
+
+        if let Err(e) = self.rebuild_oit() {
+            println!("{}", e);
+        }
+
+        if self.deferred.is_some() {
+            self.deferred = None;
+            match GBuffer::new(width, height) {
+                Ok(gbuffer) => self.deferred = Some(gbuffer),
+                Err(e) => println!("{}", e)
+            }
+        }
+
+        if let Some(ssao) = self.ssao.take() {
+            match Ssao::new(width, height, ssao.settings().clone()) {
+                Ok(ssao) => self.ssao = Some(ssao),
+                Err(e) => println!("{}", e)
+            }
+        }
+    }
+
+    /// Draws opaque materials through a G-buffer, lighting it one light at a
+    /// time over the screen rectangle of its range, so many small lights only
+    /// cost for the pixels around them.
+    /// Transparent materials stay forward shaded.
+    ///
+    /// Only PBR materials are lit by the lights of the block. The default
+    /// material and other non-PBR ones keep their fixed lighting, which the
+    /// geometry pass stores whole, so they look as they do forward shaded.
+    pub fn set_deferred_shading(&mut self, enabled : bool) -> Result<(), String> {
+        let gbuffer = if enabled { Some(GBuffer::new(self.viewport.0, self.viewport.1)?) } else { None };
+        let previous = std::mem::replace(&mut self.deferred, gbuffer);
+
+        if let Err(e) = self.set_shader_features(self.shader_features) {
+            self.deferred = previous;
+            return Err(e);
         }
 
-        self.shader_program = shader_program;
+        Ok(())
+    }
+
+    /// Shows a channel of the G-buffer instead of the lit image, `None` goes
+    /// back to it. Only used with deferred shading.
+    pub fn set_gbuffer_view(&mut self, channel : Option<GBufferChannel>) {
+        self.gbuffer_view = channel;
+    }
+
+    pub fn gbuffer_view(&self) -> Option<GBufferChannel> {
+        self.gbuffer_view
+    }
+
+    /// Draws transparent materials with weighted blended OIT instead of sorting them.
+    /// Sorting is per draw, OIT also handles triangles of one draw overlapping each other.
+    pub fn set_order_independent_transparency(&mut self, enabled : bool) -> Result<(), String> {
+        self.oit_enabled = enabled;
+
+        let result = self.rebuild_oit().and_then(|_| self.set_shader_features(self.shader_features));
+        if result.is_err() {
+            self.oit_enabled = false;
+            self.oit = None;
+        }
+
+        result
+    }
+
+    /// Recreates the OIT targets with the size, depth format and samples of
+    /// the scene target, which change with the window, post-processing and MSAA
+    fn rebuild_oit(&mut self) -> Result<(), String> {
+        // Dropped first, so the old textures are freed before the new ones are made
+        self.oit = None;
+
+        if !self.oit_enabled {
+            return Ok(());
+        }
+
+        let scene_fbo = self.post_process.as_ref().map_or(0, |post| post.scene_fbo());
+        let oit = OitTarget::new(self.viewport.0, self.viewport.1, scene_fbo)?;
+        self.shader_cache.get_or_compile(COMPOSITE_VERTEX_SHADER_PATH, COMPOSITE_FRAGMENT_SHADER_PATH, oit.composite_features())?;
+        self.oit = Some(oit);
+
+        Ok(())
     }
     
     pub fn init_buffers(&mut self, triangle_mesh : Option<&TriangleMesh>) {
@@ -145,8 +666,37 @@ impl OpenGLHandler {
         if let Some(tri_mesh) = triangle_mesh {
             vbo.set_data(&tri_mesh.verticies, gl::STATIC_DRAW);
             ebo.set_data(&tri_mesh.vertex_indicies, gl::STATIC_DRAW);
+            // Attributes of a previously uploaded mesh would point into the old buffer
+            for index in 0..=COLOR_ATTRIB {
+                unsafe { gl::DisableVertexAttribArray(index) };
+            }
+            // Meshes without vertex colours are drawn white
+            unsafe { gl::VertexAttrib4f(COLOR_ATTRIB, 1., 1., 1., 1.) };
             tri_mesh.enable_vertex_attributes();
-            self.num_triangles = (tri_mesh.verticies.len() / 3) as u32;
+
+            if let Err(e) = self.set_shader_features(ShaderFeatures::from_attributes(&tri_mesh.vertex_attrib_layout)) {
+                println!("{}", e);
+            }
+
+            self.bounds = (tri_mesh.num_verticies() > 0).then(|| BoundingBox::from_mesh(tri_mesh));
+
+            // Verticies before the first `usemtl` use the default material
+            let num_verticies = tri_mesh.num_verticies() as u32;
+            let first = tri_mesh.materials.first().map_or(num_verticies, |g| g.first);
+
+            self.draw_groups = std::iter::once(MaterialGroup { name : String::new(), first : 0, count : first })
+                .chain(tri_mesh.materials.iter().cloned())
+                .filter(|g| g.count > 0)
+                .map(|group| {
+                    let mut center = [0.; 3];
+                    for i in group.first..group.first + group.count {
+                        let p = tri_mesh.position(i as usize);
+                        (0..3).for_each(|k| center[k] += p[k] / group.count as f32);
+                    }
+
+                    DrawGroup { group, center : glm::vec4(center[0], center[1], center[2], 1.) }
+                })
+                .collect();
         }
 
         self.vbo = Some(vbo);
@@ -155,26 +705,282 @@ impl OpenGLHandler {
         unsafe {gl::Enable(gl::DEPTH_TEST)};
     }
 
-    pub fn init_textures(&self, tex_path : Option<&str>) {
-        if let Some(path) = tex_path {
-            Texture::load(path)
-        } else {
-            Texture::load("textures/missing.jpg")
+    pub fn init_textures(&mut self, tex_path : Option<&str>) {
+        if self.white_texture == 0 {
+            self.white_texture = Texture::from_rgba(1, 1, &[255; 4]);
+        }
+
+        let img = Texture::decode(tex_path.unwrap_or("textures/missing.jpg"));
+
+        self.set_texture(&img);
+    }
+
+    /// Uploads `img` and deletes the texture it replaces. Groups without a
+    /// material take their alpha mode from its alpha channel.
+    pub fn set_texture(&mut self, img : &image::RgbaImage) {
+        if self.texture != 0 {
+            unsafe { gl::DeleteTextures(1, &self.texture) };
+        }
+
+        self.texture = Texture::from_rgba(img.width(), img.height(), img.as_raw());
+        self.default_material.alpha_mode = AlphaMode::from_image(img);
+    }
+
+    /// Binds what differs between `material` and the previously drawn one.
+    /// Render state is left alone in the OIT pass, which sets its own.
+    fn bind_material(&self, program : &ShaderProgram, material : &Material, previous : Option<&Material>, oit : bool) {
+        // PBR maps default to white, the factors in the material block apply alone
+        let fallback = if material.pbr { self.white_texture } else { self.texture };
+        let missing = PBR_SAMPLERS.iter()
+            .filter(|sampler| material.pbr || **sampler == BASE_COLOR_SAMPLER)
+            .filter(|sampler| !material.textures.iter().any(|(s, _)| s == *sampler))
+            .map(|sampler| (sampler.to_string(), fallback));
+
+        let mut next_unit = 1;
+        for (sampler, texture) in material.textures.iter().cloned().chain(missing) {
+            let unit = if sampler == BASE_COLOR_SAMPLER {
+                0
+            } else {
+                next_unit += 1;
+                next_unit - 1
+            };
+
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+            }
+            program.set_uniform(&sampler, UniformType::INT(unit));
+        }
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+
+        // Flat shaded permutations do not sample the shadow maps
+        if self.shadows.is_some() && program.uniform(SHADOW_SAMPLER).is_some() {
+            program.set_uniform(SHADOW_SAMPLER, SHADOW_MAP_UNIT);
+        }
+        if self.ssao.is_some() && program.uniform(SSAO_SAMPLER).is_some() {
+            program.set_uniform(SSAO_SAMPLER, SSAO_UNIT);
+        }
+
+        if material.pbr && self.environment.is_some() {
+            for (sampler, unit) in IBL_SAMPLERS {
+                program.set_uniform(sampler, unit);
+            }
+        }
+
+        for (name, value) in &material.uniforms {
+            program.set_uniform(name, value.clone());
+        }
+
+        if let Some(material_buffer) = &self.material_buffer {
+            let block = MaterialBlock { alpha_cutoff : material.alpha_mode.cutoff(), ..material.block.clone() };
+            material_buffer.update(&block.std140());
+        }
+
+        if !oit {
+            material.render_state().apply(previous.map(|m| m.render_state()).as_ref());
+        }
+    }
+
+    /// Pairs each group with its material and program, skipping groups whose program failed to compile
+    fn with_programs<'a>(&'a self, groups : Vec<&'a DrawGroup>, pass : Pass) -> Vec<Draw<'a>> {
+        groups.into_iter().filter_map(|group| {
+            let material = self.material(&group.group.name);
+            self.shader_program(material, pass).map(|program| (program, material, group))
+        }).collect()
+    }
+
+    /// Draws in order, binding programs and materials only when they change.
+    /// Returns the last material drawn.
+    fn draw_list<'a>(&'a self, draws : &[Draw<'a>], oit : bool) -> Option<&'a Material> {
+        let mut current_program = None;
+        let mut current_material : Option<&Material> = None;
+
+        for (program, material, draw_group) in draws {
+            if current_program != Some(program.id()) {
+                program.use_program();
+                current_program = Some(program.id());
+                // Uniforms belong to the program, set them again
+                current_material = None;
+            }
+
+            if current_material.is_none_or(|m| m.name != material.name) {
+                self.bind_material(program, material, current_material, oit);
+                current_material = Some(material);
+            }
+
+            let group = &draw_group.group;
+            unsafe { gl::DrawArrays(gl::TRIANGLES, group.first as i32, group.count as i32) };
+        }
+
+        current_material
+    }
+
+    /// Renders the shadow maps, with the opaque groups as casters
+    fn draw_shadows(&self, casters : &[&DrawGroup]) {
+        let program = self.shader_cache.get(SHADOW_VERTEX_SHADER_PATH, SHADOW_FRAGMENT_SHADER_PATH, ShaderFeatures::default());
+
+        if let (Some(shadows), Some(program), Some(bounds)) = (&self.shadows, program, &self.bounds) {
+            let block = shadows.shadows_block(&self.lights.lights, &self.camera_handler.transform(), bounds);
+
+            if let Some(shadows_buffer) = &self.shadows_buffer {
+                shadows_buffer.update(&block.std140());
+            }
+
+            shadows.render(program, &block, || {
+                for draw_group in casters {
+                    let group = &draw_group.group;
+                    unsafe { gl::DrawArrays(gl::TRIANGLES, group.first as i32, group.count as i32) };
+                }
+            });
+            shadows.bind();
+        }
+    }
+
+    /// Renders the ambient occlusion of the opaque groups
+    fn draw_ambient_occlusion(&self, occluders : &[&DrawGroup]) {
+        let program = |vertex_path, fragment_path, features| self.shader_cache.get(vertex_path, fragment_path, features);
+        let prepass = program(SSAO_PREPASS_VERTEX_SHADER_PATH, SSAO_PREPASS_FRAGMENT_SHADER_PATH, prepass_features(self.shader_features));
+        let occlusion = program(FULLSCREEN_VERTEX_SHADER_PATH, SSAO_SHADER_PATH, ShaderFeatures::default());
+        let blur = program(FULLSCREEN_VERTEX_SHADER_PATH, SSAO_BLUR_SHADER_PATH, ShaderFeatures::default());
+
+        if let (Some(ssao), Some(prepass), Some(occlusion), Some(blur)) = (&self.ssao, prepass, occlusion, blur) {
+            ssao.render(prepass, occlusion, blur, &self.camera_handler.projection(), || {
+                for draw_group in occluders {
+                    let group = &draw_group.group;
+                    unsafe { gl::DrawArrays(gl::TRIANGLES, group.first as i32, group.count as i32) };
+                }
+            });
+            ssao.bind();
+        }
+    }
+
+    /// Lights the G-buffer and composites it into `scene_fbo`, or the channel
+    /// of `gbuffer_view` in its place
+    fn draw_deferred(&self, gbuffer : &GBuffer, scene_fbo : u32) {
+        let projection = self.camera_handler.projection();
+        let program = |fragment_path, features| self.shader_cache.get(FULLSCREEN_VERTEX_SHADER_PATH, fragment_path, features);
+
+        if let Some(light) = program(DEFERRED_LIGHT_SHADER_PATH, deferred_light_features(self.shader_features)) {
+            light.use_program();
+            if self.shadows.is_some() && light.uniform(SHADOW_SAMPLER).is_some() {
+                light.set_uniform(SHADOW_SAMPLER, SHADOW_MAP_UNIT);
+            }
+            gbuffer.accumulate_lights(light, &self.lights.lights, &projection, &self.camera_handler.view());
+        }
+
+        let features = deferred_composite_features(self.shader_features);
+        match self.gbuffer_view {
+            Some(channel) => if let Some(debug) = program(DEFERRED_DEBUG_SHADER_PATH, features) {
+                gbuffer.debug(debug, channel, &projection, scene_fbo);
+            },
+            None => if let Some(composite) = program(DEFERRED_COMPOSITE_SHADER_PATH, features) {
+                gbuffer.composite(composite, &projection, scene_fbo);
+            }
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, scene_fbo);
+            gl::Viewport(0, 0, self.viewport.0, self.viewport.1);
         }
     }
 
     pub fn draw(&self) {
+        // The window, or the HDR target of the post-processing passes
+        let scene_fbo = self.post_process.as_ref().map_or(0, |post| post.scene_fbo());
+
         unsafe { 
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-            gl::Clear(gl::DEPTH_BUFFER_BIT);
-            gl::ClearColor(0.2, 0.3, 0.3, 1.);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, scene_fbo);
+
+            let mut clear_color = self.background.clear_color();
+            if self.post_process.is_some() {
+                clear_color = clear_color.map(|c| c.powf(2.2));
+            }
+            let [r, g, b] = clear_color;
+            gl::ClearColor(r, g, b, 1.);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+
+        if let Some(camera_buffer) = &self.camera_buffer {
+            camera_buffer.update(&self.camera_block().std140());
+        }
+
+        let (transparent, opaque) : (Vec<&DrawGroup>, Vec<&DrawGroup>) = self.draw_groups.iter()
+            .partition(|g| self.material(&g.group.name).is_transparent());
+
+        self.draw_shadows(&opaque);
+        self.draw_ambient_occlusion(&opaque);
+
+        // Sorted by program, then textures and state, so each changes as rarely as possible
+        let pass = if self.deferred.is_some() { Pass::GEOMETRY } else { Pass::FORWARD };
+        let mut opaque = self.with_programs(opaque, pass);
+        opaque.sort_by_key(|(program, material, _)| (program.id(), material.sort_key()));
+
+        if let Some(environment) = &self.environment {
+            environment.bind();
+        }
+
+        let mut last_material = match &self.deferred {
+            Some(gbuffer) => {
+                gbuffer.begin_geometry();
+                let last_material = self.draw_list(&opaque, false);
+                // The light and composite passes set their own blending and depth state
+                RenderState::default().apply(last_material.map(|m| m.render_state()).as_ref());
+
+                self.draw_deferred(gbuffer, scene_fbo);
+                None
+            }
+            None => self.draw_list(&opaque, false)
+        };
+
+        // After the opaque draws, so it is only shaded where they left the far depth
+        let background = self.background.shaders()
+            .and_then(|(vertex_path, fragment_path)| self.shader_cache.get(vertex_path, fragment_path, ShaderFeatures::default()));
+        if let (Some(program), Some(screen)) = (background, &self.screen) {
+            self.background.draw(
+                program, &self.camera_handler.transform(), self.environment.as_ref(), screen, self.post_process.is_some()
+            );
+
+            // The background leaves depth writes on and the base colour unit changed
+            last_material = None;
+            RenderState::default().apply(None);
+        }
+
+        if let Some(oit) = &self.oit {
+            let transparent = self.with_programs(transparent, Pass::OIT);
+
+            if !transparent.is_empty() {
+                oit.begin(scene_fbo);
+                self.draw_list(&transparent, true);
+
+                if let Some(composite) = self.shader_cache.get(COMPOSITE_VERTEX_SHADER_PATH, COMPOSITE_FRAGMENT_SHADER_PATH, oit.composite_features()) {
+                    oit.composite(composite, scene_fbo);
+                }
+                // Composite leaves blending off and depth writes on
+                last_material = None;
+                RenderState::default().apply(None);
+            }
+        } else {
+            // Back to front, clip space z grows with the view depth
+            let view_depth = |group : &DrawGroup| (self.camera_handler.transform() * group.center).z;
 
-            set_uniform(self.shader_program, "texture0", UniformType::INT(0));
-            set_uniform(self.shader_program, "transformMatrix", UniformType::MAT4(self.camera_handler.transform_mat));
-        
-            // gl::DrawElements(gl::TRIANGLES, self.num_indicies as i32, gl::UNSIGNED_INT, std::ptr::null());
-            gl::DrawArrays(gl::TRIANGLES, 0, self.num_triangles as i32);
+            let mut transparent = self.with_programs(transparent, Pass::FORWARD);
+            transparent.sort_by(|(_, _, a), (_, _, b)| view_depth(b).total_cmp(&view_depth(a)));
+
+            last_material = self.draw_list(&transparent, false).or(last_material);
+        }
+
+        // The next frame clears with depth writes on
+        RenderState::default().apply(last_material.map(|m| m.render_state()).as_ref());
+
+        if let Some(post_process) = &self.post_process {
+            post_process.run(&self.shader_cache, 0);
         }
     }
     
-}
\ No newline at end of file
+}
+
+impl Default for OpenGLHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,467 @@
+use std::{fs, io};
+
+use crate::triangles::{
+    TriangleMesh, VertexAttribute, VertexAttributeLayout,
+    POSITION_ATTRIB, NORMAL_ATTRIB, TEXCOORD_ATTRIB, COLOR_ATTRIB
+};
+
+// Binary little and big endian
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    ASCII,
+    LE,
+    BE
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    CHAR, UCHAR, SHORT, USHORT, INT, UINT, FLOAT, DOUBLE
+}
+
+fn invalid_data(msg : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Scalar {
+    fn parse(name : &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::CHAR,
+            "uchar" | "uint8" => Scalar::UCHAR,
+            "short" | "int16" => Scalar::SHORT,
+            "ushort" | "uint16" => Scalar::USHORT,
+            "int" | "int32" => Scalar::INT,
+            "uint" | "uint32" => Scalar::UINT,
+            "float" | "float32" => Scalar::FLOAT,
+            "double" | "float64" => Scalar::DOUBLE,
+            _ => return Err(invalid_data(format!("Unknown PLY property type: {}", name)))
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::CHAR | Scalar::UCHAR => 1,
+            Scalar::SHORT | Scalar::USHORT => 2,
+            Scalar::INT | Scalar::UINT | Scalar::FLOAT => 4,
+            Scalar::DOUBLE => 8,
+        }
+    }
+
+    /// Value divided by the type max, so colours stored as uchar end up in 0..1
+    fn normalized(&self, x : f64) -> f64 {
+        match self {
+            Scalar::UCHAR => x / 255.,
+            Scalar::USHORT => x / 65535.,
+            Scalar::CHAR => x / 127.,
+            Scalar::SHORT => x / 32767.,
+            _ => x
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name : String,
+    count : usize,
+    properties : Vec<Property>,
+}
+
+/// Reads values one at a time from either the ASCII or binary body
+struct Body<'a> {
+    data : &'a [u8],
+    pos : usize,
+    format : Format,
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar : Scalar) -> io::Result<f64> {
+        if self.format == Format::ASCII {
+            while self.data.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self.data.get(self.pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+
+            if start == self.pos {
+                return Err(invalid_data("PLY file is truncated".to_string()));
+            }
+
+            return std::str::from_utf8(&self.data[start..self.pos]).ok()
+                .and_then(|x| x.parse::<f64>().ok())
+                .ok_or_else(|| invalid_data(format!(
+                    "Invalid value in PLY file: {}", String::from_utf8_lossy(&self.data[start..self.pos])
+                )));
+        }
+
+        let size = scalar.size();
+        let mut b = [0u8; 8];
+        b[..size].copy_from_slice(self.data.get(self.pos..self.pos + size)
+            .ok_or_else(|| invalid_data("PLY file is truncated".to_string()))?);
+        self.pos += size;
+
+        if self.format == Format::BE {
+            b[..size].reverse();
+        }
+
+        Ok(match scalar {
+            Scalar::CHAR => b[0] as i8 as f64,
+            Scalar::UCHAR => b[0] as f64,
+            Scalar::SHORT => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::USHORT => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::INT => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::UINT => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::FLOAT => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::DOUBLE => f64::from_le_bytes(b),
+        })
+    }
+}
+
+/// Vertex property with no dedicated attribute, one value per vertex
+pub struct PlyProperty {
+    pub name : String,
+    pub values : Vec<f32>,
+}
+
+pub struct PlyData {
+    pub mesh : TriangleMesh,
+    pub properties : Vec<PlyProperty>,
+}
+
+const POSITION_NAMES : [&str; 3] = ["x", "y", "z"];
+const NORMAL_NAMES : [&str; 3] = ["nx", "ny", "nz"];
+const TEXCOORD_NAMES : [[&str; 2]; 3] = [["s", "t"], ["u", "v"], ["texture_u", "texture_v"]];
+const COLOR_NAMES : [&str; 4] = ["red", "green", "blue", "alpha"];
+
+/// Reads an ASCII or binary PLY file. Malformed or truncated files, and faces
+/// pointing past the verticies, are an `InvalidData` error.
+pub fn read_ply(filename : &str) -> io::Result<PlyData> {
+    let content = fs::read(filename)?;
+
+    let header_end = content.windows(10)
+        .position(|w| w == b"end_header")
+        .ok_or_else(|| invalid_data("Missing PLY header".to_string()))?;
+    let body_start = content[header_end..].iter()
+        .position(|b| *b == b'\n')
+        .map_or(content.len(), |i| header_end + i + 1);
+
+    let header = String::from_utf8_lossy(&content[..header_end]);
+
+    let mut format = Format::ASCII;
+    let mut elements : Vec<Element> = Vec::new();
+
+    for row in header.split("\n") {
+        let elms : Vec<&str> = row.split_whitespace().collect();
+
+        if elms.is_empty() {continue;}
+
+        let malformed = || invalid_data(format!("Malformed PLY header line: {}", row.trim()));
+
+        match elms[0] {
+            "format" => {
+                format = match elms.get(1).copied() {
+                    Some("ascii") => Format::ASCII,
+                    Some("binary_little_endian") => Format::LE,
+                    Some("binary_big_endian") => Format::BE,
+                    _ => return Err(malformed())
+                }
+            }
+            "element" if elms.len() >= 3 => elements.push(Element {
+                name : elms[1].to_string(),
+                count : elms[2].parse().map_err(|_| malformed())?,
+                properties : Vec::new()
+            }),
+            "property" => {
+                let element = elements.last_mut().ok_or_else(malformed)?;
+
+                element.properties.push(match elms[1..] {
+                    ["list", count, item, name] => Property::List(name.to_string(), Scalar::parse(count)?, Scalar::parse(item)?),
+                    [scalar, name] => Property::Scalar(name.to_string(), Scalar::parse(scalar)?),
+                    _ => return Err(malformed())
+                });
+            }
+            "element" => return Err(malformed()),
+            _ => ()
+        }
+    }
+
+    let mut body = Body { data : &content[body_start..], pos : 0, format };
+
+    // Per vertex values of every scalar property, keyed by property name
+    let mut vertex_values : Vec<(String, Scalar, Vec<f32>)> = Vec::new();
+    let mut faces : Vec<Vec<u32>> = Vec::new();
+    let mut vertex_count = 0;
+
+    for element in &elements {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+
+        if is_vertex {
+            vertex_count = element.count;
+            // The count comes from the header, each value takes at least a byte
+            let capacity = element.count.min(body.data.len());
+
+            for property in &element.properties {
+                if let Property::Scalar(name, scalar) = property {
+                    vertex_values.push((name.clone(), *scalar, Vec::with_capacity(capacity)));
+                }
+            }
+        }
+
+        for _ in 0..element.count {
+            let mut scalar_index = 0;
+
+            for property in &element.properties {
+                match property {
+                    Property::Scalar(_, scalar) => {
+                        let x = body.read(*scalar)?;
+
+                        if is_vertex {
+                            vertex_values[scalar_index].2.push(x as f32);
+                            scalar_index += 1;
+                        }
+                    }
+                    Property::List(name, count_type, item_type) => {
+                        let count = body.read(*count_type)? as usize;
+                        let items = (0..count).map(|_| body.read(*item_type)).collect::<io::Result<Vec<f64>>>()?;
+
+                        if is_face && (name == "vertex_indices" || name == "vertex_index") {
+                            if let Some(i) = items.iter().find(|i| **i < 0. || **i >= vertex_count as f64) {
+                                return Err(invalid_data(format!(
+                                    "PLY face {} points to vertex {}, the file has {}", faces.len(), i, vertex_count
+                                )));
+                            }
+                            faces.push(items.into_iter().map(|i| i as u32).collect());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let find = |name : &str| vertex_values.iter().position(|(n, _, _)| n == name);
+    let find_all = |names : &[&str]| -> Option<Vec<usize>> { names.iter().map(|n| find(n)).collect() };
+
+    let positions = find_all(&POSITION_NAMES)
+        .ok_or_else(|| invalid_data("PLY file has no vertex positions".to_string()))?;
+    let normals = find_all(&NORMAL_NAMES);
+    let tex_coords = TEXCOORD_NAMES.iter().find_map(|names| find_all(names));
+    let colors = find_all(&COLOR_NAMES[..3]);
+    let alpha = find(COLOR_NAMES[3]);
+
+    let mut attributes = vec![VertexAttribute::float(POSITION_ATTRIB, 3)];
+    if normals.is_some() {
+        attributes.push(VertexAttribute::float(NORMAL_ATTRIB, 3));
+    }
+    if tex_coords.is_some() {
+        attributes.push(VertexAttribute::float(TEXCOORD_ATTRIB, 2));
+    }
+    if colors.is_some() {
+        attributes.push(VertexAttribute::float(COLOR_ATTRIB, 4));
+    }
+
+    let value = |column : usize, i : usize| vertex_values[column].2[i];
+    let color = |column : usize, i : usize| {
+        let (_, scalar, values) = &vertex_values[column];
+        scalar.normalized(values[i] as f64) as f32
+    };
+
+    let mut verticies : Vec<f32> = Vec::new();
+    let mut vertex_indicies : Vec<u32> = Vec::new();
+
+    for face in &faces {
+        // Fan triangulation for polygons
+        for k in 1..face.len().saturating_sub(1) {
+            for i in [face[0], face[k], face[k + 1]] {
+                let i = i as usize;
+
+                verticies.extend(positions.iter().map(|c| value(*c, i)));
+                if let Some(n) = &normals {
+                    verticies.extend(n.iter().map(|c| value(*c, i)));
+                }
+                if let Some(t) = &tex_coords {
+                    verticies.extend(t.iter().map(|c| value(*c, i)));
+                }
+                if let Some(c) = &colors {
+                    verticies.extend(c.iter().map(|c| color(*c, i)));
+                    verticies.push(alpha.map_or(1., |a| color(a, i)));
+                }
+
+                vertex_indicies.push(i as u32);
+            }
+        }
+    }
+
+    let normal_indicies = if normals.is_some() { vertex_indicies.clone() } else { Vec::new() };
+    let texture_indicies = if tex_coords.is_some() { vertex_indicies.clone() } else { Vec::new() };
+
+    let known : Vec<usize> = [Some(positions), normals, tex_coords, colors, alpha.map(|a| vec![a])]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect();
+
+    let properties = vertex_values.into_iter()
+        .enumerate()
+        .filter(|(i, _)| !known.contains(i))
+        .map(|(_, (name, _, values))| PlyProperty { name, values })
+        .collect();
+
+    let mesh = TriangleMesh::from_array_indicies(
+        verticies,
+        vertex_indicies,
+        normal_indicies,
+        texture_indicies,
+        VertexAttributeLayout::new(attributes)
+    );
+
+    Ok(PlyData { mesh, properties })
+}
+
+pub fn ply_to_data(filename : &str) -> PlyData {
+    read_ply(filename)
+        .unwrap_or_else(|e| panic!("Could not read file: {}: {}", filename, e))
+}
+
+pub fn ply_to_mesh(filename : &str) -> TriangleMesh {
+    ply_to_data(filename).mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    const QUAD_POSITIONS : [[f32; 3]; 4] = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+
+    fn header(format : &str, vertex_properties : &str, faces : usize) -> Vec<u8> {
+        format!(
+            "ply\nformat {} 1.0\nelement vertex 4\n{}element face {}\nproperty list uchar int vertex_indices\nend_header\n",
+            format, vertex_properties, faces
+        ).into_bytes()
+    }
+
+    /// Quad with float positions and one face, written with `to_bytes`
+    fn binary_quad(format : &str, to_bytes : fn(f32) -> [u8; 4], index_bytes : fn(i32) -> [u8; 4]) -> Vec<u8> {
+        let mut content = header(format, "property float x\nproperty float y\nproperty float z\n", 1);
+
+        for p in QUAD_POSITIONS.iter().flatten() {
+            content.extend(to_bytes(*p));
+        }
+        content.push(4);
+        for i in 0..4 {
+            content.extend(index_bytes(i));
+        }
+
+        content
+    }
+
+    fn write(dir : &Path, name : &str, content : &[u8]) -> String {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn positions(mesh : &TriangleMesh) -> Vec<&[f32]> {
+        (0..mesh.num_verticies()).map(|i| mesh.attribute(i, POSITION_ATTRIB, 3).unwrap()).collect()
+    }
+
+    #[test]
+    fn ascii_quad_is_fan_triangulated() {
+        let dir = temp_dir("ply-ascii");
+        let mut content = header("ascii", "property float x\nproperty float y\nproperty float z\n", 1);
+        content.extend(b"0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n");
+        let path = write(&dir, "quad.ply", &content);
+
+        let mesh = read_ply(&path).unwrap().mesh;
+
+        assert_eq!(mesh.vertex_indicies, vec![0, 1, 2, 0, 2, 3]);
+        let q = QUAD_POSITIONS;
+        assert_eq!(positions(&mesh), vec![&q[0][..], &q[1], &q[2], &q[0], &q[2], &q[3]]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn binary_little_and_big_endian_match_ascii() {
+        let dir = temp_dir("ply-binary");
+        let mut ascii = header("ascii", "property float x\nproperty float y\nproperty float z\n", 1);
+        ascii.extend(b"0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n");
+
+        let ascii = read_ply(&write(&dir, "ascii.ply", &ascii)).unwrap().mesh;
+        let le = binary_quad("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        let le = read_ply(&write(&dir, "le.ply", &le)).unwrap().mesh;
+        let be = binary_quad("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes);
+        let be = read_ply(&write(&dir, "be.ply", &be)).unwrap().mesh;
+
+        for mesh in [&le, &be] {
+            assert_eq!(mesh.verticies, ascii.verticies);
+            assert_eq!(mesh.vertex_indicies, ascii.vertex_indicies);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn uchar_colours_are_normalised() {
+        let dir = temp_dir("ply-colour");
+        let mut content = header(
+            "ascii",
+            "property float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\nproperty float quality\n",
+            1
+        );
+        content.extend(b"0 0 0 255 128 0 7\n1 0 0 255 128 0 7\n1 1 0 255 128 0 7\n0 1 0 255 128 0 7\n3 0 1 2\n");
+        let path = write(&dir, "colour.ply", &content);
+
+        let data = read_ply(&path).unwrap();
+
+        // No alpha property, so colours are opaque
+        assert_eq!(data.mesh.attribute(0, COLOR_ATTRIB, 4).unwrap(), &[1., 128. / 255., 0., 1.]);
+        // Unknown properties are kept per vertex
+        assert_eq!(data.properties.len(), 1);
+        assert_eq!(data.properties[0].name, "quality");
+        assert_eq!(data.properties[0].values, vec![7.; 4]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let dir = temp_dir("ply-malformed");
+        let properties = "property float x\nproperty float y\nproperty float z\n";
+
+        let mut out_of_range = header("ascii", properties, 1);
+        out_of_range.extend(b"0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 4\n");
+
+        let mut truncated = binary_quad("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        truncated.truncate(truncated.len() - 2);
+
+        // Far more faces than there are bytes
+        let mut huge_count = header("binary_little_endian", properties, usize::MAX);
+        huge_count.extend([0; 48]);
+
+        let mut bad_value = header("ascii", properties, 0);
+        bad_value.extend(b"0 0 0\n1 zero 0\n1 1 0\n0 1 0\n");
+
+        for (name, content) in [
+            ("out-of-range.ply", &out_of_range[..]),
+            ("truncated.ply", &truncated),
+            ("huge-count.ply", &huge_count),
+            ("bad-value.ply", &bad_value),
+            ("no-header.ply", b"ply\nformat ascii 1.0\n"),
+            ("bad-property.ply", b"ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n0\n"),
+        ] {
+            let err = read_ply(&write(&dir, name, content)).err();
+            assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::InvalidData), "{}", name);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+use gl::types::{GLenum, GLint, GLuint};
+
+use crate::fullscreen::{EmptyVertexArray, FULLSCREEN_VERTEX_SHADER_PATH};
+use crate::multisample::MultisampleTarget;
+use crate::shader_preprocessor::ShaderFeatures;
+use crate::shader_program::{ShaderCache, ShaderProgram};
+
+pub const EXPOSURE_SHADER_PATH : &str = "shaders/post_exposure.glsl";
+pub const TONEMAP_SHADER_PATH : &str = "shaders/post_tonemap.glsl";
+pub const BLOOM_EXTRACT_SHADER_PATH : &str = "shaders/post_bloom_extract.glsl";
+pub const BLUR_SHADER_PATH : &str = "shaders/post_blur.glsl";
+pub const BLOOM_COMBINE_SHADER_PATH : &str = "shaders/post_bloom_combine.glsl";
+pub const FXAA_SHADER_PATH : &str = "shaders/post_fxaa.glsl";
+pub const COLOR_GRADING_SHADER_PATH : &str = "shaders/post_color_grading.glsl";
+pub const VIGNETTE_SHADER_PATH : &str = "shaders/post_vignette.glsl";
+
+/// The image of the previous pass, on unit 0
+pub const SOURCE_SAMPLER : &str = "sourceTexture";
+
+fn color_texture(width : i32, height : i32) -> GLuint {
+    let mut id = 0;
+
+    unsafe {
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA16F as GLint, width, height, 0, gl::RGBA, gl::FLOAT, std::ptr::null());
+    }
+
+    id
+}
+
+/// Floating point colour target, with a depth buffer for the scene
+pub struct RenderTarget {
+    fbo : GLuint,
+    color : GLuint,
+    depth : Option<GLuint>,
+    width : i32,
+    height : i32,
+}
+
+impl RenderTarget {
+    /// `depth` adds a depth and stencil buffer in the format of the default
+    /// framebuffer, so the OIT pass can blit it
+    pub fn new(width : i32, height : i32, depth : bool) -> Result<Self, String> {
+        let mut fbo = 0;
+        let color = color_texture(width, height);
+
+        let depth = depth.then(|| {
+            let mut id = 0;
+            unsafe {
+                gl::GenRenderbuffers(1, &mut id);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, id);
+                gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+            }
+            id
+        });
+
+        unsafe {
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, color, 0);
+            if let Some(depth) = depth {
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, depth);
+            }
+        }
+
+        let target = RenderTarget { fbo, color, depth, width, height };
+
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) };
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Post-processing framebuffer is incomplete: 0x{:X}", status));
+        }
+
+        Ok(target)
+    }
+
+    pub fn fbo(&self) -> GLuint {
+        self.fbo
+    }
+
+    pub fn color(&self) -> GLuint {
+        self.color
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Binds the target for drawing, over its whole size
+    fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl::Viewport(0, 0, self.width, self.height);
+        }
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.color);
+            if let Some(depth) = &self.depth {
+                gl::DeleteRenderbuffers(1, depth);
+            }
+        }
+    }
+}
+
+/// Colour lookup table, a cube of `size` entries per channel
+pub struct ColorLut {
+    texture : GLuint,
+}
+
+impl ColorLut {
+    /// A strip of `size` squares side by side, `size` pixels wide each. Blue
+    /// grows from one square to the next, red to the right and green downwards
+    /// within a square.
+    pub fn from_strip(strip : &image::RgbaImage) -> Result<Self, String> {
+        let size = strip.height();
+        if size < 2 || strip.width() != size * size {
+            return Err(format!("A LUT strip of height N must be N * N wide, got {}x{}", strip.width(), strip.height()));
+        }
+
+        // Red, then green, then blue slices, as GL reads 3D textures
+        let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    texels.extend_from_slice(&strip.get_pixel(b * size + r, g).0);
+                }
+            }
+        }
+
+        let mut texture = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_3D, texture);
+            for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+                gl::TexParameteri(gl::TEXTURE_3D, wrap, gl::CLAMP_TO_EDGE as GLint);
+            }
+            gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexImage3D(
+                gl::TEXTURE_3D, 0, gl::RGBA8 as GLint, size as i32, size as i32, size as i32,
+                0, gl::RGBA, gl::UNSIGNED_BYTE, texels.as_ptr() as *const _
+            );
+        }
+
+        Ok(ColorLut { texture })
+    }
+
+    /// Reads a strip, see `from_strip`
+    pub fn load(path : &str) -> Result<Self, String> {
+        let strip = image::open(path).map_err(|e| format!("Could not read LUT {}: {}", path, e))?;
+
+        ColorLut::from_strip(&strip.into_rgba8())
+    }
+}
+
+impl Drop for ColorLut {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.texture) };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemap {
+    REINHARD,
+    /// Narkowicz's fit of the ACES filmic curve
+    ACES,
+}
+
+/// A step of the post-processing chain. Passes before `TONEMAP` work on
+/// linear HDR values, the ones after it on display values.
+#[allow(non_camel_case_types)]
+pub enum PostPass {
+    /// Scales the colour by 2^stops
+    EXPOSURE(f32),
+    /// Maps linear HDR values to the display
+    TONEMAP(Tonemap),
+    /// Adds a blur of what is brighter than `threshold`, blurred at half
+    /// resolution `iterations` times
+    BLOOM { threshold : f32, intensity : f32, iterations : u32 },
+    FXAA,
+    COLOR_GRADING(ColorLut),
+    /// Darkens the corners, `radius` is where it starts from the center
+    VIGNETTE { intensity : f32, radius : f32 },
+}
+
+impl PostPass {
+    /// Fragment shaders of the pass, drawn with `FULLSCREEN_VERTEX_SHADER_PATH`
+    pub fn fragment_shaders(&self) -> &'static [&'static str] {
+        match self {
+            PostPass::EXPOSURE(_) => &[EXPOSURE_SHADER_PATH],
+            PostPass::TONEMAP(_) => &[TONEMAP_SHADER_PATH],
+            PostPass::BLOOM { .. } => &[BLOOM_EXTRACT_SHADER_PATH, BLUR_SHADER_PATH, BLOOM_COMBINE_SHADER_PATH],
+            PostPass::FXAA => &[FXAA_SHADER_PATH],
+            PostPass::COLOR_GRADING(_) => &[COLOR_GRADING_SHADER_PATH],
+            PostPass::VIGNETTE { .. } => &[VIGNETTE_SHADER_PATH],
+        }
+    }
+}
+
+fn program<'a>(shader_cache : &'a ShaderCache, fragment_path : &str) -> Option<&'a ShaderProgram> {
+    shader_cache.get(FULLSCREEN_VERTEX_SHADER_PATH, fragment_path, ShaderFeatures::default())
+}
+
+fn bind_texture(unit : u32, target : GLenum, texture : GLuint) {
+    unsafe {
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
+        gl::BindTexture(target, texture);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+/// The scene is drawn into a floating point target, then each pass draws a
+/// fullscreen triangle reading the output of the previous one. The triangle
+/// stands in for the `objects/full_quad.obj` quad, it needs no vertex buffer.
+pub struct PostProcess {
+    passes : Vec<PostPass>,
+    scene : RenderTarget,
+    /// Drawn into instead of `scene` with MSAA, then resolved into it
+    multisample : Option<MultisampleTarget>,
+    /// Requested samples per pixel, 0 or 1 without MSAA
+    samples : i32,
+    /// Intermediate results, passes alternate between them
+    ping_pong : [RenderTarget; 2],
+    /// Half resolution, for the bloom blur
+    bloom : [RenderTarget; 2],
+    screen : EmptyVertexArray,
+}
+
+impl PostProcess {
+    /// `samples` above 1 draws the scene into multisampled renderbuffers
+    pub fn new(width : i32, height : i32, samples : i32, passes : Vec<PostPass>) -> Result<Self, String> {
+        let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+        let multisample = (samples > 1)
+            .then(|| MultisampleTarget::new(width, height, samples, gl::RGBA16F))
+            .transpose()?;
+
+        Ok(PostProcess {
+            passes,
+            scene : RenderTarget::new(width, height, multisample.is_none())?,
+            multisample,
+            samples,
+            ping_pong : [RenderTarget::new(width, height, false)?, RenderTarget::new(width, height, false)?],
+            bloom : [RenderTarget::new(half_width, half_height, false)?, RenderTarget::new(half_width, half_height, false)?],
+            screen : EmptyVertexArray::new(),
+        })
+    }
+
+    /// Recreates the targets for a new window size, keeping the passes
+    pub fn resize(&mut self, width : i32, height : i32) -> Result<(), String> {
+        let passes = std::mem::take(&mut self.passes);
+        *self = PostProcess::new(width, height, self.samples, passes)?;
+
+        Ok(())
+    }
+
+    /// Recreates the scene target with `samples` per pixel, keeping the passes
+    pub fn set_samples(&mut self, samples : i32) -> Result<(), String> {
+        let (width, height) = self.scene.size();
+        let passes = std::mem::take(&mut self.passes);
+        *self = PostProcess::new(width, height, samples, passes)?;
+
+        Ok(())
+    }
+
+    pub fn passes(&self) -> &[PostPass] {
+        &self.passes
+    }
+
+    /// Fragment shaders of every pass, see `PostPass::fragment_shaders`
+    pub fn fragment_shaders(&self) -> Vec<&'static str> {
+        self.passes.iter().flat_map(|pass| pass.fragment_shaders().iter().copied()).collect()
+    }
+
+    /// Where the scene is drawn, in linear HDR
+    pub fn scene_fbo(&self) -> GLuint {
+        self.multisample.as_ref().map_or(self.scene.fbo(), |multisample| multisample.fbo())
+    }
+
+    /// Runs the passes over the scene, the last one drawing into `output_fbo`.
+    /// Passes whose shaders are not in `shader_cache` are skipped.
+    pub fn run(&self, shader_cache : &ShaderCache, output_fbo : GLuint) {
+        let (width, height) = self.scene.size();
+
+        let passes : Vec<(&PostPass, Vec<&ShaderProgram>)> = self.passes.iter()
+            .filter_map(|pass| {
+                let programs = pass.fragment_shaders().iter().map(|path| program(shader_cache, path)).collect::<Option<_>>();
+                programs.map(|programs| (pass, programs))
+            })
+            .collect();
+
+        if let Some(multisample) = &self.multisample {
+            multisample.resolve(self.scene.fbo());
+        }
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+        }
+
+        if passes.is_empty() {
+            unsafe {
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.scene.fbo());
+                gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, output_fbo);
+                gl::BlitFramebuffer(0, 0, width, height, 0, 0, width, height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            }
+        }
+
+        let mut source = self.scene.color();
+
+        for (i, (pass, programs)) in passes.iter().enumerate() {
+            let target = &self.ping_pong[i % 2];
+
+            if i + 1 == passes.len() {
+                unsafe {
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, output_fbo);
+                    gl::Viewport(0, 0, width, height);
+                }
+            } else {
+                target.bind();
+            }
+
+            self.draw_pass(pass, programs, source);
+            source = target.color();
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, output_fbo);
+            gl::Viewport(0, 0, width, height);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    /// Draws `pass` reading `source` into the bound framebuffer, `programs`
+    /// are those of `PostPass::fragment_shaders`
+    fn draw_pass(&self, pass : &PostPass, programs : &[&ShaderProgram], source : GLuint) {
+        let mut output_fbo = 0;
+        let mut viewport = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut output_fbo);
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+
+        if let PostPass::BLOOM { threshold, iterations, .. } = pass {
+            let (extract, blur) = (programs[0], programs[1]);
+            let (width, height) = self.bloom[0].size();
+
+            self.bloom[0].bind();
+            extract.use_program();
+            extract.set_uniform(SOURCE_SAMPLER, 0);
+            extract.set_uniform("threshold", *threshold);
+            bind_texture(0, gl::TEXTURE_2D, source);
+            self.screen.draw_fullscreen();
+
+            // Separable gaussian, horizontally into the second target and back vertically
+            blur.use_program();
+            blur.set_uniform(SOURCE_SAMPLER, 0);
+            for _ in 0..*iterations {
+                for (from, to, direction) in [(0, 1, [1. / width as f32, 0.]), (1, 0, [0., 1. / height as f32])] {
+                    self.bloom[to].bind();
+                    blur.set_uniform("direction", direction);
+                    bind_texture(0, gl::TEXTURE_2D, self.bloom[from].color());
+                    self.screen.draw_fullscreen();
+                }
+            }
+
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, output_fbo as GLuint);
+                gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            }
+        }
+
+        let program = programs[programs.len() - 1];
+        program.use_program();
+        program.set_uniform(SOURCE_SAMPLER, 0);
+        bind_texture(0, gl::TEXTURE_2D, source);
+
+        match pass {
+            PostPass::EXPOSURE(stops) => program.set_uniform("exposure", *stops),
+            PostPass::TONEMAP(tonemap) => program.set_uniform("tonemapOperator", *tonemap as i32),
+            PostPass::BLOOM { intensity, .. } => {
+                program.set_uniform("bloomTexture", 1);
+                program.set_uniform("intensity", *intensity);
+                bind_texture(1, gl::TEXTURE_2D, self.bloom[0].color());
+            }
+            PostPass::FXAA => (),
+            PostPass::COLOR_GRADING(lut) => {
+                program.set_uniform("lutTexture", 1);
+                bind_texture(1, gl::TEXTURE_3D, lut.texture);
+            }
+            PostPass::VIGNETTE { intensity, radius } => {
+                program.set_uniform("intensity", *intensity);
+                program.set_uniform("radius", *radius);
+            }
+        }
+
+        self.screen.draw_fullscreen();
+    }
+}
@@ -1,35 +1,373 @@
-use gl::types::GLuint;
-use std::ffi::CString;
+use gl::types::{GLenum, GLint};
 
+#[allow(non_camel_case_types)]
+#[derive(Clone, Debug)]
 pub enum UniformType {
     INT(i32),
+    UINT(u32),
+    BOOL(bool),
     FLOAT(f32),
-    VEC2([f32;2]),   
+    VEC2([f32;2]),
     VEC3([f32;3]),
     VEC4([f32;4]),
-    MAT4(glm::Matrix4<f32>)
-}
-
-pub fn set_uniform(shader_program : GLuint, uniform_name : &str, uniform_value : UniformType) {
-    // Get the location of the uniform variable in the shader program
-    let uniform_location = unsafe {
-        gl::GetUniformLocation(shader_program, CString::new(uniform_name).unwrap().as_ptr())
-    };
-
-    // Check if the uniform location is valid (-1 means not found)
-    if uniform_location != -1 {
-        unsafe {
-            match uniform_value {
-                UniformType::VEC2(x) => gl::Uniform2f(uniform_location, x[0], x[1]),
-                UniformType::VEC3(x) => gl::Uniform3f(uniform_location, x[0], x[1], x[2]),
-                UniformType::VEC4(x) => gl::Uniform4f(uniform_location, x[0], x[1], x[2], x[3]),
-                UniformType::FLOAT(x) => gl::Uniform1f(uniform_location, x),
-                UniformType::INT(x) => gl::Uniform1i(uniform_location, x),
-                UniformType::MAT4(x) => gl::UniformMatrix4fv(uniform_location, 1, gl::FALSE, &x.as_array()[0][0]),
+    IVEC2([i32;2]),
+    IVEC3([i32;3]),
+    IVEC4([i32;4]),
+    UVEC2([u32;2]),
+    UVEC3([u32;3]),
+    UVEC4([u32;4]),
+    MAT2(glm::Matrix2<f32>),
+    MAT3(glm::Matrix3<f32>),
+    MAT4(glm::Matrix4<f32>),
+
+    INT_ARRAY(Vec<i32>),
+    UINT_ARRAY(Vec<u32>),
+    BOOL_ARRAY(Vec<bool>),
+    FLOAT_ARRAY(Vec<f32>),
+    VEC2_ARRAY(Vec<[f32;2]>),
+    VEC3_ARRAY(Vec<[f32;3]>),
+    VEC4_ARRAY(Vec<[f32;4]>),
+    IVEC2_ARRAY(Vec<[i32;2]>),
+    IVEC3_ARRAY(Vec<[i32;3]>),
+    IVEC4_ARRAY(Vec<[i32;4]>),
+    UVEC2_ARRAY(Vec<[u32;2]>),
+    UVEC3_ARRAY(Vec<[u32;3]>),
+    UVEC4_ARRAY(Vec<[u32;4]>),
+    MAT2_ARRAY(Vec<glm::Matrix2<f32>>),
+    MAT3_ARRAY(Vec<glm::Matrix3<f32>>),
+    MAT4_ARRAY(Vec<glm::Matrix4<f32>>),
+}
+
+impl UniformType {
+    /// Whether a GLSL uniform of `gl_type` (as reported by GetActiveUniform) accepts this value
+    pub fn matches(&self, gl_type : GLenum) -> bool {
+        use UniformType::*;
+
+        match self {
+            // Samplers are set through their texture unit
+            INT(_) | INT_ARRAY(_) => gl_type == gl::INT || gl_type == gl::BOOL || is_sampler(gl_type),
+            UINT(_) | UINT_ARRAY(_) => gl_type == gl::UNSIGNED_INT || gl_type == gl::BOOL,
+            BOOL(_) | BOOL_ARRAY(_) => gl_type == gl::BOOL,
+            FLOAT(_) | FLOAT_ARRAY(_) => gl_type == gl::FLOAT,
+            VEC2(_) | VEC2_ARRAY(_) => gl_type == gl::FLOAT_VEC2,
+            VEC3(_) | VEC3_ARRAY(_) => gl_type == gl::FLOAT_VEC3,
+            VEC4(_) | VEC4_ARRAY(_) => gl_type == gl::FLOAT_VEC4,
+            IVEC2(_) | IVEC2_ARRAY(_) => gl_type == gl::INT_VEC2 || gl_type == gl::BOOL_VEC2,
+            IVEC3(_) | IVEC3_ARRAY(_) => gl_type == gl::INT_VEC3 || gl_type == gl::BOOL_VEC3,
+            IVEC4(_) | IVEC4_ARRAY(_) => gl_type == gl::INT_VEC4 || gl_type == gl::BOOL_VEC4,
+            UVEC2(_) | UVEC2_ARRAY(_) => gl_type == gl::UNSIGNED_INT_VEC2 || gl_type == gl::BOOL_VEC2,
+            UVEC3(_) | UVEC3_ARRAY(_) => gl_type == gl::UNSIGNED_INT_VEC3 || gl_type == gl::BOOL_VEC3,
+            UVEC4(_) | UVEC4_ARRAY(_) => gl_type == gl::UNSIGNED_INT_VEC4 || gl_type == gl::BOOL_VEC4,
+            MAT2(_) | MAT2_ARRAY(_) => gl_type == gl::FLOAT_MAT2,
+            MAT3(_) | MAT3_ARRAY(_) => gl_type == gl::FLOAT_MAT3,
+            MAT4(_) | MAT4_ARRAY(_) => gl_type == gl::FLOAT_MAT4,
+        }
+    }
+
+    /// Number of array elements, 1 for single values
+    pub fn len(&self) -> usize {
+        use UniformType::*;
+
+        match self {
+            INT_ARRAY(x) => x.len(),
+            UINT_ARRAY(x) => x.len(),
+            BOOL_ARRAY(x) => x.len(),
+            FLOAT_ARRAY(x) => x.len(),
+            VEC2_ARRAY(x) => x.len(),
+            VEC3_ARRAY(x) => x.len(),
+            VEC4_ARRAY(x) => x.len(),
+            IVEC2_ARRAY(x) => x.len(),
+            IVEC3_ARRAY(x) => x.len(),
+            IVEC4_ARRAY(x) => x.len(),
+            UVEC2_ARRAY(x) => x.len(),
+            UVEC3_ARRAY(x) => x.len(),
+            UVEC4_ARRAY(x) => x.len(),
+            MAT2_ARRAY(x) => x.len(),
+            MAT3_ARRAY(x) => x.len(),
+            MAT4_ARRAY(x) => x.len(),
+            _ => 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn name(&self) -> &'static str {
+        use UniformType::*;
+
+        match self {
+            INT(_) => "INT",
+            UINT(_) => "UINT",
+            BOOL(_) => "BOOL",
+            FLOAT(_) => "FLOAT",
+            VEC2(_) => "VEC2",
+            VEC3(_) => "VEC3",
+            VEC4(_) => "VEC4",
+            IVEC2(_) => "IVEC2",
+            IVEC3(_) => "IVEC3",
+            IVEC4(_) => "IVEC4",
+            UVEC2(_) => "UVEC2",
+            UVEC3(_) => "UVEC3",
+            UVEC4(_) => "UVEC4",
+            MAT2(_) => "MAT2",
+            MAT3(_) => "MAT3",
+            MAT4(_) => "MAT4",
+            INT_ARRAY(_) => "INT_ARRAY",
+            UINT_ARRAY(_) => "UINT_ARRAY",
+            BOOL_ARRAY(_) => "BOOL_ARRAY",
+            FLOAT_ARRAY(_) => "FLOAT_ARRAY",
+            VEC2_ARRAY(_) => "VEC2_ARRAY",
+            VEC3_ARRAY(_) => "VEC3_ARRAY",
+            VEC4_ARRAY(_) => "VEC4_ARRAY",
+            IVEC2_ARRAY(_) => "IVEC2_ARRAY",
+            IVEC3_ARRAY(_) => "IVEC3_ARRAY",
+            IVEC4_ARRAY(_) => "IVEC4_ARRAY",
+            UVEC2_ARRAY(_) => "UVEC2_ARRAY",
+            UVEC3_ARRAY(_) => "UVEC3_ARRAY",
+            UVEC4_ARRAY(_) => "UVEC4_ARRAY",
+            MAT2_ARRAY(_) => "MAT2_ARRAY",
+            MAT3_ARRAY(_) => "MAT3_ARRAY",
+            MAT4_ARRAY(_) => "MAT4_ARRAY",
+        }
+    }
+}
+
+/// Sampler types of every dimension, including the integer, shadow, array and
+/// multisample variants
+fn is_sampler(gl_type : GLenum) -> bool {
+    matches!(gl_type,
+        gl::SAMPLER_1D | gl::SAMPLER_2D | gl::SAMPLER_3D | gl::SAMPLER_CUBE |
+        gl::SAMPLER_1D_SHADOW | gl::SAMPLER_2D_SHADOW | gl::SAMPLER_CUBE_SHADOW |
+        gl::SAMPLER_1D_ARRAY | gl::SAMPLER_2D_ARRAY | gl::SAMPLER_CUBE_MAP_ARRAY |
+        gl::SAMPLER_1D_ARRAY_SHADOW | gl::SAMPLER_2D_ARRAY_SHADOW | gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW |
+        gl::SAMPLER_2D_MULTISAMPLE | gl::SAMPLER_2D_MULTISAMPLE_ARRAY |
+        gl::SAMPLER_2D_RECT | gl::SAMPLER_2D_RECT_SHADOW | gl::SAMPLER_BUFFER |
+
+        gl::INT_SAMPLER_1D | gl::INT_SAMPLER_2D | gl::INT_SAMPLER_3D | gl::INT_SAMPLER_CUBE |
+        gl::INT_SAMPLER_1D_ARRAY | gl::INT_SAMPLER_2D_ARRAY | gl::INT_SAMPLER_CUBE_MAP_ARRAY |
+        gl::INT_SAMPLER_2D_MULTISAMPLE | gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY |
+        gl::INT_SAMPLER_2D_RECT | gl::INT_SAMPLER_BUFFER |
+
+        gl::UNSIGNED_INT_SAMPLER_1D | gl::UNSIGNED_INT_SAMPLER_2D | gl::UNSIGNED_INT_SAMPLER_3D |
+        gl::UNSIGNED_INT_SAMPLER_CUBE | gl::UNSIGNED_INT_SAMPLER_1D_ARRAY | gl::UNSIGNED_INT_SAMPLER_2D_ARRAY |
+        gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY | gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE |
+        gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY | gl::UNSIGNED_INT_SAMPLER_2D_RECT |
+        gl::UNSIGNED_INT_SAMPLER_BUFFER)
+}
+
+/// Column major floats of glm matrices
+fn mat2_floats(m : &[glm::Matrix2<f32>]) -> Vec<f32> {
+    m.iter().flat_map(|m| m.as_array().iter().flat_map(|c| [c[0], c[1]])).collect()
+}
+
+fn mat3_floats(m : &[glm::Matrix3<f32>]) -> Vec<f32> {
+    m.iter().flat_map(|m| m.as_array().iter().flat_map(|c| [c[0], c[1], c[2]])).collect()
+}
+
+fn mat4_floats(m : &[glm::Matrix4<f32>]) -> Vec<f32> {
+    m.iter().flat_map(|m| m.as_array().iter().flat_map(|c| [c[0], c[1], c[2], c[3]])).collect()
+}
+
+/// Sets the value at an already looked up location of the program in use
+pub fn upload_uniform(uniform_location : GLint, uniform_value : UniformType) {
+    let count = uniform_value.len() as i32;
+
+    unsafe {
+        match uniform_value {
+            UniformType::VEC2(x) => gl::Uniform2f(uniform_location, x[0], x[1]),
+            UniformType::VEC3(x) => gl::Uniform3f(uniform_location, x[0], x[1], x[2]),
+            UniformType::VEC4(x) => gl::Uniform4f(uniform_location, x[0], x[1], x[2], x[3]),
+            UniformType::FLOAT(x) => gl::Uniform1f(uniform_location, x),
+            UniformType::INT(x) => gl::Uniform1i(uniform_location, x),
+            UniformType::UINT(x) => gl::Uniform1ui(uniform_location, x),
+            UniformType::BOOL(x) => gl::Uniform1i(uniform_location, x as i32),
+            UniformType::IVEC2(x) => gl::Uniform2i(uniform_location, x[0], x[1]),
+            UniformType::IVEC3(x) => gl::Uniform3i(uniform_location, x[0], x[1], x[2]),
+            UniformType::IVEC4(x) => gl::Uniform4i(uniform_location, x[0], x[1], x[2], x[3]),
+            UniformType::UVEC2(x) => gl::Uniform2ui(uniform_location, x[0], x[1]),
+            UniformType::UVEC3(x) => gl::Uniform3ui(uniform_location, x[0], x[1], x[2]),
+            UniformType::UVEC4(x) => gl::Uniform4ui(uniform_location, x[0], x[1], x[2], x[3]),
+            UniformType::MAT2(x) => gl::UniformMatrix2fv(uniform_location, 1, gl::FALSE, mat2_floats(&[x]).as_ptr()),
+            UniformType::MAT3(x) => gl::UniformMatrix3fv(uniform_location, 1, gl::FALSE, mat3_floats(&[x]).as_ptr()),
+            UniformType::MAT4(x) => gl::UniformMatrix4fv(uniform_location, 1, gl::FALSE, &x.as_array()[0][0]),
+
+            UniformType::INT_ARRAY(x) => gl::Uniform1iv(uniform_location, count, x.as_ptr()),
+            UniformType::UINT_ARRAY(x) => gl::Uniform1uiv(uniform_location, count, x.as_ptr()),
+            UniformType::BOOL_ARRAY(x) => {
+                let ints : Vec<i32> = x.iter().map(|b| *b as i32).collect();
+                gl::Uniform1iv(uniform_location, count, ints.as_ptr())
+            }
+            UniformType::FLOAT_ARRAY(x) => gl::Uniform1fv(uniform_location, count, x.as_ptr()),
+            UniformType::VEC2_ARRAY(x) => gl::Uniform2fv(uniform_location, count, x.as_ptr() as *const f32),
+            UniformType::VEC3_ARRAY(x) => gl::Uniform3fv(uniform_location, count, x.as_ptr() as *const f32),
+            UniformType::VEC4_ARRAY(x) => gl::Uniform4fv(uniform_location, count, x.as_ptr() as *const f32),
+            UniformType::IVEC2_ARRAY(x) => gl::Uniform2iv(uniform_location, count, x.as_ptr() as *const i32),
+            UniformType::IVEC3_ARRAY(x) => gl::Uniform3iv(uniform_location, count, x.as_ptr() as *const i32),
+            UniformType::IVEC4_ARRAY(x) => gl::Uniform4iv(uniform_location, count, x.as_ptr() as *const i32),
+            UniformType::UVEC2_ARRAY(x) => gl::Uniform2uiv(uniform_location, count, x.as_ptr() as *const u32),
+            UniformType::UVEC3_ARRAY(x) => gl::Uniform3uiv(uniform_location, count, x.as_ptr() as *const u32),
+            UniformType::UVEC4_ARRAY(x) => gl::Uniform4uiv(uniform_location, count, x.as_ptr() as *const u32),
+            UniformType::MAT2_ARRAY(x) => gl::UniformMatrix2fv(uniform_location, count, gl::FALSE, mat2_floats(&x).as_ptr()),
+            UniformType::MAT3_ARRAY(x) => gl::UniformMatrix3fv(uniform_location, count, gl::FALSE, mat3_floats(&x).as_ptr()),
+            UniformType::MAT4_ARRAY(x) => gl::UniformMatrix4fv(uniform_location, count, gl::FALSE, mat4_floats(&x).as_ptr()),
+        }
+    }
+}
+
+/// Values that convert directly into a `UniformType`
+pub trait IntoUniform {
+    fn into_uniform(self) -> UniformType;
+}
+
+impl IntoUniform for UniformType {
+    fn into_uniform(self) -> UniformType {
+        self
+    }
+}
+
+macro_rules! impl_into_uniform {
+    ($($t:ty => $variant:ident, $array:ident);+ $(;)?) => {
+        $(
+            impl IntoUniform for $t {
+                fn into_uniform(self) -> UniformType {
+                    UniformType::$variant(self)
+                }
+            }
+
+            impl IntoUniform for Vec<$t> {
+                fn into_uniform(self) -> UniformType {
+                    UniformType::$array(self)
+                }
             }
-            
+
+            impl IntoUniform for &[$t] {
+                fn into_uniform(self) -> UniformType {
+                    UniformType::$array(self.to_vec())
+                }
+            }
+        )+
+    }
+}
+
+impl_into_uniform! {
+    i32 => INT, INT_ARRAY;
+    u32 => UINT, UINT_ARRAY;
+    bool => BOOL, BOOL_ARRAY;
+    f32 => FLOAT, FLOAT_ARRAY;
+    [f32;2] => VEC2, VEC2_ARRAY;
+    [f32;3] => VEC3, VEC3_ARRAY;
+    [f32;4] => VEC4, VEC4_ARRAY;
+    [i32;2] => IVEC2, IVEC2_ARRAY;
+    [i32;3] => IVEC3, IVEC3_ARRAY;
+    [i32;4] => IVEC4, IVEC4_ARRAY;
+    [u32;2] => UVEC2, UVEC2_ARRAY;
+    [u32;3] => UVEC3, UVEC3_ARRAY;
+    [u32;4] => UVEC4, UVEC4_ARRAY;
+    glm::Matrix2<f32> => MAT2, MAT2_ARRAY;
+    glm::Matrix3<f32> => MAT3, MAT3_ARRAY;
+    glm::Matrix4<f32> => MAT4, MAT4_ARRAY;
+}
+
+macro_rules! impl_glm_vector {
+    ($($t:ty => $variant:ident [$($field:ident),+]);+ $(;)?) => {
+        $(
+            impl IntoUniform for $t {
+                fn into_uniform(self) -> UniformType {
+                    UniformType::$variant([$(self.$field),+])
+                }
+            }
+        )+
+    }
+}
+
+impl_glm_vector! {
+    glm::Vector2<f32> => VEC2 [x, y];
+    glm::Vector3<f32> => VEC3 [x, y, z];
+    glm::Vector4<f32> => VEC4 [x, y, z, w];
+    glm::Vector2<i32> => IVEC2 [x, y];
+    glm::Vector3<i32> => IVEC3 [x, y, z];
+    glm::Vector4<i32> => IVEC4 [x, y, z, w];
+    glm::Vector2<u32> => UVEC2 [x, y];
+    glm::Vector3<u32> => UVEC3 [x, y, z];
+    glm::Vector4<u32> => UVEC4 [x, y, z, w];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_match_their_glsl_types() {
+        let mat2 = glm::mat2(1., 0., 0., 1.);
+        let table : Vec<(UniformType, &[GLenum])> = vec![
+            (UniformType::INT(0), &[gl::INT, gl::BOOL, gl::SAMPLER_2D]),
+            (UniformType::UINT(0), &[gl::UNSIGNED_INT, gl::BOOL]),
+            (UniformType::BOOL(true), &[gl::BOOL]),
+            (UniformType::FLOAT(0.), &[gl::FLOAT]),
+            (UniformType::VEC3([0.; 3]), &[gl::FLOAT_VEC3]),
+            (UniformType::IVEC2([0; 2]), &[gl::INT_VEC2, gl::BOOL_VEC2]),
+            (UniformType::UVEC4([0; 4]), &[gl::UNSIGNED_INT_VEC4, gl::BOOL_VEC4]),
+            (UniformType::MAT2(mat2), &[gl::FLOAT_MAT2]),
+            (UniformType::FLOAT_ARRAY(vec![0.; 4]), &[gl::FLOAT]),
+            (UniformType::MAT2_ARRAY(vec![mat2]), &[gl::FLOAT_MAT2]),
+        ];
+        let all = [
+            gl::INT, gl::UNSIGNED_INT, gl::BOOL, gl::FLOAT, gl::FLOAT_VEC3, gl::INT_VEC2, gl::BOOL_VEC2,
+            gl::UNSIGNED_INT_VEC4, gl::BOOL_VEC4, gl::FLOAT_MAT2, gl::FLOAT_MAT3, gl::SAMPLER_2D
+        ];
+
+        for (value, accepted) in &table {
+            for gl_type in all {
+                assert_eq!(value.matches(gl_type), accepted.contains(&gl_type), "{} with 0x{:x}", value.name(), gl_type);
+            }
+        }
+    }
+
+    #[test]
+    fn ints_set_every_kind_of_sampler() {
+        for gl_type in [
+            gl::SAMPLER_2D, gl::SAMPLER_CUBE, gl::SAMPLER_2D_ARRAY, gl::SAMPLER_2D_SHADOW,
+            gl::SAMPLER_2D_ARRAY_SHADOW, gl::SAMPLER_CUBE_SHADOW, gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW,
+            gl::SAMPLER_2D_MULTISAMPLE, gl::SAMPLER_2D_MULTISAMPLE_ARRAY, gl::INT_SAMPLER_2D_ARRAY,
+            gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE, gl::SAMPLER_BUFFER
+        ] {
+            assert!(UniformType::INT(0).matches(gl_type), "0x{:x}", gl_type);
+            assert!(UniformType::INT_ARRAY(vec![0, 1]).matches(gl_type), "0x{:x}", gl_type);
+            assert!(!UniformType::UINT(0).matches(gl_type), "0x{:x}", gl_type);
+            assert!(!UniformType::FLOAT(0.).matches(gl_type), "0x{:x}", gl_type);
         }
-    } else {
-        println!("Uniform location {} not found", uniform_name);
-    } 
-}
\ No newline at end of file
+    }
+
+    #[test]
+    fn values_convert_into_their_variant() {
+        assert!(matches!(3.into_uniform(), UniformType::INT(3)));
+        assert!(matches!(3u32.into_uniform(), UniformType::UINT(3)));
+        assert!(matches!(true.into_uniform(), UniformType::BOOL(true)));
+        assert!(matches!(0.5.into_uniform(), UniformType::FLOAT(x) if x == 0.5));
+        assert!(matches!([1., 2.].into_uniform(), UniformType::VEC2([1., 2.])));
+        assert!(matches!([1, 2, 3].into_uniform(), UniformType::IVEC3([1, 2, 3])));
+        assert!(matches!([1u32, 2, 3, 4].into_uniform(), UniformType::UVEC4([1, 2, 3, 4])));
+        assert!(matches!(glm::vec3(1., 2., 3.).into_uniform(), UniformType::VEC3([1., 2., 3.])));
+        assert!(matches!(glm::ivec4(1, 2, 3, 4).into_uniform(), UniformType::IVEC4([1, 2, 3, 4])));
+        assert!(matches!(glm::uvec2(1, 2).into_uniform(), UniformType::UVEC2([1, 2])));
+
+        let mat4 = glm::mat4(1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.);
+        assert!(matches!(mat4.into_uniform(), UniformType::MAT4(m) if m == mat4));
+        assert!(matches!(UniformType::FLOAT(1.).into_uniform(), UniformType::FLOAT(x) if x == 1.));
+    }
+
+    #[test]
+    fn slices_and_vecs_convert_into_arrays() {
+        let kernel = vec![[0., 1., 2.], [3., 4., 5.]];
+
+        assert!(matches!(kernel.as_slice().into_uniform(), UniformType::VEC3_ARRAY(v) if v == kernel));
+        assert!(matches!(vec![1, 2].into_uniform(), UniformType::INT_ARRAY(v) if v == [1, 2]));
+        assert!(matches!(vec![true, false].into_uniform(), UniformType::BOOL_ARRAY(v) if v == [true, false]));
+
+        let array = [0.25f32; 3].as_slice().into_uniform();
+        assert_eq!((array.name(), array.len()), ("FLOAT_ARRAY", 3));
+        assert_eq!(Vec::<f32>::new().into_uniform().len(), 0);
+        assert_eq!(glm::vec2(0., 0.).into_uniform().len(), 1);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{fs, path::Path};
+
+use crate::obj_parser::FaceLayout;
+use crate::triangles::{VertexAttributeLayout, NORMAL_ATTRIB, TANGENT_ATTRIB, TEXCOORD_ATTRIB};
+
+/// Optional shader paths, each one enabled by a `#define`
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures {
+    pub normals : bool,
+    pub texcoords : bool,
+    pub normal_map : bool,
+    /// Writes the weighted blended OIT targets instead of a colour
+    pub oit : bool,
+    /// Metallic-roughness shading
+    pub pbr : bool,
+    /// Ambient light of PBR materials from an environment map
+    pub ibl : bool,
+    /// Lights are attenuated by their shadow maps
+    pub shadows : bool,
+    /// Linear colour for the post-processing passes, which tone map it
+    pub hdr_output : bool,
+    /// Ambient light is multiplied by screen space ambient occlusion
+    pub ssao : bool,
+    /// Writes the G-buffer of the deferred path instead of a colour
+    pub deferred : bool,
+    /// Full-screen passes read multisampled textures, one sample per invocation
+    pub multisample : bool,
+}
+
+impl ShaderFeatures {
+    pub fn from_attributes(layout : &VertexAttributeLayout) -> Self {
+        let has = |index| layout.contains(index);
+
+        ShaderFeatures {
+            normals : has(NORMAL_ATTRIB),
+            texcoords : has(TEXCOORD_ATTRIB),
+            // Sampling a normal map needs a tangent frame
+            normal_map : has(NORMAL_ATTRIB) && has(TEXCOORD_ATTRIB) && has(TANGENT_ATTRIB),
+            oit : false,
+            pbr : false,
+            ibl : false,
+            shadows : false,
+            hdr_output : false,
+            ssao : false,
+            deferred : false,
+            multisample : false,
+        }
+    }
+
+    pub fn from_layout(face_layout : &FaceLayout) -> Self {
+        ShaderFeatures::from_attributes(&face_layout.vertex_attrib_layout())
+    }
+
+    pub fn defines(&self) -> Vec<String> {
+        [
+            (self.normals, "HAS_NORMALS"),
+            (self.texcoords, "HAS_TEXCOORDS"),
+            (self.normal_map, "HAS_NORMAL_MAP"),
+            (self.oit, "WEIGHTED_OIT"),
+            (self.pbr, "PBR"),
+            (self.ibl, "HAS_IBL"),
+            (self.shadows, "HAS_SHADOWS"),
+            (self.hdr_output, "HDR_OUTPUT"),
+            (self.ssao, "HAS_SSAO"),
+            (self.deferred, "DEFERRED"),
+            (self.multisample, "MULTISAMPLE"),
+        ].iter().filter(|(enabled, _)| *enabled).map(|(_, name)| name.to_string()).collect()
+    }
+}
+
+/// GLSL with its includes expanded, remembering where each line came from
+#[derive(Clone, Debug)]
+pub struct ShaderSource {
+    pub code : String,
+    /// (file, line) of every line of `code`, lines start at 1
+    origins : Vec<(String, usize)>,
+    /// The file itself and every file it includes
+    pub files : Vec<String>,
+}
+
+const DEFINES_ORIGIN : &str = "<defines>";
+
+fn include_path(line : &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+
+    rest.strip_prefix('"')?.strip_suffix('"')
+}
+
+impl ShaderSource {
+    /// Expands `#include "file"` (relative to the including file) and adds
+    /// `#define`s right after the `#version` line, or first without one
+    pub fn load(path : &str, defines : &[String]) -> Result<Self, String> {
+        let mut source = ShaderSource { code : String::new(), origins : Vec::new(), files : Vec::new() };
+        let mut stack = Vec::new();
+
+        source.expand(path, defines, &mut stack)?;
+
+        if !defines.is_empty() && !source.origins.iter().any(|(file, _)| file == DEFINES_ORIGIN) {
+            let mut prefixed = ShaderSource { code : String::new(), origins : Vec::new(), files : Vec::new() };
+            for define in defines {
+                prefixed.push_line(&format!("#define {}", define), DEFINES_ORIGIN, 0);
+            }
+
+            source.code.insert_str(0, &prefixed.code);
+            source.origins.splice(0..0, prefixed.origins);
+        }
+
+        Ok(source)
+    }
+
+    fn push_line(&mut self, line : &str, file : &str, number : usize) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.origins.push((file.to_string(), number));
+    }
+
+    fn expand(&mut self, path : &str, defines : &[String], stack : &mut Vec<String>) -> Result<(), String> {
+        if stack.iter().any(|p| p == path) {
+            return Err(format!("Recursive include of {} from {}", path, stack.join(" -> ")));
+        }
+
+        // Included once, like #pragma once
+        if self.files.iter().any(|p| p == path) {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read shader file {}: {}", path, e))?;
+
+        self.files.push(path.to_string());
+        stack.push(path.to_string());
+
+        let is_root = stack.len() == 1;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        for (i, line) in content.lines().enumerate() {
+            if let Some(include) = include_path(line) {
+                let include = dir.join(include).to_string_lossy().into_owned();
+                self.expand(&include, defines, stack)
+                    .map_err(|e| format!("{}:{}: {}", path, i + 1, e))?;
+                continue;
+            }
+
+            self.push_line(line, path, i + 1);
+
+            if is_root && line.trim_start().starts_with("#version") {
+                for define in defines {
+                    self.push_line(&format!("#define {}", define), DEFINES_ORIGIN, 0);
+                }
+            }
+        }
+
+        stack.pop();
+
+        Ok(())
+    }
+
+    /// (file, line) of a line of the expanded code
+    pub fn origin(&self, line : usize) -> Option<(&str, usize)> {
+        self.origins.get(line.checked_sub(1)?).map(|(f, l)| (f.as_str(), *l))
+    }
+
+    /// Rewrites the `0:12` / `0(12)` line references drivers put in their
+    /// info logs to `file:line` of the original sources
+    pub fn map_log(&self, log : &str) -> String {
+        log.lines().map(|line| {
+            match log_line_number(line) {
+                Some((start, end, number)) => match self.origin(number) {
+                    Some((file, original)) => format!("{}{}:{}{}", &line[..start], file, original, &line[end..]),
+                    None => line.to_string()
+                },
+                None => line.to_string()
+            }
+        }).collect::<Vec<String>>().join("\n")
+    }
+}
+
+/// Byte range and value of the line reference in a log line. Mesa and AMD
+/// write `0:12`, NVIDIA writes `0(12)`.
+fn log_line_number(line : &str) -> Option<(usize, usize, usize)> {
+    let bytes = line.as_bytes();
+
+    for start in 0..bytes.len() {
+        if bytes[start] != b'0' || (start > 0 && bytes[start - 1].is_ascii_alphanumeric()) {
+            continue;
+        }
+
+        let Some(&open) = bytes.get(start + 1) else { break };
+        if open != b':' && open != b'(' {
+            continue;
+        }
+
+        let digits = bytes[start + 2..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            continue;
+        }
+
+        let mut end = start + 2 + digits;
+        if open == b'(' {
+            if bytes.get(end) != Some(&b')') {
+                continue;
+            }
+            end += 1;
+        }
+
+        let number = line[start + 2..start + 2 + digits].parse().ok()?;
+
+        return Some((start, end, number));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn write(dir : &Path, name : &str, content : &str) -> String {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn defines(names : &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn includes_are_expanded_once() {
+        let dir = temp_dir("preprocessor-includes");
+        let common = write(&dir, "lib/common.glsl", "float common() { return 1.0; }");
+        let lights = write(&dir, "lib/lights.glsl", "#include \"common.glsl\"\nfloat light() { return common(); }");
+        let root = write(&dir, "main.glsl", "#version 430 core\n#include \"lib/lights.glsl\"\n#include \"lib/common.glsl\"\nvoid main() {}");
+
+        let source = ShaderSource::load(&root, &[]).unwrap();
+
+        assert_eq!(source.code, "#version 430 core\nfloat common() { return 1.0; }\nfloat light() { return common(); }\nvoid main() {}\n");
+        assert_eq!(source.files, vec![root, lights, common]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recursive_includes_are_an_error() {
+        let dir = temp_dir("preprocessor-recursive");
+        let a = write(&dir, "a.glsl", "#include \"b.glsl\"");
+        let b = write(&dir, "b.glsl", "\n#include \"a.glsl\"");
+        let root = write(&dir, "main.glsl", "#version 430 core\n#include \"a.glsl\"");
+
+        let error = ShaderSource::load(&root, &[]).unwrap_err();
+
+        let expected = format!(
+            "{}:2: {}:1: {}:2: Recursive include of {} from {} -> {} -> {}", root, a, b, a, root, a, b
+        );
+        assert_eq!(error, expected);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_includes_are_an_error() {
+        let dir = temp_dir("preprocessor-missing");
+        let root = write(&dir, "main.glsl", "#version 430 core\n#include \"missing.glsl\"");
+
+        let error = ShaderSource::load(&root, &[]).unwrap_err();
+
+        assert!(error.starts_with(&format!("{}:2: Failed to read shader file", root)), "{}", error);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn defines_follow_the_version_of_the_root_only() {
+        let dir = temp_dir("preprocessor-defines");
+        write(&dir, "part.glsl", "#version 430 core\nfloat part;");
+        let root = write(&dir, "main.glsl", "// header\n#version 430 core\n#include \"part.glsl\"\nvoid main() {}");
+
+        let source = ShaderSource::load(&root, &defines(&["PBR", "HAS_SHADOWS"])).unwrap();
+
+        let lines : Vec<&str> = source.code.lines().collect();
+        assert_eq!(lines, vec![
+            "// header", "#version 430 core", "#define PBR", "#define HAS_SHADOWS",
+            "#version 430 core", "float part;", "void main() {}"
+        ]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn defines_come_first_without_a_version() {
+        let dir = temp_dir("preprocessor-no-version");
+        let root = write(&dir, "main.glsl", "void main() {}");
+
+        let source = ShaderSource::load(&root, &defines(&["PBR"])).unwrap();
+
+        assert_eq!(source.code, "#define PBR\nvoid main() {}\n");
+        assert_eq!(source.origin(1), Some((DEFINES_ORIGIN, 0)));
+        assert_eq!(source.origin(2), Some((root.as_str(), 1)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn origins_map_back_to_the_files() {
+        let dir = temp_dir("preprocessor-origins");
+        let part = write(&dir, "part.glsl", "float a;\nfloat b;");
+        let root = write(&dir, "main.glsl", "#version 430 core\n#include \"part.glsl\"\nvoid main() {}");
+
+        let source = ShaderSource::load(&root, &defines(&["PBR"])).unwrap();
+
+        assert_eq!(source.origin(0), None);
+        assert_eq!(source.origin(1), Some((root.as_str(), 1)));
+        assert_eq!(source.origin(2), Some((DEFINES_ORIGIN, 0)));
+        assert_eq!(source.origin(3), Some((part.as_str(), 1)));
+        assert_eq!(source.origin(4), Some((part.as_str(), 2)));
+        assert_eq!(source.origin(5), Some((root.as_str(), 3)));
+        assert_eq!(source.origin(6), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn logs_point_at_the_original_lines() {
+        let dir = temp_dir("preprocessor-logs");
+        let part = write(&dir, "part.glsl", "float a;\nfloat b");
+        let root = write(&dir, "main.glsl", "#version 430 core\n#include \"part.glsl\"\nvoid main() {}");
+        let source = ShaderSource::load(&root, &[]).unwrap();
+
+        // Mesa and AMD, NVIDIA, a line without a reference and one with a 0 inside a word
+        let log = "0:3(1): error: syntax error\n0(4) : error C0000: syntax error\nlinking failed\nvec30:2 stays";
+
+        assert_eq!(source.map_log(log), format!(
+            "{}:2(1): error: syntax error\n{}:3 : error C0000: syntax error\nlinking failed\nvec30:2 stays", part, root
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn log_references_past_the_source_are_left_alone() {
+        let dir = temp_dir("preprocessor-log-range");
+        let root = write(&dir, "main.glsl", "#version 430 core");
+        let source = ShaderSource::load(&root, &[]).unwrap();
+
+        assert_eq!(source.map_log("0:12: error"), "0:12: error");
+        assert_eq!(source.map_log("0:"), "0:");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
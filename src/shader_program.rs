@@ -0,0 +1,453 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}, ffi::CString};
+
+use gl::types::{GLenum, GLint, GLuint};
+
+use crate::set_uniform::{upload_uniform, IntoUniform};
+use crate::shader_preprocessor::{ShaderFeatures, ShaderSource};
+use crate::uniform_buffer::{Std140Block, BLOCK_BINDINGS};
+
+/// An active uniform or attribute as reported after linking
+#[derive(Clone, Debug)]
+pub struct ActiveVariable {
+    pub name : String,
+    pub location : GLint,
+    pub gl_type : GLenum,
+    /// Array length, 1 for non arrays
+    pub size : GLint,
+}
+
+fn info_log(object : GLuint, is_program : bool) -> String {
+    let mut log_length = 0;
+
+    unsafe {
+        if is_program {
+            gl::GetProgramiv(object, gl::INFO_LOG_LENGTH, &mut log_length);
+        } else {
+            gl::GetShaderiv(object, gl::INFO_LOG_LENGTH, &mut log_length);
+        }
+
+        let log = vec![0u8; log_length.max(1) as usize];
+
+        if is_program {
+            gl::GetProgramInfoLog(object, log_length, std::ptr::null_mut(), log.as_ptr() as *mut i8);
+        } else {
+            gl::GetShaderInfoLog(object, log_length, std::ptr::null_mut(), log.as_ptr() as *mut i8);
+        }
+
+        String::from_utf8_lossy(&log).trim_end_matches('\0').to_string()
+    }
+}
+
+pub fn compile_source(source : &ShaderSource, shader_type : GLenum) -> Result<GLuint, String> {
+    let name = source.files.first().map(|f| f.as_str()).unwrap_or("");
+    let c_str = CString::new(source.code.as_bytes())
+        .map_err(|_| format!("Shader file {} contains a nul byte", name))?;
+
+    let shader = unsafe { gl::CreateShader(shader_type) };
+    let mut success = gl::FALSE as i32;
+
+    unsafe {
+        gl::ShaderSource(shader, 1, &c_str.as_ptr(), std::ptr::null());
+        gl::CompileShader(shader);
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+    }
+
+    if success != gl::TRUE as i32 {
+        let log_string = source.map_log(&info_log(shader, false));
+        unsafe { gl::DeleteShader(shader) };
+
+        return Err(format!("Failed to compile shader {}: {}", name, log_string));
+    }
+
+    Ok(shader)
+}
+
+pub fn compile_shader(source_path : &str, shader_type : GLenum) -> Result<GLuint, String> {
+    compile_source(&ShaderSource::load(source_path, &[])?, shader_type)
+}
+
+/// Queries every active uniform or attribute of a linked program
+fn active_variables(program : GLuint, uniforms : bool) -> HashMap<String, ActiveVariable> {
+    let (count_param, length_param) = if uniforms {
+        (gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH)
+    } else {
+        (gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH)
+    };
+
+    let mut count = 0;
+    let mut max_length = 0;
+    unsafe {
+        gl::GetProgramiv(program, count_param, &mut count);
+        gl::GetProgramiv(program, length_param, &mut max_length);
+    }
+
+    let mut variables = HashMap::new();
+
+    for i in 0..count as GLuint {
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let mut length = 0;
+        let mut size = 0;
+        let mut gl_type = 0;
+
+        let location = unsafe {
+            if uniforms {
+                gl::GetActiveUniform(program, i, max_length, &mut length, &mut size, &mut gl_type, name.as_mut_ptr() as *mut i8);
+            } else {
+                gl::GetActiveAttrib(program, i, max_length, &mut length, &mut size, &mut gl_type, name.as_mut_ptr() as *mut i8);
+            }
+
+            name.truncate(length as usize);
+            let c_name = CString::new(name.clone()).unwrap();
+
+            if uniforms {
+                gl::GetUniformLocation(program, c_name.as_ptr())
+            } else {
+                gl::GetAttribLocation(program, c_name.as_ptr())
+            }
+        };
+
+        // Members of uniform blocks have no location, they are set through a buffer
+        if uniforms && location == -1 {
+            continue;
+        }
+
+        // Arrays are reported as `name[0]`, look them up by `name` as well
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let variable = ActiveVariable { name : name.clone(), location, gl_type, size };
+
+        if let Some(base) = name.strip_suffix("[0]") {
+            variables.insert(base.to_string(), variable.clone());
+        }
+        variables.insert(name, variable);
+    }
+
+    variables
+}
+
+/// An active uniform block and the offsets GL chose for its members
+#[derive(Clone, Debug)]
+pub struct UniformBlock {
+    pub name : String,
+    pub index : GLuint,
+    pub data_size : usize,
+    pub members : HashMap<String, usize>,
+}
+
+fn uniform_name(program : GLuint, index : GLuint, max_length : GLint) -> String {
+    let mut name = vec![0u8; max_length.max(1) as usize];
+    let mut length = 0;
+
+    unsafe {
+        gl::GetActiveUniformName(program, index, max_length, &mut length, name.as_mut_ptr() as *mut i8);
+    }
+    name.truncate(length as usize);
+
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+/// Queries every active uniform block, and binds the shared ones to their binding point
+fn uniform_blocks(program : GLuint) -> HashMap<String, UniformBlock> {
+    let mut count = 0;
+    let mut max_name_length = 0;
+    let mut max_uniform_length = 0;
+    unsafe {
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH, &mut max_name_length);
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_uniform_length);
+    }
+
+    let mut blocks = HashMap::new();
+
+    for index in 0..count as GLuint {
+        let mut name = vec![0u8; max_name_length.max(1) as usize];
+        let mut length = 0;
+        let mut data_size = 0;
+        let mut num_members = 0;
+
+        unsafe {
+            gl::GetActiveUniformBlockName(program, index, max_name_length, &mut length, name.as_mut_ptr() as *mut i8);
+            gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut data_size);
+            gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS, &mut num_members);
+        }
+        name.truncate(length as usize);
+        let name = String::from_utf8_lossy(&name).into_owned();
+
+        let mut member_indices = vec![0; num_members.max(0) as usize];
+        unsafe {
+            gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES, member_indices.as_mut_ptr());
+        }
+
+        let members = member_indices.iter().map(|&member| {
+            let mut offset = 0;
+            let member = member as GLuint;
+            unsafe { gl::GetActiveUniformsiv(program, 1, &member, gl::UNIFORM_OFFSET, &mut offset) };
+
+            // Members of blocks with an instance name are reported as `Block.member`
+            let member_name = uniform_name(program, member, max_uniform_length);
+            let member_name = member_name.strip_prefix(&format!("{}.", name))
+                .map(|n| n.to_string())
+                .unwrap_or(member_name);
+
+            (member_name, offset as usize)
+        }).collect();
+
+        if let Some((_, binding)) = BLOCK_BINDINGS.iter().find(|(block, _)| *block == name) {
+            unsafe { gl::UniformBlockBinding(program, index, *binding) };
+        }
+
+        blocks.insert(name.clone(), UniformBlock { name, index, data_size : data_size as usize, members });
+    }
+
+    blocks
+}
+
+/// A linked program with the locations of its active uniforms and attributes
+pub struct ShaderProgram {
+    id : GLuint,
+    uniforms : HashMap<String, ActiveVariable>,
+    attributes : HashMap<String, ActiveVariable>,
+    blocks : HashMap<String, UniformBlock>,
+    warned : RefCell<HashSet<String>>,
+}
+
+impl ShaderProgram {
+    /// Links already compiled shaders, the shaders are deleted either way
+    pub fn link(shaders : &[GLuint]) -> Result<Self, String> {
+        let id = unsafe { gl::CreateProgram() };
+        let mut success = gl::FALSE as i32;
+
+        unsafe {
+            for shader in shaders {
+                gl::AttachShader(id, *shader);
+            }
+            gl::LinkProgram(id);
+            gl::GetProgramiv(id, gl::LINK_STATUS, &mut success);
+
+            // The program keeps what it needs once linked
+            for shader in shaders {
+                gl::DeleteShader(*shader);
+            }
+        }
+
+        if success != gl::TRUE as i32 {
+            let log_string = info_log(id, true);
+            unsafe { gl::DeleteProgram(id) };
+
+            return Err(format!("Failed to link shader program: {}", log_string));
+        }
+
+        Ok(ShaderProgram {
+            id,
+            uniforms : active_variables(id, true),
+            attributes : active_variables(id, false),
+            blocks : uniform_blocks(id),
+            warned : RefCell::new(HashSet::new()),
+        })
+    }
+
+    pub fn from_files(vertex_path : &str, fragment_path : &str) -> Result<Self, String> {
+        ShaderProgram::from_sources(
+            &ShaderSource::load(vertex_path, &[])?,
+            &ShaderSource::load(fragment_path, &[])?
+        )
+    }
+
+    pub fn from_sources(vertex_source : &ShaderSource, fragment_source : &ShaderSource) -> Result<Self, String> {
+        let vertex_shader = compile_source(vertex_source, gl::VERTEX_SHADER)?;
+        let fragment_shader = match compile_source(fragment_source, gl::FRAGMENT_SHADER) {
+            Ok(shader) => shader,
+            Err(e) => {
+                unsafe { gl::DeleteShader(vertex_shader) };
+                return Err(e);
+            }
+        };
+
+        ShaderProgram::link(&[vertex_shader, fragment_shader])
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn use_program(&self) {
+        unsafe { gl::UseProgram(self.id) };
+    }
+
+    pub fn uniforms(&self) -> impl Iterator<Item = &ActiveVariable> {
+        self.uniforms.iter().filter(|(k, v)| **k == v.name).map(|(_, v)| v)
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = &ActiveVariable> {
+        self.attributes.iter().filter(|(k, v)| **k == v.name).map(|(_, v)| v)
+    }
+
+    pub fn uniform(&self, name : &str) -> Option<&ActiveVariable> {
+        self.uniforms.get(name)
+    }
+
+    pub fn attribute(&self, name : &str) -> Option<&ActiveVariable> {
+        self.attributes.get(name)
+    }
+
+    pub fn uniform_block(&self, name : &str) -> Option<&UniformBlock> {
+        self.blocks.get(name)
+    }
+
+    /// Compares the layout of `block` with the one GL chose for the block `name`.
+    /// A block the program does not use always matches.
+    pub fn check_block(&self, name : &str, block : &Std140Block) -> Result<(), String> {
+        let Some(active) = self.blocks.get(name) else {
+            return Ok(());
+        };
+
+        for (member, gl_offset) in &active.members {
+            match block.offset(member) {
+                Some(offset) if offset == *gl_offset => (),
+                Some(offset) => return Err(format!(
+                    "Uniform block {}: {} is at offset {}, GL expects {}", name, member, offset, gl_offset
+                )),
+                None => return Err(format!("Uniform block {}: {} is not written", name, member))
+            }
+        }
+
+        if block.size() < active.data_size {
+            return Err(format!(
+                "Uniform block {} is {} bytes, GL expects {}", name, block.size(), active.data_size
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn warn_once(&self, key : &str, msg : String) {
+        if self.warned.borrow_mut().insert(key.to_string()) {
+            println!("{}", msg);
+        }
+    }
+
+    /// Sets a uniform of this program, which must be in use. Unknown uniforms
+    /// (usually optimized out) and type mismatches are reported once.
+    pub fn set_uniform<T : IntoUniform>(&self, uniform_name : &str, uniform_value : T) {
+        let uniform_value = uniform_value.into_uniform();
+
+        let Some(uniform) = self.uniforms.get(uniform_name) else {
+            self.warn_once(uniform_name, format!("Uniform {} is not active in program {}", uniform_name, self.id));
+            return;
+        };
+
+        if !uniform_value.matches(uniform.gl_type) {
+            self.warn_once(uniform_name, format!(
+                "Uniform {} has GL type 0x{:X}, cannot set it from {}",
+                uniform_name, uniform.gl_type, uniform_value.name()
+            ));
+            return;
+        }
+
+        // GL ignores the elements past the declared size
+        if uniform_value.len() > uniform.size as usize {
+            self.warn_once(uniform_name, format!(
+                "Uniform {} holds {} elements, {} given",
+                uniform_name, uniform.size, uniform_value.len()
+            ));
+        }
+
+        upload_uniform(uniform.location, uniform_value);
+    }
+}
+
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.id) };
+    }
+}
+
+/// Shader paths are interned to indicies into `ShaderCache::paths`, so
+/// looking up a program never allocates
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct PermutationKey {
+    vertex_path : usize,
+    fragment_path : usize,
+    features : ShaderFeatures,
+}
+
+/// Programs compiled for each combination of shader files and features
+#[derive(Default)]
+pub struct ShaderCache {
+    programs : HashMap<PermutationKey, ShaderProgram>,
+    paths : Vec<String>,
+    path_ids : HashMap<String, usize>,
+    files : HashSet<String>,
+}
+
+impl ShaderCache {
+    pub fn new() -> Self {
+        ShaderCache::default()
+    }
+
+    fn intern(&mut self, path : &str) -> usize {
+        if let Some(id) = self.path_ids.get(path) {
+            return *id;
+        }
+
+        self.paths.push(path.to_string());
+        self.path_ids.insert(path.to_string(), self.paths.len() - 1);
+
+        self.paths.len() - 1
+    }
+
+    fn key(&self, vertex_path : &str, fragment_path : &str, features : ShaderFeatures) -> Option<PermutationKey> {
+        Some(PermutationKey {
+            vertex_path : *self.path_ids.get(vertex_path)?,
+            fragment_path : *self.path_ids.get(fragment_path)?,
+            features
+        })
+    }
+
+    fn compile(&mut self, key : &PermutationKey) -> Result<ShaderProgram, String> {
+        let defines = key.features.defines();
+        let vertex_source = ShaderSource::load(&self.paths[key.vertex_path], &defines)?;
+        let fragment_source = ShaderSource::load(&self.paths[key.fragment_path], &defines)?;
+
+        self.files.extend(vertex_source.files.iter().cloned());
+        self.files.extend(fragment_source.files.iter().cloned());
+
+        ShaderProgram::from_sources(&vertex_source, &fragment_source)
+    }
+
+    /// The program for this permutation, compiled on first use
+    pub fn get_or_compile(&mut self, vertex_path : &str, fragment_path : &str, features : ShaderFeatures) -> Result<&ShaderProgram, String> {
+        let key = PermutationKey { vertex_path : self.intern(vertex_path), fragment_path : self.intern(fragment_path), features };
+
+        if !self.programs.contains_key(&key) {
+            let program = self.compile(&key)?;
+            self.programs.insert(key, program);
+        }
+
+        Ok(&self.programs[&key])
+    }
+
+    /// The program for this permutation if it was already compiled
+    pub fn get(&self, vertex_path : &str, fragment_path : &str, features : ShaderFeatures) -> Option<&ShaderProgram> {
+        self.programs.get(&self.key(vertex_path, fragment_path, features)?)
+    }
+
+    /// Recompiles every cached permutation. Nothing is replaced unless all of them succeed.
+    pub fn reload(&mut self) -> Result<(), String> {
+        let keys : Vec<PermutationKey> = self.programs.keys().copied().collect();
+        let mut programs = HashMap::new();
+
+        for key in keys {
+            let program = self.compile(&key)?;
+            programs.insert(key, program);
+        }
+
+        self.programs = programs;
+
+        Ok(())
+    }
+
+    /// Every shader file and include the cached programs were built from
+    pub fn files(&self) -> impl Iterator<Item = &String> {
+        self.files.iter()
+    }
+}
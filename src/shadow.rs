@@ -0,0 +1,433 @@
+use std::cell::Cell;
+
+use gl::types::{GLenum, GLint, GLuint};
+use glm::{Matrix4, Vector3, Vector4};
+
+use crate::moving::BoundingBox;
+use crate::shader_program::ShaderProgram;
+use crate::uniform_buffer::{Light, ShadowsBlock, MAX_LIGHTS, MAX_SHADOW_MAPS};
+
+pub const SHADOW_VERTEX_SHADER_PATH : &str = "shaders/shadow_vertex.glsl";
+pub const SHADOW_FRAGMENT_SHADER_PATH : &str = "shaders/shadow_fragment.glsl";
+
+pub const SHADOW_SAMPLER : &str = "shadowMaps";
+/// Texture unit of the shadow maps, above the ones materials and IBL use
+pub const SHADOW_MAP_UNIT : i32 = 13;
+
+pub const MAX_CASCADES : usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct ShadowSettings {
+    /// Width and height of each map in texels
+    pub map_size : i32,
+    /// Maps splitting the view for each directional light, at most `MAX_CASCADES`
+    pub cascades : usize,
+    /// Blend between logarithmic (1) and uniform (0) cascade splits
+    pub split_lambda : f32,
+    /// Subtracted from the depth of the receiver, in depth buffer units
+    pub bias : f32,
+    /// Receivers are moved along their normal by up to this, in mesh units
+    pub normal_bias : f32,
+    /// Slope factor of the polygon offset of the depth pass
+    pub slope_bias : f32,
+    /// The filter reads (2 * radius + 1)² texels
+    pub pcf_radius : i32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            map_size : 1024,
+            cascades : MAX_CASCADES,
+            split_lambda : 0.75,
+            bias : 0.0005,
+            normal_bias : 0.,
+            slope_bias : 2.,
+            pcf_radius : 1,
+        }
+    }
+}
+
+fn vec3(p : [f32; 3]) -> Vector3<f32> {
+    Vector3::new(p[0], p[1], p[2])
+}
+
+fn transform_point(m : &Matrix4<f32>, p : Vector3<f32>) -> Vector3<f32> {
+    let v = *m * Vector4::new(p.x, p.y, p.z, 1.);
+
+    Vector3::new(v.x / v.w, v.y / v.w, v.z / v.w)
+}
+
+/// GL orthographic projection, the box is in view space looking down -z
+fn ortho(left : f32, right : f32, bottom : f32, top : f32, near : f32, far : f32) -> Matrix4<f32> {
+    Matrix4::new(
+        Vector4::new(2. / (right - left), 0., 0., 0.),
+        Vector4::new(0., 2. / (top - bottom), 0., 0.),
+        Vector4::new(0., 0., -2. / (far - near), 0.),
+        Vector4::new(-(right + left) / (right - left), -(top + bottom) / (top - bottom), -(far + near) / (far - near), 1.)
+    )
+}
+
+/// Any up vector not parallel to `direction`
+fn up_for(direction : Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() < 0.99 { Vector3::new(0., 1., 0.) } else { Vector3::new(1., 0., 0.) }
+}
+
+/// Corners of the slices of the camera frustum for each cascade, split along
+/// the view depth between the practical split scheme and uniform splits
+fn cascade_corners(camera : &Matrix4<f32>, cascades : usize, lambda : f32) -> Vec<[Vector3<f32>; 8]> {
+    let inverse = glm::inverse(camera);
+
+    let rays : Vec<(Vector3<f32>, Vector3<f32>)> = [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)].iter()
+        .map(|&(x, y)| (
+            transform_point(&inverse, Vector3::new(x, y, -1.)),
+            transform_point(&inverse, Vector3::new(x, y, 1.))
+        ))
+        .collect();
+
+    // Near and far distances of a perspective camera, the eye of an orthographic one is at infinity
+    let eye = inverse * Vector4::new(0., 0., -1., 0.);
+    let ratio = if eye.w.abs() > 1e-6 {
+        let eye = Vector3::new(eye.x / eye.w, eye.y / eye.w, eye.z / eye.w);
+        let near = glm::length(transform_point(&inverse, Vector3::new(0., 0., -1.)) - eye);
+        let far = glm::length(transform_point(&inverse, Vector3::new(0., 0., 1.)) - eye);
+        far / near
+    } else {
+        1.
+    };
+
+    // Fraction of the way from the near plane to the far plane
+    let split = |i : usize| {
+        let s = i as f32 / cascades as f32;
+        let logarithmic = (ratio.powf(s) - 1.) / (ratio - 1.);
+
+        if ratio.is_finite() && ratio > 1. + 1e-4 { lambda * logarithmic + (1. - lambda) * s } else { s }
+    };
+
+    (0..cascades).map(|i| {
+        let (start, end) = (split(i), split(i + 1));
+        let mut corners = [Vector3::new(0., 0., 0.); 8];
+
+        for (k, (near, far)) in rays.iter().enumerate() {
+            corners[k] = *near + (*far - *near) * start;
+            corners[k + 4] = *near + (*far - *near) * end;
+        }
+        corners
+    }).collect()
+}
+
+/// Orthographic light matrix holding the sphere around `corners`, and the
+/// scene along the light so casters outside the view still cast into it
+fn cascade_matrix(direction : Vector3<f32>, corners : &[Vector3<f32>; 8], scene : &BoundingBox, map_size : i32) -> Matrix4<f32> {
+    let center = corners.iter().fold(Vector3::new(0., 0., 0.), |sum, c| sum + *c) / 8.;
+    let radius = corners.iter().map(|c| glm::length(*c - center)).fold(0., f32::max);
+
+    // Looking along the light from the origin, so texels stay put as the cascade moves
+    let view = glm::ext::look_at(Vector3::new(0., 0., 0.), -direction, up_for(direction));
+
+    // A sphere keeps its size when the camera turns, snapping to texels keeps
+    // the edges of the shadows from crawling when it moves. Half a texel of
+    // margin keeps the sphere in the map after snapping.
+    let radius = radius * map_size as f32 / (map_size - 1).max(1) as f32;
+    let texel = 2. * radius / map_size as f32;
+    let light_center = transform_point(&view, center);
+    let x = (light_center.x / texel).round() * texel;
+    let y = (light_center.y / texel).round() * texel;
+
+    let scene_z = transform_point(&view, vec3(scene.center())).z;
+    let scene_radius = scene.radius();
+    let near = -(light_center.z + radius).max(scene_z + scene_radius);
+    let far = -(light_center.z - radius).min(scene_z - scene_radius);
+
+    ortho(x - radius, x + radius, y - radius, y + radius, near, far) * view
+}
+
+/// Perspective light matrix of a spot light covering its cone and the scene in front of it
+fn spot_matrix(light : &Light, scene : &BoundingBox) -> Matrix4<f32> {
+    let position = Vector3::new(light.position[0], light.position[1], light.position[2]);
+    let direction = glm::normalize(vec3(light.spot_direction));
+
+    let distance = glm::length(vec3(scene.center()) - position);
+    let far = (distance + scene.radius()).max(1e-3);
+    let near = (distance - scene.radius()).max(far * 1e-3);
+
+    let fov = (2. * light.spot_cutoff.clamp(-1., 1.).acos()).min(std::f32::consts::PI * 0.99);
+    let view = glm::ext::look_at(position, position + direction, up_for(direction));
+
+    glm::ext::perspective(fov, 1., near, far) * view
+}
+
+/// Depth maps of the shadow casting lights, in one texture array. Directional
+/// lights get a cascade of orthographic maps fitted to the view, spot lights a
+/// perspective map of their cone. Point lights do not cast shadows.
+pub struct ShadowMaps {
+    texture : GLuint,
+    fbo : GLuint,
+    settings : ShadowSettings,
+    /// Running out of maps is reported once, not every frame
+    warned : Cell<bool>,
+}
+
+impl ShadowMaps {
+    pub fn new(settings : ShadowSettings) -> Result<Self, String> {
+        let mut texture = 0;
+        let mut fbo = 0;
+        let size = settings.map_size;
+
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, texture);
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY, 0, gl::DEPTH_COMPONENT32F as GLint, size, size, MAX_SHADOW_MAPS as i32,
+                0, gl::DEPTH_COMPONENT, gl::FLOAT, std::ptr::null()
+            );
+            // Hardware comparison, with linear filtering it blends four of them
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            // Lit outside of the maps
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as GLint);
+            gl::TexParameterfv(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BORDER_COLOR, [1f32; 4].as_ptr());
+
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, texture, 0, 0);
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+        }
+
+        let maps = ShadowMaps { texture, fbo, settings, warned : Cell::new(false) };
+
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) };
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Shadow map framebuffer is incomplete: 0x{:X}", status));
+        }
+
+        Ok(maps)
+    }
+
+    pub fn settings(&self) -> &ShadowSettings {
+        &self.settings
+    }
+
+    /// Light matrices for the shadow casting `lights`, fitted to the view of
+    /// `camera` and to `scene`. When `MAX_SHADOW_MAPS` runs out, a directional
+    /// light gets the cascades that are left and later lights get no shadows.
+    pub fn shadows_block(&self, lights : &[Light], camera : &Matrix4<f32>, scene : &BoundingBox) -> ShadowsBlock {
+        let settings = &self.settings;
+        let cascades = settings.cascades.clamp(1, MAX_CASCADES);
+
+        let mut block = ShadowsBlock {
+            bias : settings.bias,
+            normal_bias : settings.normal_bias,
+            pcf_radius : settings.pcf_radius,
+            ..ShadowsBlock::default()
+        };
+
+        for light in lights.iter().take(MAX_LIGHTS) {
+            let first = block.matrices.len();
+            let left = MAX_SHADOW_MAPS - first;
+
+            if light.casts_shadows && (light.is_directional() || light.is_spot()) && left == 0 && !self.warned.replace(true) {
+                println!("Only {} shadow maps fit, some shadow casting lights get no shadows", MAX_SHADOW_MAPS);
+            }
+
+            if light.casts_shadows && light.is_directional() && left > 0 {
+                let direction = glm::normalize(Vector3::new(light.position[0], light.position[1], light.position[2]));
+
+                for corners in cascade_corners(camera, cascades.min(left), settings.split_lambda) {
+                    block.matrices.push(cascade_matrix(direction, &corners, scene, settings.map_size));
+                }
+            } else if light.casts_shadows && light.is_spot() && left > 0 {
+                block.matrices.push(spot_matrix(light, scene));
+            }
+
+            block.light_maps.push([first as i32, (block.matrices.len() - first) as i32]);
+        }
+
+        block
+    }
+
+    /// Renders the depth of what `draw` draws into each map of `block`. `program`
+    /// is the depth only program, `lightMatrix` is set for each map.
+    pub fn render(&self, program : &ShaderProgram, block : &ShadowsBlock, draw : impl Fn()) {
+        let mut viewport = [0; 4];
+        let mut previous_fbo = 0;
+
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl::Viewport(0, 0, self.settings.map_size, self.settings.map_size);
+            gl::Enable(gl::POLYGON_OFFSET_FILL);
+            gl::PolygonOffset(self.settings.slope_bias, 1.);
+        }
+
+        program.use_program();
+
+        for (layer, matrix) in block.matrices.iter().enumerate() {
+            unsafe {
+                gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, self.texture, 0, layer as GLint);
+                gl::Clear(gl::DEPTH_BUFFER_BIT);
+            }
+            program.set_uniform("lightMatrix", *matrix);
+
+            draw();
+        }
+
+        unsafe {
+            gl::Disable(gl::POLYGON_OFFSET_FILL);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous_fbo as GLuint);
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        }
+    }
+
+    /// Binds the maps to `SHADOW_MAP_UNIT`
+    pub fn bind(&self) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + SHADOW_MAP_UNIT as GLenum);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+}
+
+impl Drop for ShadowMaps {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEAR : f32 = 0.1;
+    const FAR : f32 = 10.;
+
+    fn camera() -> Matrix4<f32> {
+        glm::ext::perspective(std::f32::consts::PI / 3., 1.5, NEAR, FAR)
+    }
+
+    fn scene() -> BoundingBox {
+        BoundingBox { x_min : -1., x_max : 1., y_min : -1., y_max : 1., z_min : -3., z_max : -1. }
+    }
+
+    fn assert_close(a : Vector3<f32>, b : Vector3<f32>) {
+        assert!(glm::length(a - b) < 1e-4 * glm::length(b).max(1.), "{:?} != {:?}", a, b);
+    }
+
+    /// View depths of the near plane of each cascade and the far plane of the last
+    fn split_depths(corners : &[[Vector3<f32>; 8]]) -> Vec<f32> {
+        corners.iter().map(|c| -c[0].z).chain(corners.last().map(|c| -c[4].z)).collect()
+    }
+
+    #[test]
+    fn cascades_cover_the_frustum_without_gaps() {
+        let camera = camera();
+        let inverse = glm::inverse(&camera);
+        let corners = cascade_corners(&camera, 4, 0.75);
+
+        assert_eq!(corners.len(), 4);
+        for (k, &(x, y)) in [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)].iter().enumerate() {
+            assert_close(corners[0][k], transform_point(&inverse, Vector3::new(x, y, -1.)));
+            assert_close(corners[3][k + 4], transform_point(&inverse, Vector3::new(x, y, 1.)));
+        }
+        for pair in corners.windows(2) {
+            for k in 0..4 {
+                assert_close(pair[0][k + 4], pair[1][k]);
+            }
+        }
+    }
+
+    #[test]
+    fn cascade_splits_blend_logarithmic_and_uniform() {
+        let camera = camera();
+
+        let uniform = split_depths(&cascade_corners(&camera, 4, 0.));
+        let logarithmic = split_depths(&cascade_corners(&camera, 4, 1.));
+
+        for i in 0..=4 {
+            let s = i as f32 / 4.;
+            let expected = NEAR + (FAR - NEAR) * s;
+            assert!((uniform[i] - expected).abs() < 1e-3 * expected, "{} != {}", uniform[i], expected);
+            let expected = NEAR * (FAR / NEAR).powf(s);
+            assert!((logarithmic[i] - expected).abs() < 1e-3 * expected, "{} != {}", logarithmic[i], expected);
+        }
+    }
+
+    #[test]
+    fn orthographic_cameras_split_uniformly() {
+        let camera = ortho(-1., 1., -1., 1., 1., 5.);
+
+        let depths = split_depths(&cascade_corners(&camera, 4, 0.75));
+
+        for (i, depth) in depths.iter().enumerate() {
+            assert!((depth - (1. + i as f32)).abs() < 1e-4, "{:?}", depths);
+        }
+    }
+
+    #[test]
+    fn cascade_matrix_holds_the_slice_and_the_scene() {
+        let direction = glm::normalize(Vector3::new(1., 2., 0.5));
+        let scene = scene();
+
+        for corners in cascade_corners(&camera(), 4, 0.75) {
+            let matrix = cascade_matrix(direction, &corners, &scene, 1024);
+
+            for corner in corners {
+                let p = transform_point(&matrix, corner);
+                assert!([p.x, p.y, p.z].iter().all(|c| c.abs() <= 1. + 1e-4), "{:?} is outside the map", p);
+            }
+            // Casters behind the view along the light still land in the depth range
+            for x in [scene.x_min, scene.x_max] {
+                for y in [scene.y_min, scene.y_max] {
+                    for z in [scene.z_min, scene.z_max] {
+                        let p = transform_point(&matrix, Vector3::new(x, y, z));
+                        assert!(p.z.abs() <= 1. + 1e-4, "scene corner at depth {}", p.z);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cascade_matrix_looks_along_the_light() {
+        let direction = glm::normalize(Vector3::new(-0.3, 1., 0.2));
+        let corners = cascade_corners(&camera(), 2, 0.75)[0];
+        let matrix = cascade_matrix(direction, &corners, &scene(), 1024);
+
+        let center = corners.iter().fold(Vector3::new(0., 0., 0.), |sum, c| sum + *c) / 8.;
+        // `direction` points at the light, so closer to it is less deep
+        let toward = transform_point(&matrix, center + direction * 0.1);
+        let away = transform_point(&matrix, center - direction * 0.1);
+
+        assert!(toward.z < away.z);
+        assert!((toward.x - away.x).abs() < 1e-4 && (toward.y - away.y).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cascade_matrix_snaps_to_texels() {
+        let direction = glm::normalize(Vector3::new(0.4, 1., -0.7));
+        let map_size = 512;
+
+        for corners in cascade_corners(&camera(), 4, 0.75) {
+            let matrix = cascade_matrix(direction, &corners, &scene(), map_size);
+
+            // The light space origin lands on a texel corner of the map
+            let origin = transform_point(&matrix, Vector3::new(0., 0., 0.));
+            for c in [origin.x, origin.y] {
+                let texels = c * map_size as f32 / 2.;
+                assert!((texels - texels.round()).abs() < 1e-2, "origin is {} texels off the grid", texels);
+            }
+        }
+    }
+}
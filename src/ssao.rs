@@ -0,0 +1,256 @@
+use gl::types::{GLenum, GLint, GLuint};
+use glm::Matrix4;
+
+use crate::fullscreen::EmptyVertexArray;
+use crate::shader_program::ShaderProgram;
+
+pub const SSAO_PREPASS_VERTEX_SHADER_PATH : &str = "shaders/ssao_prepass_vertex.glsl";
+pub const SSAO_PREPASS_FRAGMENT_SHADER_PATH : &str = "shaders/ssao_prepass_fragment.glsl";
+pub const SSAO_SHADER_PATH : &str = "shaders/ssao.glsl";
+pub const SSAO_BLUR_SHADER_PATH : &str = "shaders/ssao_blur.glsl";
+
+pub const SSAO_SAMPLER : &str = "ssaoMap";
+/// Texture unit of the ambient occlusion, above the shadow maps
+pub const SSAO_UNIT : i32 = 14;
+
+pub const MAX_KERNEL_SIZE : usize = 64;
+/// Width and height of the tiled rotation texture, the blur covers as many pixels
+const NOISE_SIZE : i32 = 4;
+
+#[derive(Clone, Debug)]
+pub struct SsaoSettings {
+    /// Radius of the sampled hemisphere, in view space units
+    pub radius : f32,
+    /// Depth difference below which a sample does not occlude, against self shadowing acne
+    pub bias : f32,
+    /// 0 leaves the ambient light alone, 1 removes it where fully occluded
+    pub intensity : f32,
+    /// Samples per pixel, at most `MAX_KERNEL_SIZE`
+    pub kernel_size : usize,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        SsaoSettings {
+            radius : 0.1,
+            bias : 0.005,
+            intensity : 1.,
+            kernel_size : 32,
+        }
+    }
+}
+
+/// Xorshift, seeded so the kernel and noise are the same on every run
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// Points in the unit hemisphere around +z, more of them close to the center
+fn hemisphere_kernel(size : usize, random : &mut Random) -> Vec<[f32; 3]> {
+    (0..size).map(|i| {
+        let p = [random.next() * 2. - 1., random.next() * 2. - 1., random.next()];
+        let length = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt().max(1e-4);
+
+        let t = i as f32 / size as f32;
+        let scale = random.next() * (0.1 + 0.9 * t * t) / length;
+
+        p.map(|c| c * scale)
+    }).collect()
+}
+
+fn texture(internal_format : GLenum, format : GLenum, gl_type : GLenum, width : i32, height : i32, filter : GLenum) -> GLuint {
+    let mut id = 0;
+
+    unsafe {
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as GLint);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as GLint, width, height, 0, format, gl_type, std::ptr::null());
+    }
+
+    id
+}
+
+fn framebuffer(color : GLuint, depth : Option<GLuint>) -> Result<GLuint, String> {
+    let mut fbo = 0;
+
+    let status = unsafe {
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, color, 0);
+        if let Some(depth) = depth {
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, depth, 0);
+        }
+
+        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        status
+    };
+
+    if status != gl::FRAMEBUFFER_COMPLETE {
+        unsafe { gl::DeleteFramebuffers(1, &fbo) };
+        return Err(format!("SSAO framebuffer is incomplete: 0x{:X}", status));
+    }
+
+    Ok(fbo)
+}
+
+/// Screen space ambient occlusion. A prepass renders the view space normals
+/// and depth of the opaque draws, the occlusion of a hemisphere kernel around
+/// each pixel is then estimated from them and blurred. Materials multiply
+/// their ambient light by the result.
+pub struct Ssao {
+    settings : SsaoSettings,
+    kernel : Vec<[f32; 3]>,
+    /// View space normals and depth
+    prepass_fbo : GLuint,
+    normals : GLuint,
+    depth : GLuint,
+    /// Raw occlusion, then the blurred ambient factor
+    occlusion_fbo : GLuint,
+    occlusion : GLuint,
+    blur_fbo : GLuint,
+    ambient : GLuint,
+    noise : GLuint,
+    width : i32,
+    height : i32,
+    screen : EmptyVertexArray,
+}
+
+impl Ssao {
+    pub fn new(width : i32, height : i32, settings : SsaoSettings) -> Result<Self, String> {
+        if settings.kernel_size == 0 || settings.kernel_size > MAX_KERNEL_SIZE {
+            return Err(format!("SSAO kernel size must be between 1 and {}, got {}", MAX_KERNEL_SIZE, settings.kernel_size));
+        }
+
+        let mut random = Random(0x9E37_79B9);
+        let kernel = hemisphere_kernel(settings.kernel_size, &mut random);
+
+        // Rotations around z, the texture repeats over the screen
+        let rotations : Vec<f32> = (0..NOISE_SIZE * NOISE_SIZE)
+            .flat_map(|_| [random.next() * 2. - 1., random.next() * 2. - 1., 0.])
+            .collect();
+        let noise = texture(gl::RGB16F, gl::RGB, gl::FLOAT, NOISE_SIZE, NOISE_SIZE, gl::NEAREST);
+        unsafe {
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, NOISE_SIZE, NOISE_SIZE, gl::RGB, gl::FLOAT, rotations.as_ptr() as *const _);
+        }
+
+        let normals = texture(gl::RGBA16F, gl::RGBA, gl::FLOAT, width, height, gl::NEAREST);
+        let depth = texture(gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, gl::FLOAT, width, height, gl::NEAREST);
+        let occlusion = texture(gl::R16F, gl::RED, gl::FLOAT, width, height, gl::NEAREST);
+        let ambient = texture(gl::R16F, gl::RED, gl::FLOAT, width, height, gl::LINEAR);
+
+        // Built before the framebuffers, so Drop frees the textures if one is incomplete
+        let mut ssao = Ssao {
+            settings, kernel, prepass_fbo : 0, normals, depth, occlusion_fbo : 0, occlusion,
+            blur_fbo : 0, ambient, noise, width, height, screen : EmptyVertexArray::new(),
+        };
+        ssao.prepass_fbo = framebuffer(normals, Some(depth))?;
+        ssao.occlusion_fbo = framebuffer(occlusion, None)?;
+        ssao.blur_fbo = framebuffer(ambient, None)?;
+
+        Ok(ssao)
+    }
+
+    pub fn settings(&self) -> &SsaoSettings {
+        &self.settings
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Renders the prepass with `draw`, which draws the occluders, then the
+    /// occlusion and its blur. The framebuffer and viewport are restored.
+    pub fn render(
+        &self, prepass : &ShaderProgram, ssao : &ShaderProgram, blur : &ShaderProgram,
+        projection : &Matrix4<f32>, draw : impl Fn()
+    ) {
+        let mut viewport = [0; 4];
+        let mut previous_fbo = 0;
+
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.prepass_fbo);
+            gl::Viewport(0, 0, self.width, self.height);
+            gl::ClearBufferfv(gl::COLOR, 0, [0f32, 0., 1., 0.].as_ptr());
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+
+        prepass.use_program();
+        draw();
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.occlusion_fbo);
+
+            let inputs = [self.normals, self.depth, self.noise];
+            for (unit, texture) in inputs.iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE0 + unit as GLenum);
+                gl::BindTexture(gl::TEXTURE_2D, *texture);
+            }
+        }
+
+        ssao.use_program();
+        ssao.set_uniform("normalTexture", 0);
+        ssao.set_uniform("depthTexture", 1);
+        ssao.set_uniform("noiseTexture", 2);
+        ssao.set_uniform("kernel", self.kernel.as_slice());
+        ssao.set_uniform("kernelSize", self.kernel.len() as i32);
+        ssao.set_uniform("radius", self.settings.radius);
+        ssao.set_uniform("bias", self.settings.bias);
+        ssao.set_uniform("projection", *projection);
+        ssao.set_uniform("inverseProjection", glm::inverse(projection));
+        self.screen.draw_fullscreen();
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.blur_fbo);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.occlusion);
+        }
+
+        blur.use_program();
+        blur.set_uniform("occlusionTexture", 0);
+        blur.set_uniform("intensity", self.settings.intensity);
+        self.screen.draw_fullscreen();
+
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous_fbo as GLuint);
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        }
+    }
+
+    /// Binds the ambient factor to `SSAO_UNIT`
+    pub fn bind(&self) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + SSAO_UNIT as GLenum);
+            gl::BindTexture(gl::TEXTURE_2D, self.ambient);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+}
+
+impl Drop for Ssao {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(3, [self.prepass_fbo, self.occlusion_fbo, self.blur_fbo].as_ptr());
+            gl::DeleteTextures(5, [self.normals, self.depth, self.occlusion, self.ambient, self.noise].as_ptr());
+        }
+    }
+}
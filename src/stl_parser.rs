@@ -0,0 +1,211 @@
+use std::{fs, io};
+
+use crate::triangles::{face_normal, TriangleMesh, VertexAttribute, VertexAttributeLayout, POSITION_ATTRIB, NORMAL_ATTRIB};
+
+const HEADER_SIZE : usize = 84;
+const FACET_SIZE : usize = 50;
+
+fn invalid_data(msg : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Binary files may also start with "solid", so trust the size check first
+fn is_binary(content : &[u8]) -> bool {
+    if content.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let count = u32::from_le_bytes([content[80], content[81], content[82], content[83]]) as usize;
+
+    HEADER_SIZE + count * FACET_SIZE == content.len() || !content.starts_with(b"solid")
+}
+
+/// (normal, [a, b, c])
+type Facet = ([f32; 3], [[f32; 3]; 3]);
+
+fn binary_facets(content : &[u8]) -> io::Result<Vec<Facet>> {
+    let f = |b : &[u8], i : usize| f32::from_le_bytes([b[4 * i], b[4 * i + 1], b[4 * i + 2], b[4 * i + 3]]);
+
+    let count = u32::from_le_bytes([content[80], content[81], content[82], content[83]]) as usize;
+    let body = &content[HEADER_SIZE..];
+
+    if body.len() / FACET_SIZE < count {
+        return Err(invalid_data(format!(
+            "STL file is truncated, {} facets for a count of {}", body.len() / FACET_SIZE, count
+        )));
+    }
+
+    Ok(body[..count * FACET_SIZE]
+        .chunks_exact(FACET_SIZE)
+        .map(|b| (
+            [f(b, 0), f(b, 1), f(b, 2)],
+            [
+                [f(b, 3), f(b, 4), f(b, 5)],
+                [f(b, 6), f(b, 7), f(b, 8)],
+                [f(b, 9), f(b, 10), f(b, 11)]
+            ]
+        ))
+        .collect())
+}
+
+fn ascii_facets(content : &str) -> io::Result<Vec<Facet>> {
+    let mut facets = Vec::new();
+    let mut normal = [0.; 3];
+    let mut corners = Vec::new();
+
+    let vec3 = |elms : &[&str]| -> io::Result<[f32; 3]> {
+        let v = elms.iter()
+            .map(|x| x.parse::<f32>().map_err(|_| invalid_data(format!("Invalid value in STL file: {}", x))))
+            .collect::<io::Result<Vec<f32>>>()?;
+        Ok([v[0], v[1], v[2]])
+    };
+
+    for row in content.split("\n") {
+        let elms : Vec<&str> = row.split_whitespace().collect();
+
+        if elms.is_empty() {continue;}
+
+        match elms[0] {
+            "facet" if elms.len() >= 5 => {
+                normal = vec3(&elms[2..5])?;
+                corners.clear();
+            }
+            "vertex" if elms.len() >= 4 => corners.push(vec3(&elms[1..4])?),
+            "endfacet" if corners.len() == 3 => {
+                facets.push((normal, [corners[0], corners[1], corners[2]]));
+            }
+            _ => ()
+        }
+    }
+
+    Ok(facets)
+}
+
+/// Reads ASCII or binary STL, every facet becomes a triangle with a flat normal.
+/// A binary file shorter than its facet count is an `InvalidData` error.
+pub fn read_stl(filename : &str) -> io::Result<TriangleMesh> {
+    let content = fs::read(filename)?;
+
+    let facets = if is_binary(&content) {
+        binary_facets(&content)?
+    } else {
+        ascii_facets(&String::from_utf8_lossy(&content))?
+    };
+
+    let mut verticies : Vec<f32> = Vec::with_capacity(facets.len() * 18);
+
+    for (normal, corners) in &facets {
+        // Many exporters write zero normals
+        let normal = if *normal == [0.; 3] { face_normal(corners[0], corners[1], corners[2]) } else { *normal };
+
+        for p in corners {
+            verticies.extend(p);
+            verticies.extend(normal);
+        }
+    }
+
+    let indicies : Vec<u32> = (0..facets.len() as u32 * 3).collect();
+
+    Ok(TriangleMesh::from_array_indicies(
+        verticies,
+        indicies.clone(),
+        indicies,
+        Vec::new(),
+        VertexAttributeLayout::new(vec![
+            VertexAttribute::float(POSITION_ATTRIB, 3),
+            VertexAttribute::float(NORMAL_ATTRIB, 3)
+        ])
+    ))
+}
+
+pub fn stl_to_mesh(filename : &str) -> TriangleMesh {
+    read_stl(filename)
+        .unwrap_or_else(|e| panic!("Could not read file: {}: {}", filename, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    const TRIANGLE : [[f32; 3]; 3] = [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]];
+
+    fn binary_triangle(header : &[u8], normal : [f32; 3]) -> Vec<u8> {
+        let mut content = header.to_vec();
+        content.resize(80, b' ');
+        content.extend(1u32.to_le_bytes());
+
+        for x in normal.iter().chain(TRIANGLE.iter().flatten()) {
+            content.extend(x.to_le_bytes());
+        }
+        content.extend([0, 0]);
+
+        content
+    }
+
+    fn write(dir : &Path, name : &str, content : &[u8]) -> String {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn assert_triangle(mesh : &TriangleMesh) {
+        assert_eq!(mesh.num_verticies(), 3);
+        assert_eq!(mesh.vertex_indicies, vec![0, 1, 2]);
+
+        for (i, p) in TRIANGLE.iter().enumerate() {
+            assert_eq!(mesh.attribute(i, POSITION_ATTRIB, 3).unwrap(), p);
+            assert_eq!(mesh.attribute(i, NORMAL_ATTRIB, 3).unwrap(), &[0., 0., 1.]);
+        }
+    }
+
+    #[test]
+    fn ascii_facets_are_read() {
+        let dir = temp_dir("stl-ascii");
+        let path = write(&dir, "triangle.stl", b"solid t\n  facet normal 0 0 1\n    outer loop\n\
+            vertex 0 0 0\n      vertex 1 0 0\n      vertex 0 1 0\n    endloop\n  endfacet\nendsolid t\n");
+
+        assert_triangle(&read_stl(&path).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn binary_facets_are_read() {
+        let dir = temp_dir("stl-binary");
+
+        // Zero normals are recomputed from the winding
+        let path = write(&dir, "triangle.stl", &binary_triangle(b"binary", [0.; 3]));
+        assert_triangle(&read_stl(&path).unwrap());
+
+        // The size matches the facet count, so this is not ASCII
+        let path = write(&dir, "solid.stl", &binary_triangle(b"solid exported by some tool", [0., 0., 1.]));
+        assert_triangle(&read_stl(&path).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let dir = temp_dir("stl-malformed");
+
+        let mut truncated = binary_triangle(b"binary", [0., 0., 1.]);
+        truncated.truncate(truncated.len() - 10);
+
+        let mut huge_count = binary_triangle(b"binary", [0., 0., 1.]);
+        huge_count[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        for (name, content) in [
+            ("truncated.stl", &truncated[..]),
+            ("huge-count.stl", &huge_count),
+            ("bad-value.stl", b"solid t\nfacet normal 0 0 one\nendfacet\nendsolid t\n"),
+        ] {
+            let err = read_stl(&write(&dir, name, content)).err();
+            assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::InvalidData), "{}", name);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use crate::triangles::{
+    face_normal, TriangleMesh, VertexAttribute, VertexAttributeLayout,
+    NORMAL_ATTRIB, TANGENT_ATTRIB, TEXCOORD_ATTRIB
+};
+
+type Vec3 = [f32; 3];
+
+fn sub(a : Vec3, b : Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a : Vec3, s : f32) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn add(a : Vec3, b : Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn dot(a : Vec3, b : Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a : Vec3, b : Vec3) -> Vec3 {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(a : Vec3) -> Option<Vec3> {
+    let len = dot(a, a).sqrt();
+
+    if len > 1e-12 { Some(scale(a, 1. / len)) } else { None }
+}
+
+/// `v` with its component along the unit vector `n` removed
+fn project(v : Vec3, n : Vec3) -> Vec3 {
+    sub(v, scale(n, dot(n, v)))
+}
+
+/// Any unit vector perpendicular to `n`
+fn perpendicular(n : Vec3) -> Vec3 {
+    let axis = if n[0].abs() < 0.9 { [1., 0., 0.] } else { [0., 1., 0.] };
+
+    normalize(project(axis, n)).unwrap_or([1., 0., 0.])
+}
+
+/// Corners sharing position, normal, texture coordinate and UV winding get the
+/// same tangent, like the vertex welding of MikkTSpace
+#[derive(PartialEq, Eq, Hash)]
+struct CornerKey {
+    bits : [u32; 8],
+    mirrored : bool,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Accumulated {
+    tangent : Vec3,
+    bitangent : Vec3,
+}
+
+impl TriangleMesh {
+    /// Adds (or recomputes) a `vec4` tangent per vertex, the sign in `w` gives the
+    /// bitangent as `w * cross(normal, tangent)`. Follows MikkTSpace: per face
+    /// tangents are projected onto the vertex normal, weighted by the corner
+    /// angle and summed over welded corners, without merging mirrored UVs.
+    /// Returns false when the mesh has no texture coordinates.
+    pub fn generate_tangents(&mut self) -> bool {
+        let layout = &self.vertex_attrib_layout;
+        if !layout.contains(TEXCOORD_ATTRIB) {
+            return false;
+        }
+        let has_normals = layout.contains(NORMAL_ATTRIB);
+
+        let num_verticies = self.num_verticies() - self.num_verticies() % 3;
+
+        let normal = |mesh : &TriangleMesh, i : usize, face : usize| -> Vec3 {
+            if has_normals {
+                let n = mesh.attribute(i, NORMAL_ATTRIB, 3).unwrap();
+                normalize([n[0], n[1], n[2]]).unwrap_or([0., 0., 1.])
+            } else {
+                face_normal(mesh.position(face), mesh.position(face + 1), mesh.position(face + 2))
+            }
+        };
+        let uv = |mesh : &TriangleMesh, i : usize| -> [f32; 2] {
+            let t = mesh.attribute(i, TEXCOORD_ATTRIB, 2).unwrap();
+            [t[0], t[1]]
+        };
+
+        let mut keys = Vec::with_capacity(num_verticies);
+        let mut accumulated : HashMap<CornerKey, Accumulated> = HashMap::new();
+
+        for face in (0..num_verticies).step_by(3) {
+            let p = [self.position(face), self.position(face + 1), self.position(face + 2)];
+            let t = [uv(self, face), uv(self, face + 1), uv(self, face + 2)];
+
+            let e1 = sub(p[1], p[0]);
+            let e2 = sub(p[2], p[0]);
+            let (du1, dv1) = (t[1][0] - t[0][0], t[1][1] - t[0][1]);
+            let (du2, dv2) = (t[2][0] - t[0][0], t[2][1] - t[0][1]);
+
+            let area = du1 * dv2 - du2 * dv1;
+            let mirrored = area < 0.;
+
+            // Direction of increasing u and v on the surface, zero for degenerate UVs
+            let (face_tangent, face_bitangent) = if area.abs() > 1e-20 {
+                (
+                    scale(sub(scale(e1, dv2), scale(e2, dv1)), 1. / area),
+                    scale(sub(scale(e2, du1), scale(e1, du2)), 1. / area)
+                )
+            } else {
+                ([0.; 3], [0.; 3])
+            };
+
+            for corner in 0..3 {
+                let i = face + corner;
+                let n = normal(self, i, face);
+
+                let mut bits = [0u32; 8];
+                for k in 0..3 {
+                    bits[k] = p[corner][k].to_bits();
+                    bits[3 + k] = n[k].to_bits();
+                }
+                bits[6] = t[corner][0].to_bits();
+                bits[7] = t[corner][1].to_bits();
+                let key = CornerKey { bits, mirrored };
+
+                // Angle between the two edges leaving this corner
+                let a = normalize(sub(p[(corner + 1) % 3], p[corner]));
+                let b = normalize(sub(p[(corner + 2) % 3], p[corner]));
+                let angle = match (a, b) {
+                    (Some(a), Some(b)) => dot(a, b).clamp(-1., 1.).acos(),
+                    _ => 0.
+                };
+
+                let entry = accumulated.entry(key).or_default();
+                if let Some(tangent) = normalize(project(face_tangent, n)) {
+                    entry.tangent = add(entry.tangent, scale(tangent, angle));
+                }
+                if let Some(bitangent) = normalize(project(face_bitangent, n)) {
+                    entry.bitangent = add(entry.bitangent, scale(bitangent, angle));
+                }
+
+                keys.push(CornerKey { bits, mirrored });
+            }
+        }
+
+        let mut tangents = Vec::with_capacity(self.num_verticies() * 4);
+
+        for (i, key) in keys.iter().enumerate() {
+            let face = i - i % 3;
+            let n = normal(self, i, face);
+            let sum = accumulated[key];
+
+            let tangent = normalize(project(sum.tangent, n)).unwrap_or_else(|| perpendicular(n));
+            let sign = if dot(cross(n, tangent), sum.bitangent) < 0. { -1. } else { 1. };
+
+            tangents.extend_from_slice(&[tangent[0], tangent[1], tangent[2], sign]);
+        }
+        // Corners of an incomplete last triangle
+        tangents.resize(self.num_verticies() * 4, 0.);
+
+        self.set_attribute(VertexAttribute::float(TANGENT_ATTRIB, 4), &tangents);
+
+        true
+    }
+
+    /// Replaces the values of `attribute`, or appends it to every vertex.
+    /// `values` holds `attribute.size()` floats per vertex.
+    pub fn set_attribute(&mut self, attribute : VertexAttribute, values : &[f32]) {
+        let layout = &self.vertex_attrib_layout;
+        let size = attribute.size() as usize;
+        let stride = layout.float_stride();
+        let num_verticies = self.num_verticies();
+
+        if let Some(offset) = layout.float_offset(attribute.index()) {
+            for i in 0..num_verticies {
+                self.verticies[i * stride + offset..i * stride + offset + size]
+                    .copy_from_slice(&values[i * size..(i + 1) * size]);
+            }
+            return;
+        }
+
+        let mut verticies = Vec::with_capacity(num_verticies * (stride + size));
+        for i in 0..num_verticies {
+            verticies.extend_from_slice(&self.verticies[i * stride..(i + 1) * stride]);
+            verticies.extend_from_slice(&values[i * size..(i + 1) * size]);
+        }
+
+        let mut attributes = layout.attributes().to_vec();
+        attributes.push(attribute);
+
+        self.verticies = verticies;
+        self.vertex_attrib_layout = VertexAttributeLayout::new(attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangles::{VertexAttribute, POSITION_ATTRIB};
+
+    /// Two triangles over the corners `positions` with normal `n` and the texture
+    /// coordinates `uvs`, corners in counter-clockwise order
+    fn quad(positions : [Vec3; 4], n : Vec3, uvs : [[f32; 2]; 4]) -> TriangleMesh {
+        let layout = VertexAttributeLayout::new(vec![
+            VertexAttribute::float(POSITION_ATTRIB, 3),
+            VertexAttribute::float(NORMAL_ATTRIB, 3),
+            VertexAttribute::float(TEXCOORD_ATTRIB, 2),
+        ]);
+
+        let mut verticies = Vec::new();
+        for i in [0, 1, 2, 0, 2, 3] {
+            verticies.extend_from_slice(&positions[i]);
+            verticies.extend_from_slice(&n);
+            verticies.extend_from_slice(&uvs[i]);
+        }
+
+        TriangleMesh::from_array_indicies(verticies, Vec::new(), Vec::new(), Vec::new(), layout)
+    }
+
+    fn tangent(mesh : &TriangleMesh, i : usize) -> (Vec3, f32) {
+        let t = mesh.attribute(i, TANGENT_ATTRIB, 4).unwrap();
+        ([t[0], t[1], t[2]], t[3])
+    }
+
+    fn assert_close(a : Vec3, b : Vec3) {
+        assert!((0..3).all(|k| (a[k] - b[k]).abs() < 1e-5), "{:?} != {:?}", a, b);
+    }
+
+    /// T and B are unit length, perpendicular to each other and to the normal,
+    /// and B points along increasing v
+    fn assert_orthonormal(mesh : &TriangleMesh, n : Vec3, v_direction : Vec3) {
+        for i in 0..mesh.num_verticies() {
+            let (t, w) = tangent(mesh, i);
+            let b = scale(cross(n, t), w);
+
+            assert!((dot(t, t) - 1.).abs() < 1e-5, "tangent {:?} is not unit length", t);
+            assert!((dot(b, b) - 1.).abs() < 1e-5, "bitangent {:?} is not unit length", b);
+            assert!(dot(t, n).abs() < 1e-5 && dot(b, n).abs() < 1e-5 && dot(t, b).abs() < 1e-5);
+            assert!(w == 1. || w == -1.);
+            assert!(dot(b, v_direction) > 0., "bitangent {:?} points against v", b);
+        }
+    }
+
+    const UNIT_QUAD : [Vec3; 4] = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+
+    #[test]
+    fn tangents_follow_u_on_a_uv_mapped_quad() {
+        let mut mesh = quad(UNIT_QUAD, [0., 0., 1.], [[0., 0.], [1., 0.], [1., 1.], [0., 1.]]);
+
+        assert!(mesh.generate_tangents());
+
+        assert_orthonormal(&mesh, [0., 0., 1.], [0., 1., 0.]);
+        for i in 0..mesh.num_verticies() {
+            let (t, w) = tangent(&mesh, i);
+            assert_close(t, [1., 0., 0.]);
+            assert_eq!(w, 1.);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_sign() {
+        // u runs against x, v still along y
+        let mut mesh = quad(UNIT_QUAD, [0., 0., 1.], [[1., 0.], [0., 0.], [0., 1.], [1., 1.]]);
+
+        assert!(mesh.generate_tangents());
+
+        assert_orthonormal(&mesh, [0., 0., 1.], [0., 1., 0.]);
+        for i in 0..mesh.num_verticies() {
+            let (t, w) = tangent(&mesh, i);
+            assert_close(t, [-1., 0., 0.]);
+            assert_eq!(w, -1.);
+        }
+    }
+
+    #[test]
+    fn sheared_uvs_on_a_tilted_quad_stay_orthonormal() {
+        // Quad in the plane x = z, its v direction is not perpendicular to u
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let positions = [[0., 0., 0.], [s, 0., s], [s, 1., s], [0., 1., 0.]];
+        let n = [-s, 0., s];
+        let uvs = [[0., 0.], [1., 0.], [1.5, 1.], [0.5, 1.]];
+        let mut mesh = quad(positions, n, uvs);
+
+        assert!(mesh.generate_tangents());
+
+        assert_orthonormal(&mesh, n, [0., 1., 0.]);
+        for i in 0..mesh.num_verticies() {
+            let (t, w) = tangent(&mesh, i);
+            assert_close(t, [s, 0., s]);
+            assert_eq!(w, 1.);
+        }
+    }
+
+    #[test]
+    fn meshes_without_texcoords_get_no_tangents() {
+        let layout = VertexAttributeLayout::new(vec![VertexAttribute::float(POSITION_ATTRIB, 3)]);
+        let mut mesh = TriangleMesh::from_array_indicies(vec![0.; 9], Vec::new(), Vec::new(), Vec::new(), layout);
+
+        assert!(!mesh.generate_tangents());
+        assert!(!mesh.vertex_attrib_layout.contains(TANGENT_ATTRIB));
+    }
+}
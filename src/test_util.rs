@@ -0,0 +1,11 @@
+//! Helpers shared by the unit tests
+
+use std::{fs, path::PathBuf};
+
+/// An empty directory for the files of one test, unique per test process.
+/// Tests remove it when they are done.
+pub fn temp_dir(name : &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rendering-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,13 +1,23 @@
 pub struct Texture {}
 
 impl Texture {
-    pub fn load(img_path : &str) {
-        let mut id = 0;
+    pub fn load(img_path : &str) -> u32 {
+        let img = Texture::decode(img_path);
+
+        Texture::from_rgba(img.width(), img.height(), img.as_raw())
+    }
 
-        let img = match image::open(img_path) {
+    /// Reads an image without touching GL, so it can run on any thread
+    pub fn decode(img_path : &str) -> image::RgbaImage {
+        match image::open(img_path) {
             Ok(im) => im.into_rgba8(),
             _ => image::open("textures/missing.jpg").unwrap().into_rgba8()
-        };
+        }
+    }
+
+    /// Uploads tightly packed RGBA8 pixels into a new texture bound to TEXTURE_2D
+    pub fn from_rgba(width : u32, height : u32, pixels : &[u8]) -> u32 {
+        let mut id = 0;
 
         unsafe {
             gl::GenTextures(1, &mut id);
@@ -22,13 +32,15 @@ impl Texture {
                 gl::TEXTURE_2D,
                 0,
                 gl::RGBA as i32,
-                img.width() as i32,
-                img.height() as i32,
+                width as i32,
+                height as i32,
                 0,
                 gl::RGBA,
                 gl::UNSIGNED_BYTE,
-                img.into_raw().as_ptr() as *const _,
+                pixels.as_ptr() as *const _,
             );
         }
+
+        id
     }
-}
\ No newline at end of file
+}
@@ -1,3 +1,30 @@
+// Attribute locations shared by all loaders and the shaders
+pub const POSITION_ATTRIB : gl::types::GLuint = 0;
+pub const NORMAL_ATTRIB : gl::types::GLuint = 1;
+pub const TEXCOORD_ATTRIB : gl::types::GLuint = 2;
+pub const TANGENT_ATTRIB : gl::types::GLuint = 3;
+pub const TEXCOORD1_ATTRIB : gl::types::GLuint = 4;
+pub const COLOR_ATTRIB : gl::types::GLuint = 5;
+
+/// Unit normal of the triangle `a, b, c` with counter clockwise winding
+pub fn face_normal(a : [f32; 3], b : [f32; 3], c : [f32; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0]
+    ];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+
+    if len > 0. {
+        [n[0] / len, n[1] / len, n[2] / len]
+    } else {
+        [0., 0., 0.]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct VertexAttribute {
     index : gl::types::GLuint,
     attrib_size : gl::types::GLint,
@@ -13,8 +40,30 @@ impl VertexAttribute {
         attrib_type : gl::types::GLenum) -> Self {
         VertexAttribute {index, attrib_size, attrib_mem_size, attrib_type }
     }
+
+    /// Attribute of `size` tightly packed f32 components
+    pub fn float(index : gl::types::GLuint, size : gl::types::GLint) -> Self {
+        VertexAttribute::new(index, size, size * std::mem::size_of::<f32>() as i32, gl::FLOAT)
+    }
+
+    pub fn index(&self) -> gl::types::GLuint {
+        self.index
+    }
+
+    pub fn size(&self) -> gl::types::GLint {
+        self.attrib_size
+    }
+
+    pub fn mem_size(&self) -> i32 {
+        self.attrib_mem_size
+    }
+
+    pub fn attrib_type(&self) -> gl::types::GLenum {
+        self.attrib_type
+    }
 }
 
+#[derive(Clone, PartialEq, Debug)]
 pub struct VertexAttributeLayout {
     attributes : Vec<VertexAttribute>,
     stride : gl::types::GLsizei,
@@ -36,12 +85,38 @@ impl VertexAttributeLayout {
         }
     }
 
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn contains(&self, index : gl::types::GLuint) -> bool {
+        self.attributes.iter().any(|a| a.index == index)
+    }
+
+    /// Number of floats per vertex
+    pub fn float_stride(&self) -> usize {
+        self.stride as usize / std::mem::size_of::<f32>()
+    }
+
+    /// Offset in floats of the attribute bound to `index`, if the layout has it
+    pub fn float_offset(&self, index : gl::types::GLuint) -> Option<usize> {
+        let mut offset = 0;
+
+        for attrib in &self.attributes {
+            if attrib.index == index {
+                return Some(offset as usize / std::mem::size_of::<f32>());
+            }
+            offset += attrib.attrib_mem_size;
+        }
+
+        None
+    }
+
     pub fn enable_attributes(&self) {
         let mut ptr = 0;
 
         for attrib in &self.attributes {
             unsafe {
-                println!("Pointer[ index: {}, size: {}, stride: {}, ptr: {}]", attrib.index, attrib.attrib_size, self.stride, ptr);
                 gl::EnableVertexAttribArray(attrib.index);
                 gl::VertexAttribPointer(
                     attrib.index,
@@ -57,12 +132,21 @@ impl VertexAttributeLayout {
     }
 }
 
+/// A run of verticies drawn with the material set by `usemtl`
+#[derive(Clone, PartialEq, Debug)]
+pub struct MaterialGroup {
+    pub name : String,
+    pub first : u32,
+    pub count : u32,
+}
+
 pub struct TriangleMesh {
     pub verticies : Vec<f32>,
     pub vertex_indicies : Vec<u32>,
     pub normal_indicies : Vec<u32>,
     pub texture_indicies : Vec<u32>,
-    pub vertex_attrib_layout : VertexAttributeLayout
+    pub vertex_attrib_layout : VertexAttributeLayout,
+    pub materials : Vec<MaterialGroup>
 }
 
 impl TriangleMesh {
@@ -78,10 +162,28 @@ impl TriangleMesh {
             vertex_indicies,
             normal_indicies,
             texture_indicies,
-            vertex_attrib_layout
+            vertex_attrib_layout,
+            materials : Vec::new()
         }
     }
 
+    pub fn num_verticies(&self) -> usize {
+        self.verticies.len() / self.vertex_attrib_layout.float_stride().max(1)
+    }
+
+    /// Returns the `size` floats of attribute `index` for vertex `i`
+    pub fn attribute(&self, i : usize, index : gl::types::GLuint, size : usize) -> Option<&[f32]> {
+        let offset = self.vertex_attrib_layout.float_offset(index)?;
+        let start = i * self.vertex_attrib_layout.float_stride() + offset;
+
+        self.verticies.get(start..start + size)
+    }
+
+    pub fn position(&self, i : usize) -> [f32; 3] {
+        let p = self.attribute(i, POSITION_ATTRIB, 3).expect("Mesh has no position attribute");
+        [p[0], p[1], p[2]]
+    }
+
     pub fn enable_vertex_attributes(&self) {
         // Specify vertex attribute pointers
         self.vertex_attrib_layout.enable_attributes();
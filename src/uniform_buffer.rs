@@ -0,0 +1,695 @@
+use gl::types::GLuint;
+
+use crate::mtl_parser::MtlMaterial;
+
+pub const CAMERA_BLOCK : &str = "Camera";
+pub const LIGHTS_BLOCK : &str = "Lights";
+pub const MATERIAL_BLOCK : &str = "Material";
+pub const SHADOWS_BLOCK : &str = "Shadows";
+pub const FOG_BLOCK : &str = "Fog";
+
+pub const CAMERA_BINDING : GLuint = 0;
+pub const LIGHTS_BINDING : GLuint = 1;
+pub const MATERIAL_BINDING : GLuint = 2;
+pub const SHADOWS_BINDING : GLuint = 3;
+pub const FOG_BINDING : GLuint = 4;
+
+/// Binding point of every shared block, assigned to each program when it is linked
+pub const BLOCK_BINDINGS : [(&str, GLuint); 5] = [
+    (CAMERA_BLOCK, CAMERA_BINDING),
+    (LIGHTS_BLOCK, LIGHTS_BINDING),
+    (MATERIAL_BLOCK, MATERIAL_BINDING),
+    (SHADOWS_BLOCK, SHADOWS_BINDING),
+    (FOG_BLOCK, FOG_BINDING),
+];
+
+fn round_up(x : usize, align : usize) -> usize {
+    x.div_ceil(align) * align
+}
+
+/// A value with a std140 representation
+pub trait Std140 {
+    /// Base alignment in bytes
+    const ALIGN : usize;
+    /// Size in bytes, without the padding that follows it
+    const SIZE : usize;
+
+    fn write_bytes(&self, out : &mut Vec<u8>);
+}
+
+macro_rules! impl_std140_scalar {
+    ($($t:ty),+) => {
+        $(
+            impl Std140 for $t {
+                const ALIGN : usize = 4;
+                const SIZE : usize = 4;
+
+                fn write_bytes(&self, out : &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_ne_bytes());
+                }
+            }
+
+            impl Std140 for [$t; 2] {
+                const ALIGN : usize = 8;
+                const SIZE : usize = 8;
+
+                fn write_bytes(&self, out : &mut Vec<u8>) {
+                    self.iter().for_each(|x| x.write_bytes(out));
+                }
+            }
+
+            // vec3 is aligned like vec4
+            impl Std140 for [$t; 3] {
+                const ALIGN : usize = 16;
+                const SIZE : usize = 12;
+
+                fn write_bytes(&self, out : &mut Vec<u8>) {
+                    self.iter().for_each(|x| x.write_bytes(out));
+                }
+            }
+
+            impl Std140 for [$t; 4] {
+                const ALIGN : usize = 16;
+                const SIZE : usize = 16;
+
+                fn write_bytes(&self, out : &mut Vec<u8>) {
+                    self.iter().for_each(|x| x.write_bytes(out));
+                }
+            }
+        )+
+    }
+}
+
+impl_std140_scalar!(f32, i32, u32);
+
+impl Std140 for bool {
+    const ALIGN : usize = 4;
+    const SIZE : usize = 4;
+
+    fn write_bytes(&self, out : &mut Vec<u8>) {
+        (*self as u32).write_bytes(out);
+    }
+}
+
+impl Std140 for glm::Vector2<f32> {
+    const ALIGN : usize = 8;
+    const SIZE : usize = 8;
+
+    fn write_bytes(&self, out : &mut Vec<u8>) {
+        [self.x, self.y].write_bytes(out);
+    }
+}
+
+impl Std140 for glm::Vector3<f32> {
+    const ALIGN : usize = 16;
+    const SIZE : usize = 12;
+
+    fn write_bytes(&self, out : &mut Vec<u8>) {
+        [self.x, self.y, self.z].write_bytes(out);
+    }
+}
+
+impl Std140 for glm::Vector4<f32> {
+    const ALIGN : usize = 16;
+    const SIZE : usize = 16;
+
+    fn write_bytes(&self, out : &mut Vec<u8>) {
+        [self.x, self.y, self.z, self.w].write_bytes(out);
+    }
+}
+
+/// Matrices are stored as arrays of columns, each padded to a vec4
+macro_rules! impl_std140_matrix {
+    ($($t:ty => $columns:expr, [$($row:expr),+]);+ $(;)?) => {
+        $(
+            impl Std140 for $t {
+                const ALIGN : usize = 16;
+                const SIZE : usize = 16 * $columns;
+
+                fn write_bytes(&self, out : &mut Vec<u8>) {
+                    for column in self.as_array() {
+                        let start = out.len();
+                        $(column[$row].write_bytes(out);)+
+                        out.resize(start + 16, 0);
+                    }
+                }
+            }
+        )+
+    }
+}
+
+impl_std140_matrix! {
+    glm::Matrix2<f32> => 2, [0, 1];
+    glm::Matrix3<f32> => 3, [0, 1, 2];
+    glm::Matrix4<f32> => 4, [0, 1, 2, 3];
+}
+
+/// Lays out the members of a uniform block one after the other, following
+/// the std140 rules, and remembers the offset of each member
+#[derive(Default)]
+pub struct Std140Writer {
+    data : Vec<u8>,
+    offsets : Vec<(String, usize)>,
+}
+
+impl Std140Writer {
+    pub fn new() -> Self {
+        Std140Writer::default()
+    }
+
+    fn align(&mut self, align : usize) {
+        self.data.resize(round_up(self.data.len(), align), 0);
+    }
+
+    pub fn field<T : Std140>(&mut self, name : &str, value : &T) -> &mut Self {
+        self.align(T::ALIGN);
+        self.offsets.push((name.to_string(), self.data.len()));
+        value.write_bytes(&mut self.data);
+
+        self
+    }
+
+    /// Array elements are padded to a multiple of 16 bytes. Missing elements
+    /// up to `len` are zeroed so the block keeps the size the shader declares.
+    pub fn array<T : Std140>(&mut self, name : &str, values : &[T], len : usize) -> &mut Self {
+        assert!(values.len() <= len, "Uniform array {} holds {} elements, {} given", name, len, values.len());
+
+        let stride = round_up(T::SIZE, 16);
+
+        self.align(16);
+        // GL reports arrays by their first element
+        self.offsets.push((format!("{}[0]", name), self.data.len()));
+
+        for value in values {
+            let start = self.data.len();
+            value.write_bytes(&mut self.data);
+            self.data.resize(start + stride, 0);
+        }
+        self.data.resize(self.data.len() + stride * (len - values.len()), 0);
+
+        self
+    }
+
+    pub fn finish(&mut self) -> Std140Block {
+        // The block itself is aligned like a vec4
+        self.align(16);
+
+        Std140Block {
+            data : std::mem::take(&mut self.data),
+            offsets : std::mem::take(&mut self.offsets),
+        }
+    }
+}
+
+/// Packed contents of a uniform block
+#[derive(Clone, Debug)]
+pub struct Std140Block {
+    data : Vec<u8>,
+    offsets : Vec<(String, usize)>,
+}
+
+impl Std140Block {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn offset(&self, name : &str) -> Option<usize> {
+        self.offsets.iter().find(|(n, _)| n == name).map(|(_, o)| *o)
+    }
+
+    pub fn offsets(&self) -> &[(String, usize)] {
+        &self.offsets
+    }
+}
+
+/// A uniform buffer bound to a fixed binding point
+pub struct UniformBuffer {
+    id : GLuint,
+    binding : GLuint,
+    size : usize,
+}
+
+impl UniformBuffer {
+    pub fn new(binding : GLuint, size : usize) -> Self {
+        let mut id = 0;
+
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, id);
+            gl::BufferData(gl::UNIFORM_BUFFER, size as isize, std::ptr::null(), gl::DYNAMIC_DRAW);
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, id);
+        }
+
+        UniformBuffer { id, binding, size }
+    }
+
+    /// A buffer sized for `block`, holding its contents
+    pub fn from_block(binding : GLuint, block : &Std140Block) -> Self {
+        let buffer = UniformBuffer::new(binding, block.size());
+        buffer.update(block);
+
+        buffer
+    }
+
+    pub fn binding(&self) -> GLuint {
+        self.binding
+    }
+
+    pub fn update(&self, block : &Std140Block) {
+        assert!(block.size() <= self.size, "Uniform block of {} bytes does not fit a {} bytes buffer", block.size(), self.size);
+
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.id);
+            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, block.size() as isize, block.data().as_ptr() as *const gl::types::GLvoid);
+        }
+    }
+
+    /// Binds the buffer back to its binding point, after another buffer took it
+    pub fn bind(&self) {
+        unsafe { gl::BindBufferBase(gl::UNIFORM_BUFFER, self.binding, self.id) };
+    }
+}
+
+impl Drop for UniformBuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id) };
+    }
+}
+
+/// Per-frame camera state, block `Camera`
+pub struct CameraBlock {
+    /// Mesh space to clip space
+    pub transform : glm::Matrix4<f32>,
+    /// Mesh space to view space
+    pub view : glm::Matrix4<f32>,
+    /// In mesh space
+    pub position : [f32; 3],
+}
+
+impl CameraBlock {
+    pub fn new(projection : glm::Matrix4<f32>, view : glm::Matrix4<f32>) -> Self {
+        // The eye is the origin of view space
+        let eye = glm::inverse(&view) * glm::vec4(0., 0., 0., 1.);
+
+        CameraBlock {
+            transform : projection * view,
+            view,
+            position : [eye.x / eye.w, eye.y / eye.w, eye.z / eye.w],
+        }
+    }
+
+    pub fn std140(&self) -> Std140Block {
+        Std140Writer::new()
+            .field("transformMatrix", &self.transform)
+            .field("cameraPosition", &self.position)
+            .field("viewMatrix", &self.view)
+            .finish()
+    }
+}
+
+/// Enough for the deferred path, forward shading loops over every one of them per fragment
+pub const MAX_LIGHTS : usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    /// w = 0 for directional lights
+    pub position : [f32; 4],
+    pub color : [f32; 3],
+    pub intensity : f32,
+    /// Direction the cone of a spot light points to
+    pub spot_direction : [f32; 3],
+    /// Cosine of the half angle of the cone, -1 for lights shining everywhere
+    pub spot_cutoff : f32,
+    /// Directional and spot lights only
+    pub casts_shadows : bool,
+}
+
+impl Light {
+    /// `direction` points towards the light
+    pub fn directional(direction : [f32; 3], color : [f32; 3], intensity : f32) -> Self {
+        let [x, y, z] = direction;
+
+        Light { position : [x, y, z, 0.], ..Light::point([0.; 3], color, intensity) }
+    }
+
+    pub fn point(position : [f32; 3], color : [f32; 3], intensity : f32) -> Self {
+        let [x, y, z] = position;
+
+        Light {
+            position : [x, y, z, 1.],
+            color,
+            intensity,
+            spot_direction : [0., 0., -1.],
+            spot_cutoff : -1.,
+            casts_shadows : false,
+        }
+    }
+
+    /// `angle` is the half angle of the cone in radians
+    pub fn spot(position : [f32; 3], direction : [f32; 3], angle : f32, color : [f32; 3], intensity : f32) -> Self {
+        Light { spot_direction : direction, spot_cutoff : angle.cos(), ..Light::point(position, color, intensity) }
+    }
+
+    pub fn is_directional(&self) -> bool {
+        self.position[3] == 0.
+    }
+
+    pub fn is_spot(&self) -> bool {
+        !self.is_directional() && self.spot_cutoff > -1.
+    }
+}
+
+/// Per-frame lights, block `Lights`
+#[derive(Default)]
+pub struct LightsBlock {
+    pub lights : Vec<Light>,
+}
+
+impl LightsBlock {
+    pub fn std140(&self) -> Std140Block {
+        let lights = &self.lights[..self.lights.len().min(MAX_LIGHTS)];
+        let positions : Vec<[f32; 4]> = lights.iter().map(|l| l.position).collect();
+        let colors : Vec<[f32; 4]> = lights.iter()
+            .map(|l| [l.color[0], l.color[1], l.color[2], l.intensity])
+            .collect();
+        let spots : Vec<[f32; 4]> = lights.iter()
+            .map(|l| [l.spot_direction[0], l.spot_direction[1], l.spot_direction[2], l.spot_cutoff])
+            .collect();
+
+        Std140Writer::new()
+            .array("lightPositions", &positions, MAX_LIGHTS)
+            .array("lightColors", &colors, MAX_LIGHTS)
+            .array("lightSpots", &spots, MAX_LIGHTS)
+            .field("lightCount", &(lights.len() as i32))
+            .finish()
+    }
+}
+
+pub const MAX_SHADOW_MAPS : usize = 8;
+
+/// Shadow maps of the lights, block `Shadows`
+#[derive(Clone, Debug, Default)]
+pub struct ShadowsBlock {
+    /// Mesh space to the clip space of each shadow map
+    pub matrices : Vec<glm::Matrix4<f32>>,
+    /// First map and number of maps of each light, a directional light has one
+    /// map per cascade. Lights without shadows have no maps.
+    pub light_maps : Vec<[i32; 2]>,
+    pub bias : f32,
+    pub normal_bias : f32,
+    pub pcf_radius : i32,
+}
+
+impl ShadowsBlock {
+    pub fn std140(&self) -> Std140Block {
+        let light_maps : Vec<[i32; 4]> = self.light_maps.iter()
+            .take(MAX_LIGHTS)
+            .map(|[first, count]| [*first, *count, 0, 0])
+            .collect();
+
+        Std140Writer::new()
+            .array("shadowMatrices", &self.matrices[..self.matrices.len().min(MAX_SHADOW_MAPS)], MAX_SHADOW_MAPS)
+            .array("lightShadowMaps", &light_maps, MAX_LIGHTS)
+            .field("shadowBias", &self.bias)
+            .field("shadowNormalBias", &self.normal_bias)
+            .field("pcfRadius", &self.pcf_radius)
+            .finish()
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogMode {
+    NONE,
+    /// No fog before `start`, only fog after `end`
+    LINEAR { start : f32, end : f32 },
+    /// exp(-density * depth)
+    EXPONENTIAL(f32),
+    /// exp(-(density * depth)²)
+    EXPONENTIAL_SQUARED(f32),
+}
+
+/// Distance fog over view space depth, block `Fog`
+#[derive(Clone, Copy, Debug)]
+pub struct FogBlock {
+    pub mode : FogMode,
+    pub color : [f32; 3],
+}
+
+impl Default for FogBlock {
+    fn default() -> Self {
+        FogBlock { mode : FogMode::NONE, color : [0.5, 0.5, 0.5] }
+    }
+}
+
+impl FogBlock {
+    pub fn std140(&self) -> Std140Block {
+        // Same numbering as `fog.glsl`
+        let (mode, density, start, end) : (i32, f32, f32, f32) = match self.mode {
+            FogMode::NONE => (0, 0., 0., 0.),
+            FogMode::LINEAR { start, end } => (1, 0., start, end),
+            FogMode::EXPONENTIAL(density) => (2, density, 0., 0.),
+            FogMode::EXPONENTIAL_SQUARED(density) => (3, density, 0., 0.),
+        };
+
+        Std140Writer::new()
+            .field("fogColor", &self.color)
+            .field("fogMode", &mode)
+            .field("fogDensity", &density)
+            .field("fogStart", &start)
+            .field("fogEnd", &end)
+            .finish()
+    }
+}
+
+/// Per-material constants, block `Material`
+#[derive(Clone, Debug)]
+pub struct MaterialBlock {
+    pub diffuse : [f32; 4],
+    pub ambient : [f32; 3],
+    pub shininess : f32,
+    pub specular : [f32; 3],
+    /// Fragments with a lower alpha are discarded
+    pub alpha_cutoff : f32,
+    pub emissive : [f32; 3],
+    pub metallic : f32,
+    pub roughness : f32,
+}
+
+impl MaterialBlock {
+    pub fn from_mtl(material : &MtlMaterial) -> Self {
+        let [r, g, b] = material.diffuse;
+
+        MaterialBlock {
+            diffuse : [r, g, b, material.dissolve],
+            ambient : material.ambient,
+            shininess : material.shininess,
+            specular : material.specular,
+            alpha_cutoff : 0.,
+            emissive : material.emissive,
+            metallic : material.metallic.unwrap_or(0.),
+            // Roughness with about the same highlight as the Phong exponent
+            roughness : material.roughness.unwrap_or((2. / (material.shininess + 2.)).sqrt()),
+        }
+    }
+
+    pub fn std140(&self) -> Std140Block {
+        Std140Writer::new()
+            .field("diffuseColor", &self.diffuse)
+            .field("ambientColor", &self.ambient)
+            .field("shininess", &self.shininess)
+            .field("specularColor", &self.specular)
+            .field("alphaCutoff", &self.alpha_cutoff)
+            .field("emissiveColor", &self.emissive)
+            .field("metallic", &self.metallic)
+            .field("roughness", &self.roughness)
+            .finish()
+    }
+}
+
+impl Default for MaterialBlock {
+    fn default() -> Self {
+        MaterialBlock::from_mtl(&MtlMaterial::new(""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_at(block : &Std140Block, offset : usize) -> f32 {
+        f32::from_ne_bytes(block.data()[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn vec3_and_float_share_16_bytes() {
+        let block = Std140Writer::new()
+            .field("a", &[1f32, 2., 3.])
+            .field("b", &4f32)
+            .finish();
+
+        assert_eq!(block.offset("a"), Some(0));
+        assert_eq!(block.offset("b"), Some(12));
+        assert_eq!(block.size(), 16);
+        assert_eq!((0..4).map(|i| f32_at(&block, i * 4)).collect::<Vec<_>>(), vec![1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn members_are_aligned_to_their_base_alignment() {
+        let block = Std140Writer::new()
+            .field("s", &1f32)
+            .field("v2", &[2f32, 3.])
+            .field("v3", &[4f32, 5., 6.])
+            .field("i", &7i32)
+            .field("flag", &true)
+            .field("v4", &[0f32; 4])
+            .finish();
+
+        assert_eq!(block.offset("s"), Some(0));
+        assert_eq!(block.offset("v2"), Some(8));
+        assert_eq!(block.offset("v3"), Some(16));
+        assert_eq!(block.offset("i"), Some(28));
+        assert_eq!(block.offset("flag"), Some(32));
+        assert_eq!(block.offset("v4"), Some(48));
+        assert_eq!(block.size(), 64);
+        assert_eq!(&block.data()[32..36], &1u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn array_strides_round_up_to_16() {
+        let block = Std140Writer::new()
+            .field("before", &1f32)
+            .array("scalars", &[1f32, 2., 3.], 3)
+            .array("pairs", &[[1f32, 2.], [3., 4.]], 2)
+            .field("after", &9f32)
+            .finish();
+
+        assert_eq!(block.offset("scalars[0]"), Some(16));
+        assert_eq!(block.offset("pairs[0]"), Some(64));
+        assert_eq!(block.offset("after"), Some(96));
+        assert_eq!(block.size(), 112);
+        for (i, value) in [1., 2., 3.].into_iter().enumerate() {
+            assert_eq!(f32_at(&block, 16 + 16 * i), value);
+        }
+        assert_eq!(f32_at(&block, 64 + 16 + 4), 4.);
+    }
+
+    #[test]
+    fn missing_array_elements_are_zeroed() {
+        let block = Std140Writer::new().array("v", &[[1f32; 4]], 4).finish();
+
+        assert_eq!(block.size(), 64);
+        assert!(block.data()[16..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Uniform array v holds 1 elements, 2 given")]
+    fn arrays_longer_than_declared_panic() {
+        Std140Writer::new().array("v", &[1f32, 2.], 1);
+    }
+
+    #[test]
+    fn matrices_have_16_byte_columns() {
+        let m4 = glm::Matrix4::new(
+            glm::vec4(1., 2., 3., 4.), glm::vec4(5., 6., 7., 8.),
+            glm::vec4(9., 10., 11., 12.), glm::vec4(13., 14., 15., 16.)
+        );
+        let m3 = glm::Matrix3::new(glm::vec3(1., 2., 3.), glm::vec3(4., 5., 6.), glm::vec3(7., 8., 9.));
+
+        let block = Std140Writer::new()
+            .field("f", &1f32)
+            .field("m4", &m4)
+            .field("m3", &m3)
+            .finish();
+
+        assert_eq!(block.offset("m4"), Some(16));
+        assert_eq!(block.offset("m3"), Some(80));
+        assert_eq!(block.size(), 128);
+        assert_eq!((0..16).map(|i| f32_at(&block, 16 + 4 * i)).collect::<Vec<_>>(), (1..=16).map(|i| i as f32).collect::<Vec<_>>());
+        // Each vec3 column of a mat3 is padded to a vec4
+        for column in 0..3 {
+            let start = 80 + 16 * column;
+            assert_eq!(f32_at(&block, start), (3 * column + 1) as f32);
+            assert_eq!(f32_at(&block, start + 12), 0.);
+        }
+    }
+
+    #[test]
+    fn camera_block_layout() {
+        let view = glm::ext::translate(&glm::mat4(1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.), glm::vec3(0., 0., -2.));
+        let block = CameraBlock::new(glm::ext::perspective(1., 1., 0.1, 10.), view).std140();
+
+        assert_eq!(block.offset("transformMatrix"), Some(0));
+        assert_eq!(block.offset("cameraPosition"), Some(64));
+        assert_eq!(block.offset("viewMatrix"), Some(80));
+        assert_eq!(block.size(), 144);
+        assert_eq!([64, 68, 72].map(|offset| f32_at(&block, offset)), [0., 0., 2.]);
+    }
+
+    #[test]
+    fn lights_block_layout() {
+        let block = LightsBlock { lights : vec![Light::point([1., 2., 3.], [1.; 3], 2.)] }.std140();
+        let array = 16 * MAX_LIGHTS;
+
+        assert_eq!(block.offset("lightPositions[0]"), Some(0));
+        assert_eq!(block.offset("lightColors[0]"), Some(array));
+        assert_eq!(block.offset("lightSpots[0]"), Some(2 * array));
+        assert_eq!(block.offset("lightCount"), Some(3 * array));
+        assert_eq!(block.size(), 3 * array + 16);
+        assert_eq!(f32_at(&block, array + 12), 2.);
+    }
+
+    #[test]
+    fn lights_past_max_lights_are_dropped() {
+        let lights = vec![Light::point([0.; 3], [1.; 3], 1.); MAX_LIGHTS + 3];
+        let block = LightsBlock { lights }.std140();
+
+        assert_eq!(block.size(), 3 * 16 * MAX_LIGHTS + 16);
+        assert_eq!(&block.data()[3 * 16 * MAX_LIGHTS..][..4], &(MAX_LIGHTS as i32).to_ne_bytes());
+    }
+
+    #[test]
+    fn shadows_block_layout() {
+        let block = ShadowsBlock::default().std140();
+        let matrices = 64 * MAX_SHADOW_MAPS;
+
+        assert_eq!(block.offset("shadowMatrices[0]"), Some(0));
+        assert_eq!(block.offset("lightShadowMaps[0]"), Some(matrices));
+        assert_eq!(block.offset("shadowBias"), Some(matrices + 16 * MAX_LIGHTS));
+        assert_eq!(block.offset("shadowNormalBias"), Some(matrices + 16 * MAX_LIGHTS + 4));
+        assert_eq!(block.offset("pcfRadius"), Some(matrices + 16 * MAX_LIGHTS + 8));
+        assert_eq!(block.size(), matrices + 16 * MAX_LIGHTS + 16);
+    }
+
+    #[test]
+    fn fog_block_layout() {
+        let block = FogBlock { mode : FogMode::LINEAR { start : 1., end : 4. }, ..FogBlock::default() }.std140();
+
+        assert_eq!(block.offset("fogColor"), Some(0));
+        assert_eq!(block.offset("fogMode"), Some(12));
+        assert_eq!(block.offset("fogDensity"), Some(16));
+        assert_eq!(block.offset("fogStart"), Some(20));
+        assert_eq!(block.offset("fogEnd"), Some(24));
+        assert_eq!(block.size(), 32);
+        assert_eq!(&block.data()[12..16], &1i32.to_ne_bytes());
+        assert_eq!(f32_at(&block, 24), 4.);
+    }
+
+    #[test]
+    fn material_block_layout() {
+        let block = MaterialBlock::default().std140();
+
+        let offsets = [
+            ("diffuseColor", 0), ("ambientColor", 16), ("shininess", 28), ("specularColor", 32),
+            ("alphaCutoff", 44), ("emissiveColor", 48), ("metallic", 60), ("roughness", 64),
+        ];
+        for (name, offset) in offsets {
+            assert_eq!(block.offset(name), Some(offset), "{}", name);
+        }
+        assert_eq!(block.size(), 80);
+    }
+}